@@ -0,0 +1,332 @@
+use crate::game::{BAN_HERO_TIME, CHOOSE_HERO_TIME, CM_TURN_TIME, READY_TO_START_TIME, TRADE_TIME};
+use failure::Error;
+use rand::rngs::StdRng;
+use rand::{seq::SliceRandom, SeedableRng};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PhaseKind {
+    Loading,
+    Ban,
+    Pick,
+    Trade,
+    ReadyToStart,
+    Gaming,
+    Finished,
+}
+
+impl PhaseKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PhaseKind::Loading => "loading",
+            PhaseKind::Ban => "ban",
+            PhaseKind::Pick => "pick",
+            PhaseKind::Trade => "trade",
+            PhaseKind::ReadyToStart => "readyToStart",
+            PhaseKind::Gaming => "gaming",
+            PhaseKind::Finished => "finished",
+        }
+    }
+}
+
+// who acts in a step, players are counted inside their own team so a format fits any team size
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Seat {
+    All,
+    Player(usize, usize),
+    Captain(usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DraftStep {
+    pub kind: PhaseKind,
+    #[serde(default)]
+    pub seats: Vec<Seat>,
+    #[serde(default)]
+    pub time: i16,
+}
+
+// draft steps by flow
+pub type DraftFormats = BTreeMap<String, Vec<DraftStep>>;
+
+#[derive(Clone, Debug)]
+pub struct DraftPhase {
+    pub kind: PhaseKind,
+    pub positions: Vec<usize>,
+    pub captain: Option<usize>,
+    pub time: i16,
+}
+
+use self::Seat::{All, Captain, Player};
+
+const EVERYONE: &[Seat] = &[All];
+
+// an empty seat list keeps the previous positions
+const DRAFT_FORMATS: &[(&str, &[(PhaseKind, &[Seat], i16)])] = &[
+    (
+        "rk",
+        &[
+            (PhaseKind::Loading, &[], 0),
+            (PhaseKind::Ban, EVERYONE, BAN_HERO_TIME),
+            (PhaseKind::Pick, &[Player(0, 0)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(1, 0), Player(1, 1)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(0, 1), Player(0, 2)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(1, 2), Player(1, 3)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(0, 3), Player(0, 4)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(1, 4)], CHOOSE_HERO_TIME),
+            (PhaseKind::Trade, EVERYONE, TRADE_TIME),
+            (PhaseKind::ReadyToStart, EVERYONE, READY_TO_START_TIME),
+            (PhaseKind::Gaming, &[], 0),
+            (PhaseKind::Finished, &[], 0),
+        ],
+    ),
+    (
+        "at",
+        &[
+            (PhaseKind::Loading, &[], 0),
+            (PhaseKind::Ban, &[Player(0, 0)], BAN_HERO_TIME),
+            (PhaseKind::Ban, &[Player(1, 0)], BAN_HERO_TIME),
+            (PhaseKind::Ban, &[Player(0, 1)], BAN_HERO_TIME),
+            (PhaseKind::Ban, &[Player(1, 1)], BAN_HERO_TIME),
+            (PhaseKind::Ban, &[Player(0, 2)], BAN_HERO_TIME),
+            (PhaseKind::Ban, &[Player(1, 2)], BAN_HERO_TIME),
+            (PhaseKind::Pick, &[Player(0, 0)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(1, 0)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(1, 1)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(0, 1)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(0, 2)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(1, 2)], CHOOSE_HERO_TIME),
+            (PhaseKind::Ban, &[Player(1, 3)], BAN_HERO_TIME),
+            (PhaseKind::Ban, &[Player(0, 3)], BAN_HERO_TIME),
+            (PhaseKind::Ban, &[Player(1, 4)], BAN_HERO_TIME),
+            (PhaseKind::Ban, &[Player(0, 4)], BAN_HERO_TIME),
+            (PhaseKind::Pick, &[Player(1, 3)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(0, 3)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(0, 4)], CHOOSE_HERO_TIME),
+            (PhaseKind::Pick, &[Player(1, 4)], CHOOSE_HERO_TIME),
+            (PhaseKind::Trade, EVERYONE, TRADE_TIME),
+            (PhaseKind::ReadyToStart, EVERYONE, READY_TO_START_TIME),
+            (PhaseKind::Gaming, &[], 0),
+            (PhaseKind::Finished, &[], 0),
+        ],
+    ),
+    // each captain bans five and picks five
+    (
+        "cm",
+        &[
+            (PhaseKind::Loading, &[], 0),
+            (PhaseKind::Ban, &[Captain(0)], CM_TURN_TIME),
+            (PhaseKind::Ban, &[Captain(1)], CM_TURN_TIME),
+            (PhaseKind::Ban, &[Captain(0)], CM_TURN_TIME),
+            (PhaseKind::Ban, &[Captain(1)], CM_TURN_TIME),
+            (PhaseKind::Pick, &[Captain(0)], CM_TURN_TIME),
+            (PhaseKind::Pick, &[Captain(1)], CM_TURN_TIME),
+            (PhaseKind::Pick, &[Captain(1)], CM_TURN_TIME),
+            (PhaseKind::Pick, &[Captain(0)], CM_TURN_TIME),
+            (PhaseKind::Ban, &[Captain(1)], CM_TURN_TIME),
+            (PhaseKind::Ban, &[Captain(0)], CM_TURN_TIME),
+            (PhaseKind::Ban, &[Captain(1)], CM_TURN_TIME),
+            (PhaseKind::Ban, &[Captain(0)], CM_TURN_TIME),
+            (PhaseKind::Pick, &[Captain(1)], CM_TURN_TIME),
+            (PhaseKind::Pick, &[Captain(0)], CM_TURN_TIME),
+            (PhaseKind::Pick, &[Captain(0)], CM_TURN_TIME),
+            (PhaseKind::Pick, &[Captain(1)], CM_TURN_TIME),
+            (PhaseKind::Ban, &[Captain(0)], CM_TURN_TIME),
+            (PhaseKind::Ban, &[Captain(1)], CM_TURN_TIME),
+            (PhaseKind::Pick, &[Captain(1)], CM_TURN_TIME),
+            (PhaseKind::Pick, &[Captain(0)], CM_TURN_TIME),
+            (PhaseKind::Trade, EVERYONE, TRADE_TIME),
+            (PhaseKind::ReadyToStart, EVERYONE, READY_TO_START_TIME),
+            (PhaseKind::Gaming, &[], 0),
+            (PhaseKind::Finished, &[], 0),
+        ],
+    ),
+];
+
+pub fn default_draft_formats() -> DraftFormats {
+    DRAFT_FORMATS
+        .iter()
+        .map(|(flow, steps)| {
+            let steps = steps
+                .iter()
+                .map(|(kind, seats, time)| DraftStep {
+                    kind: *kind,
+                    seats: seats.to_vec(),
+                    time: *time,
+                })
+                .collect();
+            (flow.to_string(), steps)
+        })
+        .collect()
+}
+
+// flows in the json file replace the built in ones with the same name
+pub fn load_draft_formats(path: &str) -> Result<DraftFormats, Error> {
+    let mut formats = default_draft_formats();
+    let file: DraftFormats = serde_json::from_str(&fs::read_to_string(path)?)?;
+    formats.extend(file);
+    Ok(formats)
+}
+
+// the phase list a game walks through, game_status is the index into it
+pub fn draft_phases(
+    formats: &DraftFormats,
+    flow: &str,
+    team_size: usize,
+    team_count: usize,
+) -> Result<Vec<DraftPhase>, &'static str> {
+    let steps = formats.get(flow).ok_or("unknown flow")?;
+    let mut res = vec![];
+    for step in steps {
+        let mut phase = DraftPhase {
+            kind: step.kind,
+            positions: vec![],
+            captain: None,
+            time: step.time,
+        };
+        for seat in &step.seats {
+            match *seat {
+                All => phase.positions.extend(0..team_size * team_count),
+                Player(team, index) => {
+                    if team >= team_count || index >= team_size {
+                        return Err("seat out of range");
+                    }
+                    phase.positions.push(team * team_size + index);
+                }
+                Captain(team) => {
+                    if team >= team_count {
+                        return Err("seat out of range");
+                    }
+                    phase.captain = Some(team);
+                }
+            }
+        }
+        res.push(phase);
+    }
+    Ok(res)
+}
+
+// the Free table holds hero1 to hero20
+pub const FREE_ROTATION_SIZE: usize = 20;
+
+// next week's free heroes, last week's are only reused when there are not enough others
+pub fn free_rotation(
+    heros: &Vec<String>,
+    last: &Vec<String>,
+    size: usize,
+    seed: u64,
+) -> Vec<String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut fresh: Vec<String> = heros
+        .iter()
+        .filter(|h| !last.contains(h))
+        .cloned()
+        .collect();
+    let mut reused: Vec<String> = heros.iter().filter(|h| last.contains(h)).cloned().collect();
+    fresh.shuffle(&mut rng);
+    reused.shuffle(&mut rng);
+    fresh.extend(reused);
+    fresh.truncate(size);
+    fresh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(phases: &Vec<DraftPhase>, kind: PhaseKind, position: usize) -> usize {
+        phases
+            .iter()
+            .filter(|p| p.kind == kind && p.positions.contains(&position))
+            .count()
+    }
+
+    fn phases(flow: &str) -> Vec<DraftPhase> {
+        draft_phases(&default_draft_formats(), flow, 5, 2).unwrap()
+    }
+
+    #[test]
+    fn test_every_position_picks_once() {
+        for flow in &["rk", "at"] {
+            let phases = phases(flow);
+            for position in 0..10 {
+                assert_eq!(count(&phases, PhaseKind::Pick, position), 1, "{} {}", flow, position);
+                assert_eq!(count(&phases, PhaseKind::Ban, position), 1, "{} {}", flow, position);
+            }
+            assert_eq!(phases[0].kind, PhaseKind::Loading);
+            assert_eq!(phases[phases.len() - 1].kind, PhaseKind::Finished);
+        }
+    }
+
+    #[test]
+    fn test_status_numbers() {
+        assert_eq!(phases("rk").len(), 12);
+        assert_eq!(phases("at").len(), 25);
+        assert_eq!(phases("rk")[8].kind, PhaseKind::Trade);
+        assert_eq!(phases("rk")[9].kind, PhaseKind::ReadyToStart);
+        assert_eq!(phases("at")[21].kind, PhaseKind::Trade);
+        assert_eq!(phases("at")[22].kind, PhaseKind::ReadyToStart);
+        assert_eq!(phases("cm")[21].kind, PhaseKind::Trade);
+    }
+
+    #[test]
+    fn test_captains_turns() {
+        let cm = phases("cm");
+        for team in 0..2 {
+            let turns = |kind| cm.iter().filter(|p| p.kind == kind && p.captain == Some(team)).count();
+            assert_eq!(turns(PhaseKind::Ban), 5);
+            assert_eq!(turns(PhaseKind::Pick), 5);
+        }
+        assert_eq!(cm[1].captain, Some(0));
+        assert!(cm[1].positions.is_empty());
+        assert_eq!(cm[21].captain, None);
+    }
+
+    #[test]
+    fn test_seats_follow_team_size() {
+        let formats = default_draft_formats();
+        let rk = draft_phases(&formats, "rk", 5, 2).unwrap();
+        assert_eq!(rk[3].positions, vec![5, 6]);
+        let at = draft_phases(&formats, "at", 6, 2).unwrap();
+        assert_eq!(at[2].positions, vec![6]);
+        assert_eq!(at[21].positions, (0..12).collect::<Vec<usize>>());
+        assert_eq!(draft_phases(&formats, "rk", 3, 2).err(), Some("seat out of range"));
+        assert_eq!(draft_phases(&formats, "ng", 5, 2).err(), Some("unknown flow"));
+    }
+
+    #[test]
+    fn test_formats_from_json() {
+        let file: DraftFormats = serde_json::from_str(
+            r#"{"duel": [{"kind": "loading"}, {"kind": "pick", "seats": [{"player": [0, 0]}, {"player": [1, 0]}], "time": 20}, {"kind": "trade", "seats": ["all"], "time": 5}]}"#,
+        )
+        .unwrap();
+        let mut formats = default_draft_formats();
+        formats.extend(file);
+        let duel = draft_phases(&formats, "duel", 1, 2).unwrap();
+        assert_eq!(duel[1].positions, vec![0, 1]);
+        assert_eq!(duel[1].time, 20);
+        assert_eq!(duel[2].kind, PhaseKind::Trade);
+        assert!(formats.contains_key("rk"));
+    }
+
+    #[test]
+    fn test_free_rotation() {
+        let heros: Vec<String> = (0..30).map(|i| format!("hero{}", i)).collect();
+        let last: Vec<String> = heros[..20].to_vec();
+        let next = free_rotation(&heros, &last, FREE_ROTATION_SIZE, 7);
+        assert_eq!(next.len(), 20);
+        for hero in &heros[20..] {
+            assert!(next.contains(hero));
+        }
+        let mut unique = next.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), 20);
+        assert_eq!(next, free_rotation(&heros, &last, FREE_ROTATION_SIZE, 7));
+        assert_eq!(free_rotation(&heros[..5].to_vec(), &vec![], FREE_ROTATION_SIZE, 7).len(), 5);
+    }
+}
@@ -109,6 +109,8 @@ struct ScoreData {
 struct LeaderboardData {
     rkLeaderboard: Vec<ScoreData>,
     atLeaderboard: Vec<ScoreData>,
+    rkSoloLeaderboard: Vec<ScoreData>,
+    atSoloLeaderboard: Vec<ScoreData>,
 }
 
 pub fn login(
@@ -120,14 +122,16 @@ pub fn login(
 ) -> std::result::Result<(), Error> {
     let data: LoginData = serde_json::from_value(v)?;
     let mut conn = pool.get_conn()?;
-    let sql = format!(r#"select ng, rk, at, name from user where id='{}';"#, id);
+    let sql = format!(r#"select ng, rk, at, rk_solo, at_solo, name from user where id='{}';"#, id);
     let qres2: mysql::QueryResult = conn.query(sql.clone())?;
     let mut ng: i16 = 0;
     let mut rk: i16 = 0;
     let mut at: i16 = 0;
+    let mut rk_solo: i16 = 0;
+    let mut at_solo: i16 = 0;
     let mut name: String = "".to_owned();
     let mut count = 0;
-    for row in qres2 {
+    for row in qres2.take(1) {
         count += 1;
         let a = row?.clone();
         if let Some(n) = a.get("ng"){
@@ -145,12 +149,21 @@ pub fn login(
         } else {
             at = 1200;
         }
+        if let Some(n) = a.get("rk_solo"){
+            rk_solo = mysql::from_value(n);
+        } else {
+            rk_solo = 1200;
+        }
+        if let Some(n) = a.get("at_solo"){
+            at_solo = mysql::from_value(n);
+        } else {
+            at_solo = 1200;
+        }
         if let Some(n) = a.get("name"){
             name = mysql::from_value(n);
         } else {
             name = "".to_string();
         }
-        break;
     }
     //查無此人 建立表
     if count == 0 {
@@ -166,6 +179,8 @@ pub fn login(
         ng = 1200;
         rk = 1200;
         at = 1200;
+        rk_solo = 1200;
+        at_solo = 1200;
         //sender.send(RoomEventData::Login(UserLoginData {u: User { id: id.clone(), hero: name.clone(), online: true, ng: 1000, rk: 1000, ..Default::default()}}));
     }
 
@@ -190,6 +205,8 @@ pub fn login(
             ng: ng,
             rk: rk,
             at: at,
+            rk_solo: rk_solo,
+            at_solo: at_solo,
             ..Default::default()
         },
         dataid: id,
@@ -446,44 +463,38 @@ pub fn CheckBinding(
     Ok(())
 }
 
-pub fn GetLeaderboard(
-    id: String,
-    v: Value,
-    pool: mysql::Pool,
-    msgtx: Sender<MqttMsg>,
-) -> std::result::Result<(), Error> {
-    let mut conn = pool.get_conn()?;
-    let mut rkScores: Vec<ScoreData> = vec![];
-    let mut atScores: Vec<ScoreData> = vec![];
-    let mut rkSql = format!(
-        r#"select id, rk, count(res) as win from user, Finished_detail where id = steam_id and mode = 'rk' and res = 'W' group by steam_id order by rk desc limit 30;"#
+// the rating column and the Finished_detail mode share the same name
+fn get_scores(conn: &mut mysql::PooledConn, rating: &str) -> std::result::Result<Vec<ScoreData>, Error> {
+    let mut scores: Vec<ScoreData> = vec![];
+    let sql = format!(
+        r#"select id, {0}, count(res) as win from user, Finished_detail where id = steam_id and mode = '{0}' and res = 'W' group by steam_id order by {0} desc limit 30;"#,
+        rating
     );
-    let qres: mysql::QueryResult = conn.query(rkSql.clone())?;
-    for row in qres {
-        let a = row?.clone();
-        let data = ScoreData {
-            steamID: mysql::from_value_opt(a.get("id").ok_or(Error::from(core::fmt::Error))?)?,
-            score: mysql::from_value_opt(a.get("rk").ok_or(Error::from(core::fmt::Error))?)?,
-            win: mysql::from_value_opt(a.get("win").ok_or(Error::from(core::fmt::Error))?)?,
-        };
-        rkScores.push(data);
-    }
-    let mut atSql = format!(
-        r#"select id, at, count(res) as win from user, Finished_detail where id = steam_id and mode = 'at' and res = 'W' group by steam_id order by at desc limit 30;"#
-    );
-    let qres: mysql::QueryResult = conn.query(atSql.clone())?;
+    let qres: mysql::QueryResult = conn.query(sql.clone())?;
     for row in qres {
         let a = row?.clone();
         let data = ScoreData {
             steamID: mysql::from_value_opt(a.get("id").ok_or(Error::from(core::fmt::Error))?)?,
-            score: mysql::from_value_opt(a.get("at").ok_or(Error::from(core::fmt::Error))?)?,
+            score: mysql::from_value_opt(a.get(rating).ok_or(Error::from(core::fmt::Error))?)?,
             win: mysql::from_value_opt(a.get("win").ok_or(Error::from(core::fmt::Error))?)?,
         };
-        atScores.push(data);
+        scores.push(data);
     }
+    Ok(scores)
+}
+
+pub fn GetLeaderboard(
+    id: String,
+    v: Value,
+    pool: mysql::Pool,
+    msgtx: Sender<MqttMsg>,
+) -> std::result::Result<(), Error> {
+    let mut conn = pool.get_conn()?;
     let mut leaderBoard = LeaderboardData {
-        rkLeaderboard: rkScores,
-        atLeaderboard: atScores,
+        rkLeaderboard: get_scores(&mut conn, "rk")?,
+        atLeaderboard: get_scores(&mut conn, "at")?,
+        rkSoloLeaderboard: get_scores(&mut conn, "rk_solo")?,
+        atSoloLeaderboard: get_scores(&mut conn, "at_solo")?,
     };
     msgtx.try_send(MqttMsg {
         topic: format!("member/{}/res/get_leaderboard", id),
@@ -32,6 +32,11 @@ use crate::game::*;
 use crate::game_flow::*;
 use crate::msg::*;
 use crate::room::*;
+use crate::tournament::*;
+use crate::queue::*;
+use crate::draft::*;
+use crate::spectate::*;
+use crate::server::*;
 use std::process::Command;
 
 pub const TEAM_SIZE: i16 = 5;
@@ -42,6 +47,76 @@ pub const RANK_RANGE: i64 = 50;
 pub const NG_RANGE: i64 = 50;
 pub const ARAM_RANGE: i64 = 50;
 pub const SWAP_TIME: i32 = 15;
+pub const LOW_PRIORITY_JUMP_CNT: i32 = 3;
+pub const LOW_PRIORITY_GAMES: i16 = 3;
+pub const LOW_PRIORITY_WAIT: i64 = 300;
+pub const LOBBY_PLAYER_SIZE: usize = 10;
+pub const LOBBY_SPECTATOR_SIZE: usize = 4;
+pub const BACKFILL_INTERVAL: i64 = 10;
+pub const RANKED_PARTY_SPREAD: i16 = 500;
+pub const DUO_ONLY_RATING: i16 = 1800;
+pub const DUO_ONLY_SIZE: usize = 2;
+pub const SOLO_QUEUE_SIZE: usize = 2;
+pub const MAX_REROLL_TOKENS: i16 = 2;
+// seconds ranked drafts are held back from spectators
+pub const RANKED_SPECTATE_DELAY: u64 = 120;
+// every queue the matchmaker groups, sng is the solo only ng queue
+pub const QUEUE_MODES: [&str; 10] = ["sng", "ng", "aram", "rk", "at", "duel", "3v3", "rk_solo", "at_solo", "cm"];
+
+// ranked party limits, the consts are the defaults when the command line does not set them
+#[derive(Clone, Debug)]
+pub struct PartyRules {
+    pub spread: i16,
+    pub duo_only_rating: i16,
+    pub duo_only_size: usize,
+}
+
+impl Default for PartyRules {
+    fn default() -> PartyRules {
+        PartyRules {
+            spread: RANKED_PARTY_SPREAD,
+            duo_only_rating: DUO_ONLY_RATING,
+            duo_only_size: DUO_ONLY_SIZE,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ModeConfig {
+    pub flow: &'static str,
+    pub rating: &'static str,
+    pub team_size: i16,
+    pub team_count: usize,
+    pub range: i64,
+}
+
+// rk and at drafts run fixed pick orders, so they stay 5v5
+pub fn mode_config(mode: &str) -> ModeConfig {
+    match mode {
+        "rk" => ModeConfig { flow: "rk", rating: "rk", team_size: TEAM_SIZE, team_count: MATCH_SIZE, range: RANK_RANGE },
+        "at" => ModeConfig { flow: "at", rating: "at", team_size: TEAM_SIZE, team_count: MATCH_SIZE, range: RANK_RANGE },
+        "rk_solo" => ModeConfig { flow: "rk", rating: "rk_solo", team_size: TEAM_SIZE, team_count: MATCH_SIZE, range: RANK_RANGE },
+        "at_solo" => ModeConfig { flow: "at", rating: "at_solo", team_size: TEAM_SIZE, team_count: MATCH_SIZE, range: RANK_RANGE },
+        "cm" => ModeConfig { flow: "cm", rating: "rk", team_size: TEAM_SIZE, team_count: MATCH_SIZE, range: RANK_RANGE },
+        "aram" => ModeConfig { flow: "aram", rating: "aram", team_size: TEAM_SIZE, team_count: MATCH_SIZE, range: ARAM_RANGE },
+        "duel" => ModeConfig { flow: "ng", rating: "ng", team_size: 1, team_count: 2, range: NG_RANGE },
+        "3v3" => ModeConfig { flow: "ng", rating: "ng", team_size: 3, team_count: 2, range: NG_RANGE },
+        _ => ModeConfig { flow: "ng", rating: "ng", team_size: TEAM_SIZE, team_count: MATCH_SIZE, range: NG_RANGE },
+    }
+}
+
+pub fn is_valid_mode(mode: &str) -> bool {
+    mode == "ng" || mode == "rk" || mode == "at" || mode == "aram" || mode == "duel" || mode == "3v3" || mode == "cm"
+}
+
+// ranked modes have a solo/duo queue and a flex queue, each with its own rating
+pub fn queue_mode(mode: &str, queue: &str) -> Option<String> {
+    match queue {
+        "" | "flex" => Some(mode.to_string()),
+        "solo" if mode == "rk" || mode == "at" => Some(format!("{}_solo", mode)),
+        _ => None,
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct HeroData {
@@ -78,6 +153,18 @@ pub struct UpdateHerosData {
     pub enable: bool,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UnlockHeroData {
+    pub password: String,
+    pub id: String,
+    pub hero: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FreeRotationData {
+    pub password: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CreateRoomData {
     pub id: String,
@@ -175,6 +262,56 @@ pub struct JumpData {
     pub id: String,
     pub msg: String,
     pub game: u64,
+    // why the server made the player jump, clients leave it empty
+    #[serde(default)]
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AutoPickData {
+    pub id: String,
+    pub game: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BackfillData {
+    pub game: u64,
+    pub mode: String,
+    pub ng: i16,
+    pub wait: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CancelBackfillData {
+    pub game: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BackfillFoundData {
+    pub game: u64,
+    pub rid: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PlayerQualityData {
+    pub id: String,
+    pub rating: i16,
+    pub wait: i64,
+    pub party: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MatchQualityData {
+    pub game: u64,
+    pub mode: String,
+    pub players: Vec<Vec<PlayerQualityData>>,
+    pub team_avg: Vec<i16>,
+    pub spread: i16,
+    pub team_gap: i16,
+    pub parties: Vec<Vec<usize>>,
+    pub range: i64,
+    pub max_wait: i64,
+    pub avg_wait: i64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -211,6 +348,50 @@ pub struct UserNGHeroData {
     pub hero: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RerollData {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BenchSwapData {
+    pub id: String,
+    pub hero: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SpectateData {
+    pub id: String,
+    #[serde(default)]
+    pub game: u64,
+    #[serde(default)]
+    pub friend: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StopSpectateData {
+    pub id: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SpectatePlayerData {
+    pub id: String,
+    pub team: usize,
+    pub hero: String,
+    pub ban_hero: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SpectateSnapshotData {
+    pub game: u64,
+    pub mode: String,
+    pub phase: String,
+    pub paused: bool,
+    pub picker: Vec<String>,
+    pub bans: Vec<String>,
+    pub players: Vec<SpectatePlayerData>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct UserLogoutData {
     pub id: String,
@@ -222,6 +403,10 @@ pub struct StartQueueData {
     pub action: String,
     pub mode: String,
     pub id: String,
+    #[serde(default)]
+    pub queue: String,
+    #[serde(default)]
+    pub modes: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -317,6 +502,25 @@ pub struct ReconnectData {
     pub id: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RegisterServerData {
+    #[serde(default)]
+    pub id: String,
+    pub address: String,
+    pub capacity: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ServerHeartbeatData {
+    #[serde(default)]
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AllocateServerData {
+    pub game: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct DeadData {
     pub ServerDead: String,
@@ -364,6 +568,13 @@ pub struct ControlData {
     pub password: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DraftControlData {
+    pub password: String,
+    pub game: u64,
+    pub action: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct ContinueData {
     pub id: String,
@@ -419,6 +630,66 @@ pub struct UpdateRoomQueueCntData {
     pub rid: u64,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CreateLobbyData {
+    pub id: String,
+    pub mode: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JoinLobbyData {
+    pub id: String,
+    pub lobby: u64,
+    pub password: String,
+    pub spectator: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LeaveLobbyData {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AssignTeamData {
+    pub id: String,
+    pub target: String,
+    pub team: i16,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StartLobbyData {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CreateTournamentData {
+    pub password: String,
+    pub name: String,
+    pub format: String,
+    pub mode: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RegisterTeamData {
+    pub id: String,
+    pub tournament: u64,
+    pub name: String,
+    pub roster: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StartTournamentData {
+    pub password: String,
+    pub tournament: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GetStandingsData {
+    pub id: String,
+    pub tournament: u64,
+}
+
 #[derive(Debug)]
 pub enum RoomEventData {
     Reset(),
@@ -431,6 +702,10 @@ pub enum RoomEventData {
     BanHero(UserNGHeroData),
     GetHeros(GetHerosData),
     SwapHero(SwapHeroData),
+    Reroll(RerollData),
+    BenchSwap(BenchSwapData),
+    Spectate(SpectateData),
+    StopSpectate(StopSpectateData),
     LockedHero(UserNGHeroData),
     NGGameChooseHero(BTreeMap<u64, Vec<u64>>),
     Join(JoinRoomData),
@@ -439,6 +714,8 @@ pub enum RoomEventData {
     GetGood(GetGoodData),
     Reject(RejectRoomData),
     Jump(JumpData),
+    AutoPick(AutoPickData),
+    GetDraftLog(GetDraftLogData),
     CheckRestriction(CheckRestrctionData),
     CheckInGame(CheckInGameData),
     SetPassword(SetPasswordData),
@@ -458,8 +735,12 @@ pub enum RoomEventData {
     GameClose(GameCloseData),
     Status(StatusData),
     Reconnect(ReconnectData),
+    RegisterServer(RegisterServerData),
+    ServerHeartbeat(ServerHeartbeatData),
+    AllocateServer(AllocateServerData),
     MainServerDead(DeadData),
     Control(ControlData),
+    DraftControl(DraftControlData),
     CheckState(CheckStateData),
     BanUser(BanUserData),
     Loading(LoadingData),
@@ -467,6 +748,18 @@ pub enum RoomEventData {
     Free(),
     SystemBan(SystemBanData),
     UpdateHeros(UpdateHerosData),
+    UnlockHero(UnlockHeroData),
+    FreeRotation(FreeRotationData),
+    CreateLobby(CreateLobbyData),
+    JoinLobby(JoinLobbyData),
+    LeaveLobby(LeaveLobbyData),
+    AssignTeam(AssignTeamData),
+    StartLobby(StartLobbyData),
+    CreateTournament(CreateTournamentData),
+    RegisterTeam(RegisterTeamData),
+    StartTournament(StartTournamentData),
+    GetStandings(GetStandingsData),
+    BackfillFound(BackfillFoundData),
 }
 
 #[derive(Clone, Debug)]
@@ -491,17 +784,36 @@ pub struct UserChooseData {
     pub ban_hero: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GetDraftLogData {
+    pub id: String,
+    pub game: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct SqlGameInfoData {
     pub game: u64,
     pub mode: String,
     pub chooseData: Vec<UserChooseData>,
+    pub seed: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DraftLogData {
+    pub game: u64,
+    pub id: String,
+    pub action: String,
+    pub hero: String,
+    pub phase: String,
+    pub time: i64,
 }
 
 pub enum SqlData {
     Login(SqlLoginData),
     UpdateScore(SqlScoreData),
     UpdateGameInfo(SqlGameInfoData),
+    MatchQuality(MatchQualityData),
+    DraftLog(DraftLogData),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
@@ -518,6 +830,9 @@ pub struct QueueRoomData {
     pub notify: bool,
     pub queue_cnt: i64,
     pub mode: String,
+    pub modes: Vec<String>,
+    pub priority: bool,
+    pub low_priority: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
@@ -532,6 +847,9 @@ pub struct ReadyGroupData {
     pub avg_aram: i16,
     pub game_status: u16,
     pub queue_cnt: i64,
+    pub priority: bool,
+    pub low_priority: bool,
+    pub mode: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
@@ -561,26 +879,8 @@ pub struct PreReadyData {
 pub struct GamingData {
     pub game: u64,
     pub mode: String,
-    pub steam_id1: String,
-    pub steam_id2: String,
-    pub steam_id3: String,
-    pub steam_id4: String,
-    pub steam_id5: String,
-    pub steam_id6: String,
-    pub steam_id7: String,
-    pub steam_id8: String,
-    pub steam_id9: String,
-    pub steam_id10: String,
-    pub hero1: String,
-    pub hero2: String,
-    pub hero3: String,
-    pub hero4: String,
-    pub hero5: String,
-    pub hero6: String,
-    pub hero7: String,
-    pub hero8: String,
-    pub hero9: String,
-    pub hero10: String,
+    pub steam_ids: Vec<String>,
+    pub heros: Vec<String>,
     pub status: String,
     pub win_team: i16,
 }
@@ -590,6 +890,8 @@ pub enum QueueData {
     RemoveRoom(RemoveRoomData),
     Control(ControlData),
     Continue(ContinueData),
+    Backfill(BackfillData),
+    CancelBackfill(CancelBackfillData),
 }
 
 // Prints the elapsed time.
@@ -629,6 +931,71 @@ fn SendGameList(
     Ok(())
 }
 
+// the game server learns who to expect, the players learn where to connect
+fn send_allocation(
+    msgtx: &Sender<MqttMsg>,
+    game_id: u64,
+    server: &GameServer,
+    ActiveGames: &BTreeMap<u64, ActiveGame>,
+    redis_conn: &mut redis::Connection,
+) -> Result<(), Error> {
+    if let Some(game) = ActiveGames.get(&game_id) {
+        let fg = game.state();
+        info!("allocate game: {}, server: {}, address: {}, line: {}", game_id, server.id, server.address, line!());
+        // kept in redis so reconnecting players still find the server when the registry lost it
+        let _: () = redis_conn.set(format!("server{}", game_id), server.address.clone())?;
+        msgtx.try_send(MqttMsg {
+            topic: format!("gameserver/{}/res/allocate", server.id),
+            msg: json!({"game": game_id, "mode": fg.borrow().mode(), "player": fg.borrow().user_names()}).to_string(),
+        })?;
+        msgtx.try_send(MqttMsg {
+            topic: format!("game/{}/res/start_game", game_id),
+            msg: json!({"game": game_id, "server": server.address, "player": fg.borrow().user_names()}).to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+// a game lost with its server is closed like any other, unless it already reported a result to settle
+fn close_lost_game(
+    game_id: u64,
+    ActiveGames: &BTreeMap<u64, ActiveGame>,
+    InGameUsers: &mut BTreeMap<String, Rc<RefCell<User>>>,
+    GameingRoom: &mut BTreeMap<u64, Rc<RefCell<GameRoomData>>>,
+    conn: &mut mysql::PooledConn,
+    redis_conn: &mut redis::Connection,
+    tx2: &Sender<RoomEventData>,
+) -> Result<(), Error> {
+    let sql = format!("select status from Gaming where game={};", game_id);
+    let mut qres = conn.query(sql.clone())?;
+    let mut finished = false;
+    if let Some(row) = qres.next() {
+        let status: String = mysql::from_row_opt(row?).unwrap_or_default();
+        finished = status == "finished";
+    }
+    drop(qres);
+    let users = match ActiveGames.get(&game_id) {
+        Some(g) => g.state().borrow().user_names().clone(),
+        None => vec![],
+    };
+    for uid in &users {
+        InGameUsers.remove(uid);
+        let _: () = redis_conn.del(format!("g{}", uid))?;
+    }
+    let _: () = redis_conn.del(format!("gid{}", game_id))?;
+    let _: () = redis_conn.del(format!("server{}", game_id))?;
+    GameingRoom.remove(&game_id);
+    // finished games are settled and cleared by the next pass over the Gaming table
+    if finished {
+        return Ok(());
+    }
+    let sql = format!("DELETE FROM Gaming where game={};", game_id);
+    conn.query(sql.clone())?;
+    // the GameClose handler drops the game and frees its players
+    tx2.try_send(RoomEventData::GameClose(GameCloseData { game: game_id }))?;
+    Ok(())
+}
+
 fn get_rid_by_id(id: &String, users: &BTreeMap<String, Rc<RefCell<User>>>) -> u64 {
     let u = users.get(id);
     if let Some(u) = u {
@@ -645,33 +1012,17 @@ fn get_gid_by_id(id: &String, users: &BTreeMap<String, Rc<RefCell<User>>>) -> u6
     return 0;
 }
 
-fn get_ng_game_id_by_id(
-    id: &String,
-    games: &BTreeMap<u64, Rc<RefCell<NGGame>>>,
-    users: &BTreeMap<String, Rc<RefCell<User>>>,
-) -> u64 {
-    for (game_id, fg) in games {
-        for user_id in &fg.borrow().user_names {
-            if let Some(u) = users.get(user_id) {
-                if u.borrow().id == *id {
-                    return fg.borrow().game_id;
-                }
-            }
-        }
-    }
-    return 0;
-}
-
-fn get_aram_game_id_by_id(
+fn get_game_id_by_id(
     id: &String,
-    games: &BTreeMap<u64, Rc<RefCell<ARAMGame>>>,
+    games: &BTreeMap<u64, ActiveGame>,
     users: &BTreeMap<String, Rc<RefCell<User>>>,
 ) -> u64 {
-    for (game_id, fg) in games {
-        for user_id in &fg.borrow().user_names {
+    for (game_id, game) in games {
+        let fg = game.state();
+        for user_id in fg.borrow().user_names() {
             if let Some(u) = users.get(user_id) {
                 if u.borrow().id == *id {
-                    return fg.borrow().game_id;
+                    return fg.borrow().game_id();
                 }
             }
         }
@@ -679,38 +1030,22 @@ fn get_aram_game_id_by_id(
     return 0;
 }
 
-fn get_rk_game_id_by_id(
+// the game a reconnecting player belongs to, a game only known to redis must still be running
+fn reconnect_game(
     id: &String,
-    games: &BTreeMap<u64, Rc<RefCell<RKGame>>>,
-    users: &BTreeMap<String, Rc<RefCell<User>>>,
+    ActiveGames: &BTreeMap<u64, ActiveGame>,
+    TotalUsers: &BTreeMap<String, Rc<RefCell<User>>>,
+    GameingRoom: &BTreeMap<u64, Rc<RefCell<GameRoomData>>>,
+    redis_game: u64,
 ) -> u64 {
-    for (game_id, fg) in games {
-        for user_id in &fg.borrow().user_names {
-            if let Some(u) = users.get(user_id) {
-                if u.borrow().id == *id {
-                    return fg.borrow().game_id;
-                }
-            }
-        }
+    let gid = get_game_id_by_id(id, ActiveGames, TotalUsers);
+    if gid > 0 {
+        return gid;
     }
-    return 0;
-}
-
-fn get_at_game_id_by_id(
-    id: &String,
-    games: &BTreeMap<u64, Rc<RefCell<ATGame>>>,
-    users: &BTreeMap<String, Rc<RefCell<User>>>,
-) -> u64 {
-    for (game_id, fg) in games {
-        for user_id in &fg.borrow().user_names {
-            if let Some(u) = users.get(user_id) {
-                if u.borrow().id == *id {
-                    return fg.borrow().game_id;
-                }
-            }
-        }
+    if GameingRoom.contains_key(&redis_game) {
+        return redis_game;
     }
-    return 0;
+    0
 }
 
 fn get_user(id: &String, users: &BTreeMap<String, Rc<RefCell<User>>>) -> Option<Rc<RefCell<User>>> {
@@ -739,83 +1074,532 @@ fn get_users(
     }
 }
 
-fn user_score(
+fn leave_party(
     u: &Rc<RefCell<User>>,
-    value: i16,
+    TotalRoom: &mut BTreeMap<u64, Rc<RefCell<RoomData>>>,
     msgtx: &Sender<MqttMsg>,
-    sender: &Sender<SqlData>,
-    conn: &mut mysql::PooledConn,
-    mode: String,
-    isWin: bool,
-    raindrop: i64,
+    QueueSender: &Sender<QueueData>,
 ) -> Result<(), Error> {
-    if mode == "ng" {
-        info!("user: {}, ng: {} + {}, raindrop: {} + {}, line: {}",u.borrow().id, u.borrow().ng, value, u.borrow().raindrop, raindrop, line!());
-        u.borrow_mut().ng += value;
-    } else if mode == "rk" {
-        info!("user: {}, rk: {} + {}, raindrop: {} + {}, line: {}",u.borrow().id, u.borrow().rk, value, u.borrow().raindrop, raindrop, line!());
-        u.borrow_mut().rk += value;
-    } else if mode == "at" {
-        info!("user: {}, at: {} + {}, raindrop: {} + {}, line: {}",u.borrow().id, u.borrow().at, value, u.borrow().raindrop, raindrop, line!());
-        u.borrow_mut().at += value;
+    let rid = u.borrow().rid;
+    if rid == 0 {
+        return Ok(());
     }
-    u.borrow_mut().raindrop += raindrop;
-    msgtx.try_send(MqttMsg {
-        topic: format!("member/{}/res/login", u.borrow().id),
-        msg: format!(
-            r#"{{"msg":"ok", "ng":{}, "rk":{}, "at":{}, "aram":{}, "raindrop":{} ,"hero":"{}", "phone":"{}", "email":"{}"}}"#,
-            u.borrow().ng,
-            u.borrow().rk,
-            u.borrow().at,
-            u.borrow().aram,
-            u.borrow().raindrop,
-            u.borrow().hero,
-            u.borrow().phone,
-            u.borrow().email,
-        ),
-    })?;
-    let mut sql = format!(
-        "UPDATE user SET ng={}, rk={}, at={}, raindrop=raindrop+{} WHERE id='{}';",
-        u.borrow().ng.clone(),
-        u.borrow().rk.clone(),
-        u.borrow().at.clone(),
-        raindrop,
-        u.borrow().id.clone()
-    );
-    if isWin {
-        sql = format!(
-            "UPDATE user SET ng={}, rk={}, at={}, raindrop=raindrop+{}, first_win=true WHERE id='{}';",
-            u.borrow().ng.clone(),
-            u.borrow().rk.clone(),
-            u.borrow().at.clone(),
-            raindrop,
-            u.borrow().id.clone()
-        );
+    let id = u.borrow().id.clone();
+    let mut is_null = false;
+    if let Some(r) = TotalRoom.get(&rid) {
+        let m = r.borrow().master.clone();
+        r.borrow_mut().rm_user(&id);
+        if r.borrow().users.len() > 0 {
+            r.borrow().publish_update(msgtx, m)?;
+        } else {
+            is_null = true;
+        }
     }
-    let qres = conn.query(sql.clone())?;
+    if is_null {
+        TotalRoom.remove(&rid);
+        QueueSender.send(QueueData::RemoveRoom(RemoveRoomData{rid: rid}));
+    }
+    u.borrow_mut().rid = 0;
     Ok(())
 }
 
-fn get_ng(team: &Vec<Rc<RefCell<User>>>) -> Vec<i32> {
-    let mut res: Vec<i32> = vec![];
-    for u in team {
-        res.push(u.borrow().ng.into());
-    }
-    res
+// every ban, pick, lock, swap and timeout is kept so the draft can be replayed after the game
+pub fn log_draft(sender: &Sender<SqlData>, game: u64, phase: &str, id: &str, action: &str, hero: &str) {
+    sender.try_send(SqlData::DraftLog(DraftLogData {
+        game: game,
+        id: id.to_string(),
+        action: action.to_string(),
+        hero: hero.to_string(),
+        phase: phase.to_string(),
+        time: Utc::now().timestamp_millis(),
+    }));
 }
 
-fn get_rk(team: &Vec<Rc<RefCell<User>>>) -> Vec<i32> {
-    let mut res: Vec<i32> = vec![];
-    for u in team {
-        res.push(u.borrow().rk.into());
+fn draft_phase(ActiveGames: &BTreeMap<u64, ActiveGame>, game_id: u64) -> &'static str {
+    match ActiveGames.get(&game_id) {
+        Some(game) => game.state().borrow().status_name(),
+        None => "",
     }
-    res
+}
+
+// in captain's mode only the captain on turn may ban or pick
+fn captain_choose_msg(cm: &Rc<RefCell<CMGame>>, x: &UserNGHeroData, kind: PhaseKind, action: &str, sender: &Sender<SqlData>) -> MqttMsg {
+    let game_id = cm.borrow().game_id;
+    let res = cm.borrow_mut().captain_choose(&x.id, &x.hero, kind);
+    match res {
+        Ok(team) => {
+            log_draft(sender, game_id, kind.name(), &x.id, kind.name(), &x.hero);
+            MqttMsg {
+            topic: format!("game/{}/res/{}", game_id, action),
+            msg: json!({"id": x.id, "hero": x.hero, "team": team}).to_string(),
+            }
+        }
+        Err(reason) => reject_choice_msg(x, action, reason),
+    }
+}
+
+// every pick and ban from a client is checked here, the game decides what its phase, turn and bans allow
+fn validate_turn(u: &User, kind: PhaseKind, ActiveGames: &BTreeMap<u64, ActiveGame>) -> Result<(), &'static str> {
+    let game = ActiveGames.get(&u.game_id).ok_or("not in game")?.state();
+    let res = game.borrow().validate_turn(&u.id, kind);
+    res
+}
+
+fn validate_choice(
+    u: &User,
+    hero: &String,
+    kind: PhaseKind,
+    ActiveGames: &BTreeMap<u64, ActiveGame>,
+    TotalHeros: &BTreeMap<String, Rc<RefCell<HeroData>>>,
+    FreeHeros: &Vec<String>,
+) -> Result<(), &'static str> {
+    let game = ActiveGames.get(&u.game_id).ok_or("not in game")?.state();
+    game.borrow().validate_choice(&u.id, hero, kind, TotalHeros)?;
+    if kind == PhaseKind::Pick && !can_play(u, hero, FreeHeros) {
+        return Err("not owned");
+    }
+    Ok(())
+}
+
+// the latest week in the Free table and its heroes
+fn load_free_heros(conn: &mut mysql::PooledConn) -> Result<(i64, Vec<String>), Error> {
+    let sql = format!("select * from Free order by week DESC limit 1;");
+    let mut qres = conn.query(sql.clone())?;
+    let mut week: i64 = 0;
+    let mut heros: Vec<String> = vec![];
+    if let Some(row) = qres.next() {
+        let a = row?.clone();
+        week = mysql::from_value_opt(a.get("week").ok_or(Error::from(core::fmt::Error))?)?;
+        for i in 1..FREE_ROTATION_SIZE + 1 {
+            let hero: String = mysql::from_value_opt(a.get(format!("hero{}", i).as_str()).ok_or(Error::from(core::fmt::Error))?)?;
+            if hero != "" {
+                heros.push(hero);
+            }
+        }
+    }
+    Ok((week, heros))
+}
+
+// writes next week's rotation into the Free table, avoiding this week's heroes where possible
+fn generate_free_rotation(
+    conn: &mut mysql::PooledConn,
+    TotalHeros: &BTreeMap<String, Rc<RefCell<HeroData>>>,
+) -> Result<Vec<String>, Error> {
+    let (week, last) = load_free_heros(conn)?;
+    let heros: Vec<String> = TotalHeros.values().filter(|h| h.borrow().enable).map(|h| h.borrow().name.clone()).collect();
+    let rotation = free_rotation(&heros, &last, FREE_ROTATION_SIZE, (week + 1) as u64);
+    let mut columns = "week".to_string();
+    let mut values = format!("{}", week + 1);
+    for i in 0..FREE_ROTATION_SIZE {
+        columns = format!("{}, hero{}", columns, i + 1);
+        values = format!("{}, '{}'", values, rotation.get(i).cloned().unwrap_or_default());
+    }
+    let sql = format!("insert into Free ({}) values ({});", columns, values);
+    info!("sql : {}, line: {}", sql, line!());
+    conn.query(sql.clone())?;
+    Ok(rotation)
+}
+
+fn reject_choice_msg(x: &UserNGHeroData, action: &str, reason: &str) -> MqttMsg {
+    MqttMsg {
+        topic: format!("member/{}/res/{}", x.id, action),
+        msg: format!(r#"{{"id":"{}", "hero":"{}", "msg":"rejected", "reason":"{}"}}"#, x.id, x.hero, reason),
+    }
+}
+
+// the draft as it stands, sent to spectators before the live events and to reconnecting players
+fn draft_snapshot(game: &dyn GameState, TotalUsers: &BTreeMap<String, Rc<RefCell<User>>>) -> SpectateSnapshotData {
+    let mut players: Vec<SpectatePlayerData> = vec![];
+    for (team, t) in game.teams().iter().enumerate() {
+        for r in &t.borrow().rooms {
+            for u in &r.borrow().users {
+                let u = TotalUsers.get(&u.borrow().id).unwrap_or(u);
+                players.push(SpectatePlayerData {
+                    id: u.borrow().id.clone(),
+                    team: team,
+                    hero: u.borrow().hero.clone(),
+                    ban_hero: u.borrow().ban_hero.clone(),
+                });
+            }
+        }
+    }
+    SpectateSnapshotData {
+        game: game.game_id(),
+        mode: game.mode(),
+        phase: game.status_name().to_string(),
+        paused: game.paused(),
+        picker: game.pick_position().iter().filter_map(|i| game.user_names().get(*i).cloned()).collect(),
+        bans: game.ban_list(),
+        players: players,
+    }
+}
+
+// trades need both players on the same team of one game, inside its trade window, and no banned hero
+fn check_trade(a: &Rc<RefCell<User>>, b: &Rc<RefCell<User>>, ActiveGames: &BTreeMap<u64, ActiveGame>) -> Result<(), &'static str> {
+    let game_id = a.borrow().game_id;
+    if game_id == 0 || b.borrow().game_id != game_id {
+        return Err("not same game");
+    }
+    let game = ActiveGames.get(&game_id).ok_or("not same game")?.state();
+    let res = game.borrow().check_trade(&a.borrow(), &b.borrow());
+    res
+}
+
+fn user_rating(u: &User, rating: &str) -> i16 {
+    match rating {
+        "rk" => u.rk,
+        "at" => u.at,
+        "rk_solo" => u.rk_solo,
+        "at_solo" => u.at_solo,
+        "aram" => u.aram,
+        _ => u.ng,
+    }
+}
+
+fn queue_rating(flow: &str) -> fn(&QueueRoomData) -> i16 {
+    match flow {
+        "rk" | "cm" => |r| r.avg_rk,
+        "at" => |r| r.avg_at,
+        "aram" => |r| r.avg_aram,
+        _ => |r| r.avg_ng,
+    }
+}
+
+fn group_rating(g: &ReadyGroupData, flow: &str) -> i16 {
+    match flow {
+        "rk" | "cm" => g.avg_rk,
+        "at" => g.avg_at,
+        "aram" => g.avg_aram,
+        _ => g.avg_ng,
+    }
+}
+
+fn group_rating_mut<'a>(g: &'a mut ReadyGroupData, flow: &str) -> &'a mut i16 {
+    match flow {
+        "rk" | "cm" => &mut g.avg_rk,
+        "at" => &mut g.avg_at,
+        "aram" => &mut g.avg_aram,
+        _ => &mut g.avg_ng,
+    }
+}
+
+// ranked parties must stay within the rating spread, and from the duo only rating up only small parties may queue
+fn party_restriction(room: &RoomData, mode: &str, rules: &PartyRules) -> Option<&'static str> {
+    let cfg = mode_config(mode);
+    if cfg.flow != "rk" && cfg.flow != "at" && cfg.flow != "cm" {
+        return None;
+    }
+    let rating = cfg.rating;
+    let ratings: Vec<i16> = room.users.iter().map(|u| user_rating(&u.borrow(), rating)).collect();
+    if ratings.len() <= 1 {
+        return None;
+    }
+    let max = *ratings.iter().max().unwrap();
+    let min = *ratings.iter().min().unwrap();
+    if max - min > rules.spread {
+        return Some("rating spread");
+    }
+    if max >= rules.duo_only_rating && ratings.len() > rules.duo_only_size {
+        return Some("duo only");
+    }
+    None
+}
+
+// wait is counted in queue ticks, the range is the widest one the matcher could have used
+fn match_quality(fg: &FightGame) -> MatchQualityData {
+    let cfg = mode_config(&fg.mode);
+    let mut res = MatchQualityData {
+        game: fg.game_id,
+        mode: fg.mode.clone(),
+        ..Default::default()
+    };
+    let mut ratings: Vec<i16> = vec![];
+    let mut total_wait: i64 = 0;
+    for t in &fg.teams {
+        let mut team: Vec<PlayerQualityData> = vec![];
+        let mut party: Vec<usize> = vec![];
+        for r in &t.borrow().rooms {
+            let r = r.borrow();
+            party.push(r.users.len());
+            if r.queue_cnt > res.max_wait {
+                res.max_wait = r.queue_cnt;
+            }
+            for u in &r.users {
+                let rating = user_rating(&u.borrow(), cfg.rating);
+                ratings.push(rating);
+                total_wait += r.queue_cnt;
+                team.push(PlayerQualityData {
+                    id: u.borrow().id.clone(),
+                    rating: rating,
+                    wait: r.queue_cnt,
+                    party: r.users.len(),
+                });
+            }
+        }
+        let sum: i32 = team.iter().map(|p| p.rating as i32).sum();
+        if team.len() > 0 {
+            res.team_avg.push((sum / team.len() as i32) as i16);
+        } else {
+            res.team_avg.push(0);
+        }
+        res.players.push(team);
+        res.parties.push(party);
+    }
+    if ratings.len() > 0 {
+        res.spread = ratings.iter().max().unwrap() - ratings.iter().min().unwrap();
+        res.avg_wait = total_wait / ratings.len() as i64;
+    }
+    if res.team_avg.len() > 0 {
+        res.team_gap = res.team_avg.iter().max().unwrap() - res.team_avg.iter().min().unwrap();
+    }
+    res.range = cfg.range + SCORE_INTERVAL * res.max_wait;
+    res
+}
+
+fn create_direct_game(
+    teams: &Vec<Vec<String>>,
+    mode: String,
+    TotalUsers: &BTreeMap<String, Rc<RefCell<User>>>,
+    InGameUsers: &BTreeMap<String, Rc<RefCell<User>>>,
+    TotalRoom: &mut BTreeMap<u64, Rc<RefCell<RoomData>>>,
+    ReadyGroups: &mut BTreeMap<u64, Rc<RefCell<FightGroup>>>,
+    PreStartGroups: &mut BTreeMap<u64, Rc<RefCell<FightGame>>>,
+    room_id: &mut u64,
+    group_id: &mut u64,
+    game_id: &mut u64,
+    msgtx: &Sender<MqttMsg>,
+    QueueSender: &Sender<QueueData>,
+) -> Result<Option<u64>, Error> {
+    // offline players or players already matched into a game hold the match back
+    for id in teams.iter().flatten() {
+        match TotalUsers.get(id) {
+            Some(u) => {
+                if InGameUsers.contains_key(id) || ReadyGroups.contains_key(&u.borrow().gid) {
+                    info!("direct game skipped, id: {} in game, line: {}", id, line!());
+                    return Ok(None);
+                }
+            }
+            None => {
+                info!("direct game skipped, id: {} offline, line: {}", id, line!());
+                return Ok(None);
+            }
+        }
+    }
+    // players still searching are taken out of the queue with their room
+    for id in teams.iter().flatten() {
+        if let Some(u) = TotalUsers.get(id) {
+            let rid = u.borrow().rid;
+            if let Some(r) = TotalRoom.get(&rid) {
+                if r.borrow().ready != 0 {
+                    r.borrow_mut().ready = 0;
+                    QueueSender.send(QueueData::RemoveRoom(RemoveRoomData{rid: rid}));
+                    msgtx.try_send(MqttMsg{topic:format!("room/{}/res/cancel_queue", r.borrow().master.clone()),
+                        msg: format!(r#"{{"msg":"cancelled"}}"#)})?;
+                }
+            }
+        }
+    }
+    // every team becomes its own room and group, then joins the normal draft as an accepted game
+    let mut fg: FightGame = Default::default();
+    for team in teams {
+        while TotalRoom.contains_key(room_id) {
+            *room_id += 1
+        }
+        let mut new_room = RoomData {
+            rid: *room_id,
+            users: vec![],
+            master: team[0].clone(),
+            last_master: "".to_owned(),
+            avg_ng: 0,
+            avg_rk: 0,
+            avg_at: 0,
+            avg_aram: 0,
+            ready: 0,
+            queue_cnt: 1,
+            mode: mode.clone(),
+            queue_modes: vec![],
+        };
+        for id in team {
+            if let Some(u) = TotalUsers.get(id) {
+                leave_party(u, TotalRoom, msgtx, QueueSender)?;
+                new_room.add_user(Rc::clone(u));
+            }
+        }
+        let r = Rc::new(RefCell::new(new_room));
+        TotalRoom.insert(*room_id, Rc::clone(&r));
+        *group_id += 1;
+        let mut g: FightGroup = Default::default();
+        g.add_room(r);
+        g.prestart();
+        for c in &mut g.checks {
+            c.check = 1;
+        }
+        g.set_group_id(*group_id);
+        g.game_status = 1;
+        let g = Rc::new(RefCell::new(g));
+        ReadyGroups.insert(*group_id, Rc::clone(&g));
+        fg.teams.push(g);
+    }
+    fg.update_names();
+    *game_id += 1;
+    fg.set_game_id(*game_id);
+    fg.set_mode(mode);
+    fg.ready_cnt = 0.0;
+    info!("direct PreStartGroups : {:?}, line: {}", fg, line!());
+    PreStartGroups.insert(*game_id, Rc::new(RefCell::new(fg)));
+    Ok(Some(*game_id))
+}
+
+fn start_tournament_matches(
+    t: &Rc<RefCell<Tournament>>,
+    TournamentGames: &mut BTreeMap<u64, u64>,
+    TotalUsers: &BTreeMap<String, Rc<RefCell<User>>>,
+    InGameUsers: &BTreeMap<String, Rc<RefCell<User>>>,
+    TotalRoom: &mut BTreeMap<u64, Rc<RefCell<RoomData>>>,
+    ReadyGroups: &mut BTreeMap<u64, Rc<RefCell<FightGroup>>>,
+    PreStartGroups: &mut BTreeMap<u64, Rc<RefCell<FightGame>>>,
+    room_id: &mut u64,
+    group_id: &mut u64,
+    game_id: &mut u64,
+    msgtx: &Sender<MqttMsg>,
+    QueueSender: &Sender<QueueData>,
+) -> Result<(), Error> {
+    let pending = t.borrow().pending_matches();
+    for index in pending {
+        let rosters = t.borrow().match_rosters(index);
+        let mode = t.borrow().mode.clone();
+        let gid = match create_direct_game(&rosters, mode, TotalUsers, InGameUsers, TotalRoom, ReadyGroups, PreStartGroups, room_id, group_id, game_id, msgtx, QueueSender)? {
+            Some(gid) => gid,
+            None => continue,
+        };
+        t.borrow_mut().matches[index].game_id = gid;
+        TournamentGames.insert(gid, t.borrow().tid);
+        for roster in &rosters {
+            for id in roster {
+                msgtx.try_send(MqttMsg{topic:format!("tournament/{}/res/match", id),
+                    msg: format!(r#"{{"tournament":{}, "game":{}}}"#, t.borrow().tid, gid)})?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn user_score(
+    u: &Rc<RefCell<User>>,
+    value: i16,
+    msgtx: &Sender<MqttMsg>,
+    sender: &Sender<SqlData>,
+    conn: &mut mysql::PooledConn,
+    mode: String,
+    isWin: bool,
+    raindrop: i64,
+) -> Result<(), Error> {
+    if mode == "ng" {
+        info!("user: {}, ng: {} + {}, raindrop: {} + {}, line: {}",u.borrow().id, u.borrow().ng, value, u.borrow().raindrop, raindrop, line!());
+        u.borrow_mut().ng += value;
+    } else if mode == "rk" {
+        info!("user: {}, rk: {} + {}, raindrop: {} + {}, line: {}",u.borrow().id, u.borrow().rk, value, u.borrow().raindrop, raindrop, line!());
+        u.borrow_mut().rk += value;
+    } else if mode == "at" {
+        info!("user: {}, at: {} + {}, raindrop: {} + {}, line: {}",u.borrow().id, u.borrow().at, value, u.borrow().raindrop, raindrop, line!());
+        u.borrow_mut().at += value;
+    } else if mode == "rk_solo" {
+        info!("user: {}, rk_solo: {} + {}, raindrop: {} + {}, line: {}",u.borrow().id, u.borrow().rk_solo, value, u.borrow().raindrop, raindrop, line!());
+        u.borrow_mut().rk_solo += value;
+    } else if mode == "at_solo" {
+        info!("user: {}, at_solo: {} + {}, raindrop: {} + {}, line: {}",u.borrow().id, u.borrow().at_solo, value, u.borrow().raindrop, raindrop, line!());
+        u.borrow_mut().at_solo += value;
+    }
+    u.borrow_mut().raindrop += raindrop;
+    if u.borrow().reroll < MAX_REROLL_TOKENS {
+        u.borrow_mut().reroll += 1;
+    }
+    msgtx.try_send(MqttMsg {
+        topic: format!("member/{}/res/login", u.borrow().id),
+        msg: format!(
+            r#"{{"msg":"ok", "ng":{}, "rk":{}, "at":{}, "rk_solo":{}, "at_solo":{}, "aram":{}, "raindrop":{}, "reroll":{}, "hero":"{}", "phone":"{}", "email":"{}"}}"#,
+            u.borrow().ng,
+            u.borrow().rk,
+            u.borrow().at,
+            u.borrow().rk_solo,
+            u.borrow().at_solo,
+            u.borrow().aram,
+            u.borrow().raindrop,
+            u.borrow().reroll,
+            u.borrow().hero,
+            u.borrow().phone,
+            u.borrow().email,
+        ),
+    })?;
+    let mut sql = format!(
+        "UPDATE user SET ng={}, rk={}, at={}, rk_solo={}, at_solo={}, reroll={}, raindrop=raindrop+{} WHERE id='{}';",
+        u.borrow().ng.clone(),
+        u.borrow().rk.clone(),
+        u.borrow().at.clone(),
+        u.borrow().rk_solo.clone(),
+        u.borrow().at_solo.clone(),
+        u.borrow().reroll,
+        raindrop,
+        u.borrow().id.clone()
+    );
+    if isWin {
+        sql = format!(
+            "UPDATE user SET ng={}, rk={}, at={}, rk_solo={}, at_solo={}, reroll={}, raindrop=raindrop+{}, first_win=true WHERE id='{}';",
+            u.borrow().ng.clone(),
+            u.borrow().rk.clone(),
+            u.borrow().at.clone(),
+            u.borrow().rk_solo.clone(),
+            u.borrow().at_solo.clone(),
+            u.borrow().reroll,
+            raindrop,
+            u.borrow().id.clone()
+        );
+    }
+    let qres = conn.query(sql.clone())?;
+    Ok(())
+}
+
+// most played heroes first, auto pick falls back on them; owned heroes decide what may be picked
+fn load_user_heros(u: &mut User, conn: &mut mysql::PooledConn) -> Result<(), Error> {
+    let sql = format!(r#"SELECT hero FROM Hero_usage WHERE steam_id='{}' ORDER BY choose_count DESC;"#, u.id.clone());
+    let qres: mysql::QueryResult = conn.query(sql.clone())?;
+    let mut hero_usage: Vec<String> = vec![];
+    for row in qres {
+        let a = row?.clone();
+        hero_usage.push(mysql::from_value_opt(a.get("hero").ok_or(Error::from(core::fmt::Error))?)?);
+    }
+    u.hero_usage = hero_usage;
+    let sql = format!(r#"SELECT hero FROM Hero_owned WHERE steam_id='{}';"#, u.id.clone());
+    let qres: mysql::QueryResult = conn.query(sql.clone())?;
+    let mut owned_heros: Vec<String> = vec![];
+    for row in qres {
+        let a = row?.clone();
+        owned_heros.push(mysql::from_value_opt(a.get("hero").ok_or(Error::from(core::fmt::Error))?)?);
+    }
+    u.owned_heros = owned_heros;
+    Ok(())
+}
+
+fn get_ng(team: &Vec<Rc<RefCell<User>>>) -> Vec<i32> {
+    let mut res: Vec<i32> = vec![];
+    for u in team {
+        res.push(u.borrow().ng.into());
+    }
+    res
+}
+
+fn get_rk(team: &Vec<Rc<RefCell<User>>>) -> Vec<i32> {
+    get_rating(team, "rk")
 }
 
 fn get_at(team: &Vec<Rc<RefCell<User>>>) -> Vec<i32> {
+    get_rating(team, "at")
+}
+
+fn get_rating(team: &Vec<Rc<RefCell<User>>>, rating: &str) -> Vec<i32> {
     let mut res: Vec<i32> = vec![];
     for u in team {
-        res.push(u.borrow().at.into());
+        res.push(user_rating(&u.borrow(), rating).into());
     }
     res
 }
@@ -834,14 +1618,13 @@ fn check_is_black(
             );
             let qres = conn.query(sql.clone())?;
             let mut count = 0;
-            for row in qres {
+            for row in qres.take(1) {
                 let a = row?.clone();
                 if let Some(n) = a.get("count(*)") {
                     count = mysql::from_value_opt(n)?;
                 } else {
                     warn!("mysql error: {}, can not get count(*), line : {}", sql.clone(), line!());
                 }
-                break;
             }
             if count > 0 {
                 isBlack = true;
@@ -852,14 +1635,13 @@ fn check_is_black(
                 g_user_id, user_id
             );
             let qres2 = conn.query(sql.clone())?;
-            for row in qres2 {
+            for row in qres2.take(1) {
                 let a = row?.clone();
                 if let Some(n) = a.get("count(*)") {
                     count = mysql::from_value_opt(n)?;
                 } else {
                     warn!("mysql error: {}, can not get count(*), line : {}", sql.clone(), line!());
                 }
-                break;
             }
             if count > 0 {
                 isBlack = true;
@@ -885,14 +1667,18 @@ fn settlement_score(
     if win.len() == 0 || lose.len() == 0 {
         return;
     }
+    let rating = mode_config(&mode).rating.to_string();
     let mut win_score: Vec<i32> = get_ng(win);
     let mut lose_score: Vec<i32> = get_ng(lose);
-    if mode == "rk" {
+    if rating == "rk" {
         win_score = get_rk(win);
         lose_score = get_rk(lose);
-    } else if mode == "at" {
+    } else if rating == "at" {
         win_score = get_at(win);
         lose_score = get_at(lose);
+    } else if rating == "rk_solo" || rating == "at_solo" {
+        win_score = get_rating(win, &rating);
+        lose_score = get_rating(lose, &rating);
     }
     // println!("win : {:?}, lose : {:?}", win_score, lose_score);
     let elo = EloRank { k: 40.0 };
@@ -912,7 +1698,7 @@ fn settlement_score(
                 msgtx,
                 sender,
                 conn,
-                mode.clone(),
+                rating.clone(),
                 true,
                 raindrop + 400,
             );
@@ -923,7 +1709,7 @@ fn settlement_score(
                 msgtx,
                 sender,
                 conn,
-                mode.clone(),
+                rating.clone(),
                 true,
                 raindrop,
             );
@@ -937,7 +1723,7 @@ fn settlement_score(
             msgtx,
             sender,
             conn,
-            mode.clone(),
+            rating.clone(),
             false,
             raindrop/2,
         );
@@ -1004,13 +1790,35 @@ pub fn HandleSqlRequest(pool: mysql::Pool) -> Result<Sender<SqlData>, Error> {
                                     for data in x.chooseData.clone() {
                                         values = format!("{} ,'{}'", values, data.ban_hero);
                                     }
+                                    values = format!("{} ,{}", values, x.seed);
+                                    let mut columns = "game, mode".to_string();
+                                    for column in vec!["steam_id", "hero", "ban"] {
+                                        for i in 1..x.chooseData.len() + 1 {
+                                            columns = format!("{}, {}{}", columns, column, i);
+                                        }
+                                    }
                                     let sql = format!(
-                                        "REPLACE INTO Gaming(game, mode, steam_id1, steam_id2, steam_id3, steam_id4, steam_id5, steam_id6, steam_id7, steam_id8, steam_id9, steam_id10, hero1, hero2, hero3, hero4, hero5, hero6, hero7, hero8, hero9, hero10, ban1, ban2, ban3, ban4, ban5, ban6, ban7, ban8, ban9, ban10) {});",
-                                        values
+                                        "REPLACE INTO Gaming({}, seed) {});",
+                                        columns, values
                                     );
                                     info!("sql : {}, line: {}", sql, line!());
                                     let qres = conn.query(sql.clone())?;
                                 }
+                                SqlData::DraftLog(x) => {
+                                    let sql = format!(
+                                        "insert into DraftLog (game, id, action, hero, phase, time) values ({}, '{}', '{}', '{}', '{}', {});",
+                                        x.game, x.id, x.action, x.hero, x.phase, x.time
+                                    );
+                                    let qres = conn.query(sql.clone())?;
+                                }
+                                SqlData::MatchQuality(x) => {
+                                    let sql = format!(
+                                        "insert into MatchQuality (game, mode, spread, team_gap, search_range, max_wait, avg_wait, team_avg, parties, players) values ({}, '{}', {}, {}, {}, {}, {}, '{}', '{}', '{}');",
+                                        x.game, x.mode, x.spread, x.team_gap, x.range, x.max_wait, x.avg_wait,
+                                        serde_json::to_string(&x.team_avg)?, serde_json::to_string(&x.parties)?, serde_json::to_string(&x.players)?
+                                    );
+                                    let qres = conn.query(sql.clone())?;
+                                }
                             }
                         }
                         Ok(())
@@ -1026,9 +1834,11 @@ pub fn HandleSqlRequest(pool: mysql::Pool) -> Result<Sender<SqlData>, Error> {
     Ok(tx1)
 }
 
-fn canGroupNG(
+// the join rules shared by every mode, the mode config picks the rating and the allowed range
+fn canGroup(
     readyGroup: &mut ReadyGroupData,
     queueRoom: &Rc<RefCell<QueueRoomData>>,
+    cfg: &ModeConfig,
     conn: &mut mysql::PooledConn,
     group_id: u64,
 ) -> Result<bool, Error> {
@@ -1040,12 +1850,14 @@ fn canGroupNG(
     //     line!()
     // );
     if queueRoom.borrow().ready == 0
-        && queueRoom.borrow().user_len as i16 + readyGroup.user_len <= TEAM_SIZE
+        && queueRoom.borrow().user_len as i16 + readyGroup.user_len <= cfg.team_size
+        && !low_priority_blocked(readyGroup, &queueRoom.borrow())
     {
-        let Difference: i64 = i64::abs((queueRoom.borrow().avg_ng - readyGroup.avg_ng).into());
-        let mut ng = 0;
-        if readyGroup.avg_ng == 0
-            || Difference <= NG_RANGE + SCORE_INTERVAL * queueRoom.borrow().queue_cnt
+        let room_rating = queue_rating(cfg.flow)(&queueRoom.borrow());
+        let group_rating = *group_rating_mut(readyGroup, cfg.flow);
+        let Difference: i64 = i64::abs((room_rating - group_rating).into());
+        if group_rating == 0
+            || Difference <= cfg.range + SCORE_INTERVAL * queueRoom.borrow().queue_cnt
         {
             // let isBlack = check_is_black(
             //     queueRoom.borrow().user_ids.clone(),
@@ -1056,17 +1868,12 @@ fn canGroupNG(
             //     res = false;
             //     return Ok(res);
             // }
-            // info!(
-            //     "group user_len : {}, room user_len : {}",
-            //     readyGroup.user_len,
-            //     queueRoom.borrow().user_len
-            // );
             if (readyGroup.user_len + queueRoom.borrow().user_len <= 0) {
                 res = false;
                 return Ok(res);
             }
-            ng = (readyGroup.avg_ng * readyGroup.user_len
-                + queueRoom.borrow().avg_ng * queueRoom.borrow().user_len) as i16
+            let avg = (group_rating * readyGroup.user_len
+                + room_rating * queueRoom.borrow().user_len) as i16
                 / (readyGroup.user_len + queueRoom.borrow().user_len) as i16;
             for user_id in &queueRoom.borrow().user_ids {
                 readyGroup.user_ids.push(user_id.clone());
@@ -1075,7 +1882,13 @@ fn canGroupNG(
                 readyGroup.queue_cnt = queueRoom.borrow().queue_cnt;
             }
             readyGroup.rid.push(queueRoom.borrow().rid);
-            readyGroup.avg_ng = ng;
+            if queueRoom.borrow().priority {
+                readyGroup.priority = true;
+            }
+            if queueRoom.borrow().low_priority {
+                readyGroup.low_priority = true;
+            }
+            *group_rating_mut(readyGroup, cfg.flow) = avg;
             readyGroup.user_len += queueRoom.borrow().user_len;
             queueRoom.borrow_mut().ready = 1;
             queueRoom.borrow_mut().gid = group_id + 1;
@@ -1085,171 +1898,58 @@ fn canGroupNG(
     Ok(res)
 }
 
-fn canGroupRK(
-    readyGroup: &mut ReadyGroupData,
-    queueRoom: &Rc<RefCell<QueueRoomData>>,
-    conn: &mut mysql::PooledConn,
-    group_id: u64,
-) -> Result<bool, Error> {
-    let mut res = false;
-    // info!(
-    //     "room : {:?} try_join group {:?}, line : {}",
-    //     queueRoom,
-    //     readyGroup,
-    //     line!()
-    // );
-    if queueRoom.borrow().ready == 0
-        && queueRoom.borrow().user_len as i16 + readyGroup.user_len <= TEAM_SIZE
-    {
-        let Difference: i64 = i64::abs((queueRoom.borrow().avg_rk - readyGroup.avg_rk).into());
-        let mut rk = 0;
-        if readyGroup.avg_rk == 0
-            || Difference <= RANK_RANGE + SCORE_INTERVAL * queueRoom.borrow().queue_cnt
-        {
-            // let isBlack = check_is_black(
-            //     queueRoom.borrow().user_ids.clone(),
-            //     readyGroup.user_ids.clone(),
-            //     conn,
-            // )?;
-            // if (isBlack) {
-            //     res = false;
-            //     return Ok(res);
-            // }
-            // info!(
-            //     "group user_len : {}, room user_len : {}",
-            //     readyGroup.user_len,
-            //     queueRoom.borrow().user_len
-            // );
-            if (readyGroup.user_len + queueRoom.borrow().user_len <= 0) {
-                res = false;
-                return Ok(res);
-            }
-            rk = (readyGroup.avg_rk * readyGroup.user_len
-                + queueRoom.borrow().avg_rk * queueRoom.borrow().user_len) as i16
-                / (readyGroup.user_len + queueRoom.borrow().user_len) as i16;
-            for user_id in &queueRoom.borrow().user_ids {
-                readyGroup.user_ids.push(user_id.clone());
-            }
-            if queueRoom.borrow().queue_cnt > readyGroup.queue_cnt {
-                readyGroup.queue_cnt = queueRoom.borrow().queue_cnt;
-            }
-            readyGroup.rid.push(queueRoom.borrow().rid);
-            readyGroup.avg_rk = rk;
-            readyGroup.user_len += queueRoom.borrow().user_len;
-            queueRoom.borrow_mut().ready = 1;
-            queueRoom.borrow_mut().gid = group_id + 1;
-            info!("group {:?}, line: {}", readyGroup, line!());
-        }
+// low priority rooms only match each other until one side waited LOW_PRIORITY_WAIT ticks
+fn low_priority_blocked(readyGroup: &ReadyGroupData, queueRoom: &QueueRoomData) -> bool {
+    if readyGroup.user_len == 0 || readyGroup.low_priority == queueRoom.low_priority {
+        return false;
     }
-    Ok(res)
+    let waited = if queueRoom.low_priority { queueRoom.queue_cnt } else { readyGroup.queue_cnt };
+    waited < LOW_PRIORITY_WAIT
 }
 
-fn canGroupAT(
-    readyGroup: &mut ReadyGroupData,
-    queueRoom: &Rc<RefCell<QueueRoomData>>,
-    conn: &mut mysql::PooledConn,
-    group_id: u64,
-) -> Result<bool, Error> {
-    let mut res = false;
-    // info!("room : {:?} try_join group {:?}, line : {}", queueRoom, readyGroup, line!());
-    if queueRoom.borrow().ready == 0
-        && queueRoom.borrow().user_len as i16 + readyGroup.user_len <= TEAM_SIZE
-    {
-        let Difference: i64 = i64::abs((queueRoom.borrow().avg_at - readyGroup.avg_at).into());
-        let mut at = 0;
-        if readyGroup.avg_at == 0
-            || Difference <= RANK_RANGE + SCORE_INTERVAL * queueRoom.borrow().queue_cnt
-        {
-            // let isBlack = check_is_black(
-            //     queueRoom.borrow().user_ids.clone(),
-            //     readyGroup.user_ids.clone(),
-            //     conn,
-            // )?;
-            // if (isBlack) {
-            //     res = false;
-            //     return Ok(res);
-            // }
-            // info!(
-            //     "group user_len : {}, room user_len : {}",
-            //     readyGroup.user_len,
-            //     queueRoom.borrow().user_len
-            // );
-            if (readyGroup.user_len + queueRoom.borrow().user_len <= 0) {
-                res = false;
-                return Ok(res);
-            }
-            at = (readyGroup.avg_at * readyGroup.user_len
-                + queueRoom.borrow().avg_at * queueRoom.borrow().user_len) as i16
-                / (readyGroup.user_len + queueRoom.borrow().user_len) as i16;
-            for user_id in &queueRoom.borrow().user_ids {
-                readyGroup.user_ids.push(user_id.clone());
-            }
-            if queueRoom.borrow().queue_cnt > readyGroup.queue_cnt {
-                readyGroup.queue_cnt = queueRoom.borrow().queue_cnt;
-            }
-            readyGroup.rid.push(queueRoom.borrow().rid);
-            readyGroup.avg_at = at;
-            readyGroup.user_len += queueRoom.borrow().user_len;
-            queueRoom.borrow_mut().ready = 1;
-            queueRoom.borrow_mut().gid = group_id + 1;
-            info!("group {:?}, line: {}", readyGroup, line!());
-        }
+fn low_priority_match(rg: &ReadyGroupData, rg2: &ReadyGroupData) -> bool {
+    if rg.low_priority == rg2.low_priority {
+        return true;
     }
-    Ok(res)
+    let waited = if rg.low_priority { rg.queue_cnt } else { rg2.queue_cnt };
+    waited >= LOW_PRIORITY_WAIT
 }
 
-fn canGroupARAM(
-    readyGroup: &mut ReadyGroupData,
-    queueRoom: &Rc<RefCell<QueueRoomData>>,
-    conn: &mut mysql::PooledConn,
-    group_id: u64,
-) -> Result<bool, Error> {
-    let mut res = false;
-    // info!("room : {:?} try_join group {:?}, line : {}", queueRoom, readyGroup, line!());
-    if queueRoom.borrow().ready == 0
-        && queueRoom.borrow().user_len as i16 + readyGroup.user_len <= TEAM_SIZE
-    {
-        let Difference: i64 = i64::abs((queueRoom.borrow().avg_aram - readyGroup.avg_aram).into());
-        let mut aram = 0;
-        if readyGroup.avg_aram == 0
-            || Difference <= RANK_RANGE + SCORE_INTERVAL * queueRoom.borrow().queue_cnt
-        {
-            // let isBlack = check_is_black(
-            //     queueRoom.borrow().user_ids.clone(),
-            //     readyGroup.user_ids.clone(),
-            //     conn,
-            // )?;
-            // if (isBlack) {
-            //     res = false;
-            //     return Ok(res);
-            // }
-            // info!(
-            //     "group user_len : {}, room user_len : {}",
-            //     readyGroup.user_len,
-            //     queueRoom.borrow().user_len
-            // );
-            if (readyGroup.user_len + queueRoom.borrow().user_len <= 0) {
-                res = false;
-                return Ok(res);
-            }
-            aram = (readyGroup.avg_aram * readyGroup.user_len
-                + queueRoom.borrow().avg_aram * queueRoom.borrow().user_len) as i16
-                / (readyGroup.user_len + queueRoom.borrow().user_len) as i16;
-            for user_id in &queueRoom.borrow().user_ids {
-                readyGroup.user_ids.push(user_id.clone());
-            }
-            if queueRoom.borrow().queue_cnt > readyGroup.queue_cnt {
-                readyGroup.queue_cnt = queueRoom.borrow().queue_cnt;
-            }
-            readyGroup.rid.push(queueRoom.borrow().rid);
-            readyGroup.avg_aram = aram;
-            readyGroup.user_len += queueRoom.borrow().user_len;
-            queueRoom.borrow_mut().ready = 1;
-            queueRoom.borrow_mut().gid = group_id + 1;
-            info!("group {:?}, line: {}", readyGroup, line!());
+// rooms requeued after someone else declined go first, the rest keep rid order
+fn priority_rooms(
+    rooms: &BTreeMap<u64, Rc<RefCell<QueueRoomData>>>,
+) -> Vec<Rc<RefCell<QueueRoomData>>> {
+    let mut res: Vec<Rc<RefCell<QueueRoomData>>> = rooms.iter().map(|x| Rc::clone(x.1)).collect();
+    res.sort_by_key(|x| !x.borrow().priority);
+    res
+}
+
+fn priority_groups(
+    groups: &BTreeMap<u64, Rc<RefCell<ReadyGroupData>>>,
+) -> Vec<(u64, Rc<RefCell<ReadyGroupData>>)> {
+    let mut res: Vec<(u64, Rc<RefCell<ReadyGroupData>>)> = groups.iter().map(|x| (*x.0, Rc::clone(x.1))).collect();
+    res.sort_by_key(|x| !x.1.borrow().priority);
+    res
+}
+
+// closest solo room that is not grouped yet, the allowed gap widens while the slot stays open
+fn backfill_candidate(queue: &QueueIndex, req: &BackfillData) -> Option<u64> {
+    let mut res: Option<(u64, i64)> = None;
+    let width = NG_RANGE + SCORE_INTERVAL * queue.max_queue_cnt() + BACKFILL_INTERVAL * req.wait;
+    for r in queue.window(req.ng, width) {
+        let r = r.borrow();
+        if r.user_len != 1 || r.ready != 0 || r.low_priority {
+            continue;
+        }
+        let difference: i64 = i64::abs((r.avg_ng - req.ng).into());
+        if difference > NG_RANGE + SCORE_INTERVAL * r.queue_cnt + BACKFILL_INTERVAL * req.wait {
+            continue;
+        }
+        if res.map_or(true, |x| difference < x.1) {
+            res = Some((r.rid, difference));
         }
     }
-    Ok(res)
+    res.map(|x| x.0)
 }
 
 pub fn HandleQueueRequest(
@@ -1263,18 +1963,14 @@ pub fn HandleQueueRequest(
     let update5000ms = tick(Duration::from_millis(5000));
 
     thread::spawn(move || -> Result<(), Error> {
-        let mut SoloNGQueueRoom: BTreeMap<u64, Rc<RefCell<QueueRoomData>>> = BTreeMap::new();
-        let mut NGQueueRoom: BTreeMap<u64, Rc<RefCell<QueueRoomData>>> = BTreeMap::new();
-        let mut ARAMQueueRoom: BTreeMap<u64, Rc<RefCell<QueueRoomData>>> = BTreeMap::new();
-        // let mut NGGroupedQueueRoom: BTreeMap<u64, Rc<RefCell<QueueRoomData>>> = BTreeMap::new();
-        let mut RKQueueRoom: BTreeMap<u64, Rc<RefCell<QueueRoomData>>> = BTreeMap::new();
-        let mut ATQueueRoom: BTreeMap<u64, Rc<RefCell<QueueRoomData>>> = BTreeMap::new();
-        let mut SoloNGReadyGroups: BTreeMap<u64, Rc<RefCell<ReadyGroupData>>> = BTreeMap::new();
-        let mut NGReadyGroups: BTreeMap<u64, Rc<RefCell<ReadyGroupData>>> = BTreeMap::new();
-        let mut ARAMReadyGroups: BTreeMap<u64, Rc<RefCell<ReadyGroupData>>> = BTreeMap::new();
-        let mut RKReadyGroups: BTreeMap<u64, Rc<RefCell<ReadyGroupData>>> = BTreeMap::new();
-        let mut ATReadyGroups: BTreeMap<u64, Rc<RefCell<ReadyGroupData>>> = BTreeMap::new();
+        let mut QueueRooms: BTreeMap<String, QueueIndex> = BTreeMap::new();
+        let mut QueueReadyGroups: BTreeMap<String, BTreeMap<u64, Rc<RefCell<ReadyGroupData>>>> = BTreeMap::new();
+        for mode in QUEUE_MODES.iter() {
+            QueueRooms.insert(mode.to_string(), QueueIndex::new(queue_rating(mode_config(mode).flow)));
+            QueueReadyGroups.insert(mode.to_string(), BTreeMap::new());
+        }
         let mut matchGroup: BTreeMap<u64, Rc<RefCell<u64>>> = BTreeMap::new();
+        let mut BackfillRequests: Vec<BackfillData> = vec![];
         let mut conn = pool.get_conn()?;
         let mut group_id: u64 = 0;
         let mut ngState = "open";
@@ -1285,861 +1981,170 @@ pub fn HandleQueueRequest(
             select! {
                 recv(update) -> _ => {
                     let mut new_now = Instant::now();
-                    // sng
-                    if ngState == "open" {
-                        if SoloNGQueueRoom.len() >= MATCH_SIZE {
-                            let mut g: ReadyGroupData = Default::default();
-                            let mut tq: Vec<Rc<RefCell<QueueRoomData>>> = vec![];
-                            let mut id: Vec<u64> = vec![];
-                            let mut new_now = Instant::now();
-                            tq = SoloNGQueueRoom.iter().map(|x|Rc::clone(x.1)).collect();
-                            let mut new_now = Instant::now();
-                            tq.sort_by_key(|x| x.borrow().avg_ng);
-                            let mut new_now1 = Instant::now();
-                            for (k, v) in &SoloNGQueueRoom {
-                                v.borrow_mut().queue_cnt += 1;
-                                let updateRoomQueueCntData = UpdateRoomQueueCntData {
-                                    rid: v.borrow().rid,
-                                };
-                                sender.try_send(RoomEventData::UpdateRoomQueueCnt(updateRoomQueueCntData));
-                            }
-                            for (k, v) in &SoloNGQueueRoom {
-                                for (k2 , v2) in &SoloNGQueueRoom {
-                                    let mut isMatch = false;
-                                    if let Some(gid) = matchGroup.get(&v2.borrow().gid.clone()) {
-                                        isMatch = true;
-                                    }
-                                    if !isMatch {
-                                        v2.borrow_mut().ready = 0;
-                                        v2.borrow_mut().gid = 0;
-                                    }
-                                }
-                                g = Default::default();
-                                canGroupNG(&mut g, v, &mut conn, group_id);
-                                for (k2, mut v2) in &SoloNGQueueRoom {
-                                    canGroupNG(&mut g, v2, &mut conn, group_id);
-                                }
-                                if g.user_len == TEAM_SIZE {
-                                    println!("match team_size!, line: {}", line!());
-                                    group_id += 1;
-                                    info!("new group_id: {}, line: {}", group_id, line!());
-                                    g.gid = group_id;
-                                    SoloNGReadyGroups.insert(group_id, Rc::new(RefCell::new(g.clone())));
-                                    matchGroup.insert(group_id, Rc::new(RefCell::new(group_id.clone())));
-                                    g = Default::default();
+                    let mut popped: Vec<u64> = vec![];
+                    // backfill
+                    let mut found: Vec<usize> = vec![];
+                    for (i, req) in BackfillRequests.iter_mut().enumerate() {
+                        req.wait += 1;
+                        // ng games also take players from the solo ng queue
+                        let queues = QueueRooms.iter_mut().filter(|(mode, _)| **mode == req.mode || (req.mode == "ng" && *mode == "sng"));
+                        for (_, queue) in queues {
+                            if let Some(rid) = backfill_candidate(queue, req) {
+                                if let Some(r) = queue.remove(&rid) {
+                                    r.borrow_mut().ready = 1;
                                 }
-                                // info!("{:?}", matchGroup);
-                            }
-                            //println!("Time 2: {:?}",Instant::now().duration_since(new_now1));
-                            if g.user_len < TEAM_SIZE {
-                                for r in g.rid {
-                                    let mut room = SoloNGQueueRoom.get(&r);
-                                    if let Some(room) = room {
-                                        room.borrow_mut().ready = 0;
-                                        room.borrow_mut().gid = 0;
-                                    }
-                                }
-                                for r in id {
-                                    let mut room = SoloNGQueueRoom.get(&r);
-                                    if let Some(room) = room {
-                                        room.borrow_mut().ready = 0;
-                                        room.borrow_mut().gid = 0;
-                                    }
-                                }
-                            }
-                        }
-                        if SoloNGReadyGroups.len() > 0 {
-                            println!("SoloNGReadyGroup!! {}, line: {}", SoloNGReadyGroups.len(), line!());
-                            println!("SoloNGReadyGroup!! {:?}, line: {}", SoloNGReadyGroups, line!());
-                        }
-                        if SoloNGReadyGroups.len() >= MATCH_SIZE {
-                            let mut prestart = false;
-                            let mut total_ng: i16 = 0;
-                            let mut rm_ids: Vec<u64> = vec![];
-                            let mut new_now2 = Instant::now();
-                            let mut isMatch = false;
-                            for (id, rg) in &SoloNGReadyGroups {
-                                let mut fg: ReadyGameData = Default::default();
-                                total_ng = 0;
-                                if rg.borrow().game_status == 0 && fg.team_len < MATCH_SIZE {
-                                    if total_ng == 0 {
-                                        total_ng += rg.borrow().avg_ng as i16;
-                                        fg.group.push(rg.borrow().rid.clone());
-                                        fg.gid.push(*id);
-                                        fg.team_len += 1;
-                                        for (id2, rg2) in &SoloNGReadyGroups {
-                                            let mut isInFG = false;
-                                            for gid in &fg.gid {
-                                                if gid == id2 {
-                                                    isInFG = true;
-                                                }
-                                            }
-                                            if fg.team_len < MATCH_SIZE && !isInFG {
-                                                let mut difference = 0;
-                                                if fg.team_len > 0 {
-                                                    difference = i64::abs((rg2.borrow().avg_ng as i16 - total_ng/fg.team_len as i16).into());
-                                                }
-                                                if difference <= NG_RANGE + SCORE_INTERVAL * rg2.borrow().queue_cnt {
-                                                    total_ng += rg2.borrow().avg_ng as i16;
-                                                    fg.group.push(rg2.borrow().rid.clone());
-                                                    fg.team_len += 1;
-                                                    fg.gid.push(*id2);
-                                                }
-                                                else {
-                                                    rg2.borrow_mut().queue_cnt += 1;
-                                                }
-                                            }
-                                            if fg.team_len == MATCH_SIZE {
-                                                sender.send(RoomEventData::UpdateGame(PreGameData{rid: fg.group.clone(), mode: "ng".to_string()}));
-                                                for id in fg.gid {
-                                                    rm_ids.push(id);
-                                                }
-                                                fg = Default::default();
-                                                isMatch = true;
-                                            }
-                                            if isMatch {
-                                                break;
-                                            }
-                                        }
-                                        if isMatch {
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                            for id in rm_ids {
-                                let rg = SoloNGReadyGroups.remove(&id);
-                                if let Some(rg) = rg {
-                                    for rid in &rg.borrow().rid {
-                                        SoloNGQueueRoom.remove(&rid);
-                                    }
-                                }
-                            }
-                            // println!("GroupedRoom : {:?}", NGGroupedQueueRoom);
-                            // println!("MatchedGroup : {:?}", NGMatchedGroups);
-                        }
-                    } else {
-                        for (k, v) in &mut SoloNGQueueRoom {
-                            for uid in &v.borrow().user_ids {
-                                sender.try_send(RoomEventData::CancelQueue(CancelQueueData{action: "cancel_queue".to_string(), id: uid.to_string(), room: "".to_string(), mode: "ng".to_string()}));
-                                // sender.try_send(RoomEventData::Leave(LeaveData{id: uid.to_string(), room: "".to_string()}));
+                                popped.push(rid);
+                                sender.try_send(RoomEventData::BackfillFound(BackfillFoundData{game: req.game, rid: rid}));
+                                found.push(i);
+                                break;
                             }
                         }
                     }
-                    // sng
-                    // ng
-                    if ngState == "open" {
-                        if NGQueueRoom.len() >= MATCH_SIZE {
-                            let mut g: ReadyGroupData = Default::default();
-                            let mut tq: Vec<Rc<RefCell<QueueRoomData>>> = vec![];
-                            let mut id: Vec<u64> = vec![];
-                            let mut new_now = Instant::now();
-                            tq = NGQueueRoom.iter().map(|x|Rc::clone(x.1)).collect();
-                            let mut new_now = Instant::now();
-                            tq.sort_by_key(|x| x.borrow().avg_ng);
-                            let mut new_now1 = Instant::now();
-                            for (k, v) in &NGQueueRoom {
-                                v.borrow_mut().queue_cnt += 1;
-                                let updateRoomQueueCntData = UpdateRoomQueueCntData {
-                                    rid: v.borrow().rid,
-                                };
-                                sender.try_send(RoomEventData::UpdateRoomQueueCnt(updateRoomQueueCntData));
-                            }
-                            for (k, v) in &NGQueueRoom {
-                                for (k2 , v2) in &NGQueueRoom {
-                                    let mut isMatch = false;
-                                    if let Some(gid) = matchGroup.get(&v2.borrow().gid.clone()) {
-                                        isMatch = true;
-                                    }
-                                    if !isMatch {
-                                        v2.borrow_mut().ready = 0;
-                                        v2.borrow_mut().gid = 0;
-                                    }
-                                }
-                                g = Default::default();
-                                canGroupNG(&mut g, v, &mut conn, group_id);
-                                for (k2, mut v2) in &NGQueueRoom {
-                                    canGroupNG(&mut g, v2, &mut conn, group_id);
-                                }
-                                if g.user_len == TEAM_SIZE {
-                                    println!("match team_size!, line: {}", line!());
-                                    group_id += 1;
-                                    info!("new group_id: {}, line: {}", group_id, line!());
-                                    g.gid = group_id;
-                                    NGReadyGroups.insert(group_id, Rc::new(RefCell::new(g.clone())));
-                                    matchGroup.insert(group_id, Rc::new(RefCell::new(group_id.clone())));
-                                    g = Default::default();
-                                }
-                                // info!("{:?}", matchGroup);
-                            }
-                            //println!("Time 2: {:?}",Instant::now().duration_since(new_now1));
-                            if g.user_len < TEAM_SIZE {
-                                for r in g.rid {
-                                    let mut room = NGQueueRoom.get(&r);
-                                    if let Some(room) = room {
-                                        room.borrow_mut().ready = 0;
-                                        room.borrow_mut().gid = 0;
-                                    }
-                                }
-                                for r in id {
-                                    let mut room = NGQueueRoom.get(&r);
-                                    if let Some(room) = room {
-                                        room.borrow_mut().ready = 0;
-                                        room.borrow_mut().gid = 0;
-                                    }
-                                }
-                            }
-                        }
-                        if NGReadyGroups.len() > 0 {
-                            println!("NGReadyGroup!! {}, line: {}", NGReadyGroups.len(), line!());
-                            println!("NGReadyGroup!! {:?}, line: {}", NGReadyGroups, line!());
-                        }
-                        if NGReadyGroups.len() >= MATCH_SIZE {
-                            let mut prestart = false;
-                            let mut total_ng: i16 = 0;
-                            let mut rm_ids: Vec<u64> = vec![];
-                            let mut new_now2 = Instant::now();
-                            let mut isMatch = false;
-                            for (id, rg) in &NGReadyGroups {
-                                let mut fg: ReadyGameData = Default::default();
-                                total_ng = 0;
-                                if rg.borrow().game_status == 0 && fg.team_len < MATCH_SIZE {
-                                    if total_ng == 0 {
-                                        total_ng += rg.borrow().avg_ng as i16;
-                                        fg.group.push(rg.borrow().rid.clone());
-                                        fg.gid.push(*id);
-                                        fg.team_len += 1;
-                                        for (id2, rg2) in &NGReadyGroups {
-                                            let mut isInFG = false;
-                                            for gid in &fg.gid {
-                                                if gid == id2 {
-                                                    isInFG = true;
-                                                }
-                                            }
-                                            if fg.team_len < MATCH_SIZE && !isInFG {
-                                                let mut difference = 0;
-                                                if fg.team_len > 0 {
-                                                    difference = i64::abs((rg2.borrow().avg_ng as i16 - total_ng/fg.team_len as i16).into());
-                                                }
-                                                if difference <= NG_RANGE + SCORE_INTERVAL * rg2.borrow().queue_cnt {
-                                                    total_ng += rg2.borrow().avg_ng as i16;
-                                                    fg.group.push(rg2.borrow().rid.clone());
-                                                    fg.team_len += 1;
-                                                    fg.gid.push(*id2);
-                                                }
-                                                else {
-                                                    rg2.borrow_mut().queue_cnt += 1;
-                                                }
-                                            }
-                                            if fg.team_len == MATCH_SIZE {
-                                                sender.send(RoomEventData::UpdateGame(PreGameData{rid: fg.group.clone(), mode: "ng".to_string()}));
-                                                for id in fg.gid {
-                                                    rm_ids.push(id);
-                                                }
-                                                fg = Default::default();
-                                                isMatch = true;
-                                            }
-                                            if isMatch {
-                                                break;
-                                            }
-                                        }
-                                        if isMatch {
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                            for id in rm_ids {
-                                let rg = NGReadyGroups.remove(&id);
-                                if let Some(rg) = rg {
-                                    for rid in &rg.borrow().rid {
-                                        NGQueueRoom.remove(&rid);
-                                    }
-                                }
-                            }
-                            // println!("GroupedRoom : {:?}", NGGroupedQueueRoom);
-                            // println!("MatchedGroup : {:?}", NGMatchedGroups);
-                        }
-                    } else {
-                        for (k, v) in &mut NGQueueRoom {
-                            for uid in &v.borrow().user_ids {
-                                sender.try_send(RoomEventData::CancelQueue(CancelQueueData{action: "cancel_queue".to_string(), id: uid.to_string(), room: "".to_string(), mode: "ng".to_string()}));
-                                // sender.try_send(RoomEventData::Leave(LeaveData{id: uid.to_string(), room: "".to_string()}));
-                            }
-                        }
+                    for i in found.iter().rev() {
+                        BackfillRequests.remove(*i);
                     }
-                    // ng
-                    // aram
-                    if aramState == "open" {
-                        if ARAMQueueRoom.len() >= MATCH_SIZE {
-                            let mut g: ReadyGroupData = Default::default();
-                            let mut tq: Vec<Rc<RefCell<QueueRoomData>>> = vec![];
-                            let mut id: Vec<u64> = vec![];
-                            let mut new_now = Instant::now();
-                            tq = ARAMQueueRoom.iter().map(|x|Rc::clone(x.1)).collect();
-                            let mut new_now = Instant::now();
-                            tq.sort_by_key(|x| x.borrow().avg_aram);
-                            let mut new_now1 = Instant::now();
-                            for (k, v) in &ARAMQueueRoom {
-                                v.borrow_mut().queue_cnt += 1;
-                                let updateRoomQueueCntData = UpdateRoomQueueCntData {
-                                    rid: v.borrow().rid,
-                                };
-                                sender.try_send(RoomEventData::UpdateRoomQueueCnt(updateRoomQueueCntData));
-                            }
-                            for (k, v) in &ARAMQueueRoom {
-                                for (k2 , v2) in &ARAMQueueRoom {
-                                    let mut isMatch = false;
-                                    if let Some(gid) = matchGroup.get(&v2.borrow().gid.clone()) {
-                                        isMatch = true;
-                                    }
-                                    if !isMatch {
-                                        v2.borrow_mut().ready = 0;
-                                        v2.borrow_mut().gid = 0;
-                                    }
-                                }
-                                g = Default::default();
-                                canGroupARAM(&mut g, v, &mut conn, group_id);
-                                for (k2, mut v2) in &ARAMQueueRoom {
-                                    canGroupARAM(&mut g, v2, &mut conn, group_id);
-                                }
-                                if g.user_len == TEAM_SIZE {
-                                    println!("match team_size!, line: {}", line!());
-                                    group_id += 1;
-                                    info!("new group_id: {}, line: {}", group_id, line!());
-                                    g.gid = group_id;
-                                    ARAMReadyGroups.insert(group_id, Rc::new(RefCell::new(g.clone())));
-                                    matchGroup.insert(group_id, Rc::new(RefCell::new(group_id.clone())));
-                                    g = Default::default();
-                                }
-                                // info!("{:?}", matchGroup);
-                            }
-                            //println!("Time 2: {:?}",Instant::now().duration_since(new_now1));
-                            if g.user_len < TEAM_SIZE {
-                                for r in g.rid {
-                                    let mut room = ARAMQueueRoom.get(&r);
-                                    if let Some(room) = room {
-                                        room.borrow_mut().ready = 0;
-                                        room.borrow_mut().gid = 0;
-                                    }
-                                }
-                                for r in id {
-                                    let mut room = NGQueueRoom.get(&r);
-                                    if let Some(room) = room {
-                                        room.borrow_mut().ready = 0;
-                                        room.borrow_mut().gid = 0;
-                                    }
-                                }
-                            }
-                        }
-                        if ARAMReadyGroups.len() > 0 {
-                            println!("ARAMReadyGroup!! {}, line: {}", ARAMReadyGroups.len(), line!());
-                            println!("ARAMReadyGroup!! {:?}, line: {}", ARAMReadyGroups, line!());
-                        }
-                        if ARAMReadyGroups.len() >= MATCH_SIZE {
-                            let mut prestart = false;
-                            let mut total_aram: i16 = 0;
-                            let mut rm_ids: Vec<u64> = vec![];
-                            let mut new_now2 = Instant::now();
-                            let mut isMatch = false;
-                            for (id, rg) in &ARAMReadyGroups {
-                                let mut fg: ReadyGameData = Default::default();
-                                total_aram = 0;
-                                if rg.borrow().game_status == 0 && fg.team_len < MATCH_SIZE {
-                                    if total_aram == 0 {
-                                        total_aram += rg.borrow().avg_aram as i16;
-                                        fg.group.push(rg.borrow().rid.clone());
-                                        fg.gid.push(*id);
-                                        fg.team_len += 1;
-                                        for (id2, rg2) in &ARAMReadyGroups {
-                                            let mut isInFG = false;
-                                            for gid in &fg.gid {
-                                                if gid == id2 {
-                                                    isInFG = true;
-                                                }
-                                            }
-                                            if fg.team_len < MATCH_SIZE && !isInFG {
-                                                let mut difference = 0;
-                                                if fg.team_len > 0 {
-                                                    difference = i64::abs((rg2.borrow().avg_aram as i16 - total_aram/fg.team_len as i16).into());
-                                                }
-                                                if difference <= ARAM_RANGE + SCORE_INTERVAL * rg2.borrow().queue_cnt {
-                                                    total_aram += rg2.borrow().avg_aram as i16;
-                                                    fg.group.push(rg2.borrow().rid.clone());
-                                                    fg.team_len += 1;
-                                                    fg.gid.push(*id2);
-                                                }
-                                                else {
-                                                    rg2.borrow_mut().queue_cnt += 1;
-                                                }
-                                            }
-                                            if fg.team_len == MATCH_SIZE {
-                                                sender.send(RoomEventData::UpdateGame(PreGameData{rid: fg.group.clone(), mode: "aram".to_string()}));
-                                                for id in fg.gid {
-                                                    rm_ids.push(id);
-                                                }
-                                                fg = Default::default();
-                                                isMatch = true;
-                                            }
-                                            if isMatch {
-                                                break;
-                                            }
-                                        }
-                                        if isMatch {
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                            for id in rm_ids {
-                                let rg = ARAMReadyGroups.remove(&id);
-                                if let Some(rg) = rg {
-                                    for rid in &rg.borrow().rid {
-                                        ARAMQueueRoom.remove(&rid);
-                                    }
-                                }
-                            }
-                            // println!("GroupedRoom : {:?}", ARAMGroupedQueueRoom);
-                            // println!("MatchedGroup : {:?}", ARAMMatchedGroups);
-                        }
-                    } else {
-                        for (k, v) in &mut ARAMQueueRoom {
-                            for uid in &v.borrow().user_ids {
-                                sender.try_send(RoomEventData::CancelQueue(CancelQueueData{action: "cancel_queue".to_string(), id: uid.to_string(), room: "".to_string(), mode: "aram".to_string()}));
-                                // sender.try_send(RoomEventData::Leave(LeaveData{id: uid.to_string(), room: "".to_string()}));
-                            }
+                    // backfill
+                    // every queued room waits one more tick, a room in several queues is counted once
+                    let mut waiting: BTreeMap<u64, Rc<RefCell<QueueRoomData>>> = BTreeMap::new();
+                    for queue in QueueRooms.values() {
+                        for (rid, r) in queue {
+                            waiting.insert(*rid, Rc::clone(r));
                         }
                     }
-                    // aram
-                    // rank
-                    if rkState == "open" {
-                        if RKQueueRoom.len() >= MATCH_SIZE {
-                            let mut g: ReadyGroupData = Default::default();
-                            let mut tq: Vec<Rc<RefCell<QueueRoomData>>> = vec![];
-                            let mut id: Vec<u64> = vec![];
-                            let mut new_now = Instant::now();
-                            tq = RKQueueRoom.iter().map(|x|Rc::clone(x.1)).collect();
-                            let mut new_now = Instant::now();
-                            tq.sort_by_key(|x| x.borrow().avg_rk);
-                            let mut new_now1 = Instant::now();
-                            for (k, v) in &RKQueueRoom {
-                                v.borrow_mut().queue_cnt += 1;
-                                let updateRoomQueueCntData = UpdateRoomQueueCntData {
-                                    rid: v.borrow().rid,
-                                };
-                                sender.try_send(RoomEventData::UpdateRoomQueueCnt(updateRoomQueueCntData));
-                            }
-                            for (k, v) in &RKQueueRoom {
-                                for (k2 , v2) in &RKQueueRoom {
-                                    let mut isMatch = false;
-                                    if let Some(gid) = matchGroup.get(&v2.borrow().gid.clone()) {
-                                        isMatch = true;
-                                    }
-                                    if !isMatch {
-                                        v2.borrow_mut().ready = 0;
-                                        v2.borrow_mut().gid = 0;
-                                    }
-                                }
-                                g = Default::default();
-                                canGroupRK(&mut g, v, &mut conn, group_id);
-                                for (k2, mut v2) in &RKQueueRoom {
-                                    canGroupRK(&mut g, v2, &mut conn, group_id);
-                                }
-                                if g.user_len == TEAM_SIZE {
-                                    println!("match team_size!, line: {}", line!());
-                                    group_id += 1;
-                                    info!("new group_id: {}, line: {}", group_id, line!());
-                                    g.gid = group_id;
-                                    RKReadyGroups.insert(group_id, Rc::new(RefCell::new(g.clone())));
-                                    matchGroup.insert(group_id, Rc::new(RefCell::new(group_id.clone())));
-                                    g = Default::default();
-                                }
-                                info!("{:?}", matchGroup);
-                            }
-                            //println!("Time 2: {:?}",Instant::now().duration_since(new_now1));
-                            if g.user_len < TEAM_SIZE {
-                                for r in g.rid {
-                                    let mut room = RKQueueRoom.get(&r);
-                                    if let Some(room) = room {
-                                        room.borrow_mut().ready = 0;
-                                        room.borrow_mut().gid = 0;
-                                    }
-                                }
-                                for r in id {
-                                    let mut room = RKQueueRoom.get(&r);
-                                    if let Some(room) = room {
-                                        room.borrow_mut().ready = 0;
-                                        room.borrow_mut().gid = 0;
-                                    }
-                                }
-                            }
-                        }
-                        if RKReadyGroups.len() > 0 {
-                            println!("RKReadyGroup!! {}, line: {}", RKReadyGroups.len(), line!());
-                            println!("RKReadyGroup!! {:?}, line: {}", RKReadyGroups, line!());
-                        }
-                        if RKReadyGroups.len() >= MATCH_SIZE {
-                            let mut prestart = false;
-                            let mut total_rk: i16 = 0;
-                            let mut rm_ids: Vec<u64> = vec![];
-                            let mut new_now2 = Instant::now();
-                            let mut isMatch = false;
-                            for (id, rg) in &RKReadyGroups {
-                                let mut fg: ReadyGameData = Default::default();
-                                total_rk = 0;
-                                if rg.borrow().game_status == 0 && fg.team_len < MATCH_SIZE {
-                                    if total_rk == 0 {
-                                        total_rk += rg.borrow().avg_rk as i16;
-                                        fg.group.push(rg.borrow().rid.clone());
-                                        fg.gid.push(*id);
-                                        fg.team_len += 1;
-                                        for (id2, rg2) in &RKReadyGroups {
-                                            let mut isInFG = false;
-                                            for gid in &fg.gid {
-                                                if gid == id2 {
-                                                    isInFG = true;
-                                                }
-                                            }
-                                            if fg.team_len < MATCH_SIZE && !isInFG {
-                                                let mut difference = 0;
-                                                if fg.team_len > 0 {
-                                                    difference = i64::abs((rg2.borrow().avg_rk as i16 - total_rk/fg.team_len as i16).into());
-                                                }
-                                                if difference <= RANK_RANGE + SCORE_INTERVAL * rg2.borrow().queue_cnt {
-                                                    total_rk += rg2.borrow().avg_rk as i16;
-                                                    fg.group.push(rg2.borrow().rid.clone());
-                                                    fg.team_len += 1;
-                                                    fg.gid.push(*id2);
-                                                }
-                                                else {
-                                                    rg2.borrow_mut().queue_cnt += 1;
-                                                }
-                                            }
-                                            if fg.team_len == MATCH_SIZE {
-                                                sender.send(RoomEventData::UpdateGame(PreGameData{rid: fg.group.clone(), mode: "rk".to_string()}));
-                                                for id in fg.gid {
-                                                    rm_ids.push(id);
-                                                }
-                                                fg = Default::default();
-                                                isMatch = true;
-                                            }
-                                            if isMatch {
-                                                break;
-                                            }
-                                        }
-                                        if isMatch {
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                            println!("del : {:?}", rm_ids);
-                            for id in rm_ids {
-                                let rg = RKReadyGroups.remove(&id);
-                                if let Some(rg) = rg {
-                                    for rid in &rg.borrow().rid {
-                                        RKQueueRoom.remove(&rid);
-                                    }
-                                }
-                            }
-                            // println!("GroupedRoom : {:?}", RKGroupedQueueRoom);
-                            // println!("MatchedGroup : {:?}", RKMatchedGroups);
-                        }
-                    } else {
-                        for (k, v) in &mut RKQueueRoom {
-                            for uid in &v.borrow().user_ids {
-                                sender.try_send(RoomEventData::CancelQueue(CancelQueueData{action: "cancel_queue".to_string(), id: uid.to_string(), room: "".to_string(), mode: "rk".to_string()}));
-                                sender.try_send(RoomEventData::Leave(LeaveData{id: uid.to_string(), room: "".to_string()}));
-                            }
-                        }
+                    for (rid, r) in &waiting {
+                        r.borrow_mut().queue_cnt += 1;
+                        sender.try_send(RoomEventData::UpdateRoomQueueCnt(UpdateRoomQueueCntData{rid: *rid}));
                     }
-                    // rank
-                    // AT
-                    if atState == "open" {
-                        new_now = Instant::now();
-                        if ATQueueRoom.len() >= MATCH_SIZE {
-                            let mut g: ReadyGroupData = Default::default();
-                            let mut tq: Vec<Rc<RefCell<QueueRoomData>>> = vec![];
-                            let mut id: Vec<u64> = vec![];
-                            let mut new_now = Instant::now();
-                            tq = ATQueueRoom.iter().map(|x|Rc::clone(x.1)).collect();
-                            //println!("Collect Time: {:?}",Instant::now().duration_since(new_now));
-                            //tq.sort_by_key(|x| x.borrow().avg_rk);
-                            let mut new_now = Instant::now();
-                            tq.sort_by_key(|x| x.borrow().avg_at);
-                            //println!("Sort Time: {:?}",Instant::now().duration_since(new_now));
-                            let mut new_now1 = Instant::now();
-                            for (k, v) in &ATQueueRoom {
-                                v.borrow_mut().queue_cnt += 1;
-                                let updateRoomQueueCntData = UpdateRoomQueueCntData {
-                                    rid: v.borrow().rid,
-                                };
-                                sender.try_send(RoomEventData::UpdateRoomQueueCnt(updateRoomQueueCntData));
-                            }
-                            for (k, v) in &ATQueueRoom {
-                                for (k2 ,v2) in &ATQueueRoom {
-                                    let mut isMatch = false;
-                                    if let Some(gid) = matchGroup.get(&v2.borrow().gid.clone()) {
-                                        isMatch = true;
-                                    }
-                                    if !isMatch {
-                                        v2.borrow_mut().ready = 0;
-                                        v2.borrow_mut().gid = 0;
-                                    }
-                                }
-                                g = Default::default();
-                                canGroupAT(&mut g, v, &mut conn, group_id);
-                                for (k2, mut v2) in &ATQueueRoom {
-                                    canGroupAT(&mut g, v2, &mut conn, group_id);
-                                }
-                                if g.user_len == TEAM_SIZE {
-                                    println!("match team_size!, line: {}", line!());
-                                    group_id += 1;
-                                    info!("new group_id: {}, line: {}", group_id, line!());
-                                    g.gid = group_id;
-                                    g.queue_cnt = 1;
-                                    ATReadyGroups.insert(group_id, Rc::new(RefCell::new(g.clone())));
-                                    matchGroup.insert(group_id, Rc::new(RefCell::new(group_id.clone())));
-                                    g = Default::default();
-                                }
-                            }
-                            //println!("Time 2: {:?}",Instant::now().duration_since(new_now1));
-                            if g.user_len < TEAM_SIZE {
-                                for r in g.rid {
-                                    let mut room = ATQueueRoom.get(&r);
-                                    if let Some(room) = room {
-                                        room.borrow_mut().ready = 0;
-                                        room.borrow_mut().gid = 0;
-                                    }
-                                }
-                                for r in id {
-                                    let mut room = ATQueueRoom.get(&r);
-                                    if let Some(room) = room {
-                                        room.borrow_mut().ready = 0;
-                                        room.borrow_mut().gid = 0;
+                    for mode in QUEUE_MODES.iter() {
+                        let cfg = mode_config(mode);
+                        let queue = QueueRooms.entry(mode.to_string()).or_insert(QueueIndex::new(queue_rating(cfg.flow)));
+                        let readyGroups = QueueReadyGroups.entry(mode.to_string()).or_insert(BTreeMap::new());
+                        // solo ng rooms play regular ng games
+                        let game_mode = if *mode == "sng" { "ng" } else { *mode };
+                        let state = match cfg.flow {
+                            "rk" | "cm" => rkState,
+                            "at" => atState,
+                            "aram" => aramState,
+                            _ => ngState,
+                        };
+                        if state != "open" {
+                            for (k, v) in queue.iter() {
+                                for uid in &v.borrow().user_ids {
+                                    sender.try_send(RoomEventData::CancelQueue(CancelQueueData{action: "cancel_queue".to_string(), id: uid.to_string(), room: "".to_string(), mode: game_mode.to_string()}));
+                                    // closing the flex ranked queues also takes the players out of their rooms
+                                    if *mode == "rk" || *mode == "at" {
+                                        sender.try_send(RoomEventData::Leave(LeaveData{id: uid.to_string(), room: "".to_string()}));
                                     }
                                 }
                             }
+                            continue;
                         }
-                        if ATReadyGroups.len() > 0 {
-                            println!("ATReadyGroup!! {}, line: {}", ATReadyGroups.len(), line!());
-                            println!("ATReadyGroup!! {:?}, line: {}", ATReadyGroups, line!());
-                        }
-                        if ATReadyGroups.len() >= MATCH_SIZE {
-                            let mut prestart = false;
-                            let mut total_at: i16 = 0;
-                            let mut rm_ids: Vec<u64> = vec![];
-                            let mut new_now2 = Instant::now();
-                            let mut isMatch = false;
-                            for (id, rg) in &ATReadyGroups {
-                                let mut fg: ReadyGameData = Default::default();
-                                total_at = 0;
-                                if rg.borrow().game_status == 0 && fg.team_len < MATCH_SIZE {
-                                    if total_at == 0 {
-                                        total_at += rg.borrow().avg_at as i16;
-                                        fg.group.push(rg.borrow().rid.clone());
-                                        fg.gid.push(*id);
-                                        fg.team_len += 1;
-                                        for (id2, rg2) in &ATReadyGroups {
-                                            let mut isInFG = false;
-                                            for gid in &fg.gid {
-                                                if gid == id2 {
-                                                    isInFG = true;
-                                                }
-                                            }
-                                            if fg.team_len < MATCH_SIZE && !isInFG {
-                                                let mut difference = 0;
-                                                if fg.team_len > 0 {
-                                                    difference = i64::abs((rg2.borrow().avg_at as i16 - total_at/fg.team_len as i16).into());
-                                                }
-                                                if difference <= RANK_RANGE + SCORE_INTERVAL * rg2.borrow().queue_cnt {
-                                                    total_at += rg2.borrow().avg_at as i16;
-                                                    fg.group.push(rg2.borrow().rid.clone());
-                                                    fg.team_len += 1;
-                                                    fg.gid.push(*id2);
-                                                }
-                                                else {
-                                                    rg2.borrow_mut().queue_cnt += 1;
-                                                }
-                                            }
-                                            if fg.team_len == MATCH_SIZE {
-                                                sender.send(RoomEventData::UpdateGame(PreGameData{rid: fg.group.clone(), mode: "at".to_string()}));
-                                                for id in fg.gid {
-                                                    rm_ids.push(id);
-                                                }
-                                                fg = Default::default();
-                                                isMatch = true;
-                                            }
-                                            if isMatch {
-                                                break;
-                                            }
-                                        }
-                                        if isMatch {
-                                            break;
-                                        }
+                        if queue.len() >= cfg.team_count {
+                            let order = priority_rooms(&queue.rooms);
+                            let groups = queue.form_groups(&order, cfg.range, SCORE_INTERVAL, || ReadyGroupData { mode: mode.to_string(), ..Default::default() }, |g, r, n| {
+                                canGroup(g, r, &cfg, &mut conn, group_id + n);
+                            }, |g| g.user_len == cfg.team_size);
+                            for mut g in groups {
+                                group_id += 1;
+                                info!("new group_id: {}, mode: {}, line: {}", group_id, mode, line!());
+                                g.gid = group_id;
+                                readyGroups.insert(group_id, Rc::new(RefCell::new(g)));
+                                matchGroup.insert(group_id, Rc::new(RefCell::new(group_id.clone())));
+                            }
+                        }
+                        if readyGroups.len() >= cfg.team_count {
+                            let mut rm_ids: Vec<u64> = vec![];
+                            let group_order = priority_groups(readyGroups);
+                            for (id, rg) in &group_order {
+                                if rg.borrow().game_status != 0 || rm_ids.contains(id) {
+                                    continue;
+                                }
+                                let mut fg: ReadyGameData = Default::default();
+                                let mut total: i16 = group_rating(&rg.borrow(), cfg.flow);
+                                fg.group.push(rg.borrow().rid.clone());
+                                fg.gid.push(*id);
+                                fg.team_len += 1;
+                                for (id2, rg2) in &group_order {
+                                    if fg.team_len >= cfg.team_count {
+                                        break;
+                                    }
+                                    if fg.gid.contains(id2) || rm_ids.contains(id2) {
+                                        continue;
+                                    }
+                                    let difference = i64::abs((group_rating(&rg2.borrow(), cfg.flow) - total/fg.team_len as i16).into());
+                                    if difference <= cfg.range + SCORE_INTERVAL * rg2.borrow().queue_cnt && low_priority_match(&rg.borrow(), &rg2.borrow()) {
+                                        total += group_rating(&rg2.borrow(), cfg.flow);
+                                        fg.group.push(rg2.borrow().rid.clone());
+                                        fg.team_len += 1;
+                                        fg.gid.push(*id2);
+                                    } else {
+                                        rg2.borrow_mut().queue_cnt += 1;
                                     }
                                 }
+                                if fg.team_len == cfg.team_count {
+                                    sender.send(RoomEventData::UpdateGame(PreGameData{rid: fg.group.clone(), mode: game_mode.to_string()}));
+                                    rm_ids.extend(fg.gid.iter().cloned());
+                                }
                             }
                             for id in rm_ids {
-                                let rg = ATReadyGroups.remove(&id);
-                                if let Some(rg) = rg {
+                                if let Some(rg) = readyGroups.remove(&id) {
                                     for rid in &rg.borrow().rid {
-                                        ATQueueRoom.remove(&rid);
+                                        queue.remove(rid);
+                                        popped.push(*rid);
                                     }
                                 }
                             }
                         }
-                    } else {
-                        for (k, v) in &mut ATQueueRoom {
-                            for uid in &v.borrow().user_ids {
-                                sender.try_send(RoomEventData::CancelQueue(CancelQueueData{action: "cancel_queue".to_string(), id: uid.to_string(), room: "".to_string(), mode: "at".to_string()}));
-                                sender.try_send(RoomEventData::Leave(LeaveData{id: uid.to_string(), room: "".to_string()}));
-                            }
+                    }
+                    // rooms queued for several modes leave the other queues in the same tick one of them pops
+                    for rid in &popped {
+                        for queue in QueueRooms.values_mut() {
+                            queue.remove(rid);
                         }
                     }
-                    // AT
                 }
                 recv(update5000ms) -> _ => {
-                    let mut ng_solo_cnt: i32 = 0;
-                    let mut ng_cnt: i32 = 0;
-                    let mut rk_cnt: i32 = 0;
-                    let mut at_cnt: i32 = 0;
-                    let mut aram_cnt: i32 = 0;
-                    for (k, v) in &mut SoloNGQueueRoom {
-                        ng_solo_cnt += v.borrow().user_len as i32;
-                    }
-                    for (k, v) in &mut NGQueueRoom {
-                        ng_cnt += v.borrow().user_len as i32;
-                    }
-                    for (k, v) in &RKQueueRoom {
-                        rk_cnt += v.borrow().user_len as i32;
-                    }
-                    for (k, v) in &ATQueueRoom {
-                        at_cnt += v.borrow().user_len as i32;
-                    }
-                    for (k, v) in &ARAMQueueRoom {
-                        aram_cnt += v.borrow().user_len as i32;
-                    }
-                    sender.try_send(RoomEventData::UpdateQueue(UpdateQueueData{ng_solo: ng_solo_cnt, ng: ng_cnt, rk: rk_cnt, at: at_cnt, aram: aram_cnt}));
+                    let count = |mode: &str| -> i32 {
+                        match QueueRooms.get(mode) {
+                            Some(queue) => queue.iter().map(|(_, v)| v.borrow().user_len as i32).sum(),
+                            None => 0,
+                        }
+                    };
+                    sender.try_send(RoomEventData::UpdateQueue(UpdateQueueData{ng_solo: count("sng"), ng: count("ng"), rk: count("rk"), at: count("at"), aram: count("aram")}));
                 }
                 recv(rx) -> d => {
                     let handle = || -> Result<(), Error> {
                         if let Ok(d) = d {
                             match d {
                                 QueueData::UpdateRoom(x) => {
-                                    if x.mode == "ng" {
-                                        NGQueueRoom.insert(x.rid.clone(), Rc::new(RefCell::new(x.clone())));
-                                    }else if x.mode == "sng" {
-                                        SoloNGQueueRoom.insert(x.rid.clone(), Rc::new(RefCell::new(x.clone())));
-                                    }else if x.mode == "rk" {
-                                        RKQueueRoom.insert(x.rid.clone(), Rc::new(RefCell::new(x.clone())));
-                                    }else if x.mode == "at" {
-                                        ATQueueRoom.insert(x.rid.clone(), Rc::new(RefCell::new(x.clone())));
-                                    }else if x.mode == "aram" {
-                                        ARAMQueueRoom.insert(x.rid.clone(), Rc::new(RefCell::new(x.clone())));
-                                    }
-                                }
-                                QueueData::RemoveRoom(x) => {
-                                    //sng
-                                    let mut r = SoloNGQueueRoom.get(&x.rid);
-                                    if let Some(r) = r {
-                                        let mut rg = SoloNGReadyGroups.get(&r.borrow().gid);
-                                        if let Some(rg) = rg {
-                                            for rid in &rg.borrow().rid {
-                                                if rid == &x.rid {
-                                                    continue;
-                                                }
-                                                let mut room = SoloNGQueueRoom.get(rid);
-                                                if let Some(room) = room {
-                                                    room.borrow_mut().gid = 0;
-                                                    room.borrow_mut().ready = 0;
-                                                }
-                                            }
-                                        }
-                                        SoloNGReadyGroups.remove(&r.borrow().gid);
-                                    }
-                                    SoloNGQueueRoom.remove(&x.rid);
-                                    //sng
-                                    // ng
-                                    let mut r = NGQueueRoom.get(&x.rid);
-                                    if let Some(r) = r {
-                                        let mut rg = NGReadyGroups.get(&r.borrow().gid);
-                                        if let Some(rg) = rg {
-                                            for rid in &rg.borrow().rid {
-                                                if rid == &x.rid {
-                                                    continue;
-                                                }
-                                                let mut room = NGQueueRoom.get(rid);
-                                                if let Some(room) = room {
-                                                    room.borrow_mut().gid = 0;
-                                                    room.borrow_mut().ready = 0;
-                                                }
-                                            }
-                                        }
-                                        NGReadyGroups.remove(&r.borrow().gid);
-                                    }
-                                    NGQueueRoom.remove(&x.rid);
-                                    // ng
-                                    // aram
-                                    let mut r = ARAMQueueRoom.get(&x.rid);
-                                    if let Some(r) = r {
-                                        let mut rg = ARAMReadyGroups.get(&r.borrow().gid);
-                                        if let Some(rg) = rg {
-                                            for rid in &rg.borrow().rid {
-                                                if rid == &x.rid {
-                                                    continue;
-                                                }
-                                                let mut room = ARAMQueueRoom.get(rid);
-                                                if let Some(room) = room {
-                                                    room.borrow_mut().gid = 0;
-                                                    room.borrow_mut().ready = 0;
-                                                }
-                                            }
-                                        }
-                                        ARAMReadyGroups.remove(&r.borrow().gid);
+                                    // one entry shared by every queue the room is in, so only one mode can group it at a time
+                                    let room = Rc::new(RefCell::new(x.clone()));
+                                    let mut modes = x.modes.clone();
+                                    if modes.is_empty() {
+                                        modes.push(x.mode.clone());
                                     }
-                                    ARAMQueueRoom.remove(&x.rid);
-                                    // aram
-                                    // rank
-                                    r = RKQueueRoom.get(&x.rid);
-                                    if let Some(r) = r {
-                                        let mut rg = RKReadyGroups.get(&r.borrow().gid);
-                                        if let Some(rg) = rg {
-                                            for rid in &rg.borrow().rid {
-                                                if rid == &x.rid {
-                                                    continue;
-                                                }
-                                                let mut room = RKQueueRoom.get(rid);
-                                                if let Some(room) = room {
-                                                    room.borrow_mut().gid = 0;
-                                                    room.borrow_mut().ready = 0;
-                                                }
-                                            }
+                                    for mode in &modes {
+                                        if let Some(queue) = QueueRooms.get_mut(mode) {
+                                            queue.insert(Rc::clone(&room));
                                         }
-                                        RKReadyGroups.remove(&r.borrow().gid);
                                     }
-                                    RKQueueRoom.remove(&x.rid);
-                                    // rank
-                                    // AT
-                                    r = ATQueueRoom.get(&x.rid);
-                                    if let Some(r) = r {
-                                        let mut rg = ATReadyGroups.get(&r.borrow().gid);
-                                        if let Some(rg) = rg {
-                                            for rid in &rg.borrow().rid {
-                                                if rid == &x.rid {
-                                                    continue;
-                                                }
-                                                let mut room = ATQueueRoom.get(rid);
-                                                if let Some(room) = room {
-                                                    room.borrow_mut().gid = 0;
-                                                    room.borrow_mut().ready = 0;
+                                }
+                                QueueData::RemoveRoom(x) => {
+                                    // the rest of the room's group goes back to waiting
+                                    for (mode, queue) in &mut QueueRooms {
+                                        if let Some(r) = queue.remove(&x.rid) {
+                                            if let Some(readyGroups) = QueueReadyGroups.get_mut(mode) {
+                                                if let Some(rg) = readyGroups.remove(&r.borrow().gid) {
+                                                    for rid in &rg.borrow().rid {
+                                                        if let Some(room) = queue.get(rid) {
+                                                            room.borrow_mut().gid = 0;
+                                                            room.borrow_mut().ready = 0;
+                                                        }
+                                                    }
                                                 }
                                             }
                                         }
-                                        ATReadyGroups.remove(&r.borrow().gid);
                                     }
-                                    ATQueueRoom.remove(&x.rid);
-                                    // AT
                                 },
                                 QueueData::Control(x) => {
                                     if (x.mode == "rk") {
@@ -2162,6 +2167,12 @@ pub fn HandleQueueRequest(
                                         }
                                     }
                                 }
+                                QueueData::Backfill(x) => {
+                                    BackfillRequests.push(x);
+                                },
+                                QueueData::CancelBackfill(x) => {
+                                    BackfillRequests.retain(|r| r.game != x.game);
+                                },
                                 QueueData::Continue(x) => {
 
                                 }
@@ -2187,6 +2198,8 @@ pub fn init(
     redis_client: redis::Client,
     QueueSender1: Option<Sender<QueueData>>,
     isBackup: bool,
+    party_rules: PartyRules,
+    draft_formats: DraftFormats,
 ) -> Result<(Sender<RoomEventData>, Sender<QueueData>), Error> {
     let (tx, rx): (Sender<RoomEventData>, Receiver<RoomEventData>) = bounded(10000);
     let mut tx1: Sender<QueueData>;
@@ -2218,23 +2231,28 @@ pub fn init(
         //let mut QueueRoom: BTreeMap<u64, Rc<RefCell<RoomData>>> = BTreeMap::new();
         let mut ReadyGroups: BTreeMap<u64, Rc<RefCell<FightGroup>>> = BTreeMap::new();
         let mut PreStartGroups: BTreeMap<u64, Rc<RefCell<FightGame>>> = BTreeMap::new();
-        let mut GameingGroups: BTreeMap<u64, Rc<RefCell<FightGame>>> = BTreeMap::new();
-        let mut NGGameingGroups: BTreeMap<u64, Rc<RefCell<NGGame>>> = BTreeMap::new();
-        let mut ARAMGameingGroups: BTreeMap<u64, Rc<RefCell<ARAMGame>>> = BTreeMap::new();
-        let mut RKGameingGroups: BTreeMap<u64, Rc<RefCell<RKGame>>> = BTreeMap::new();
-        let mut ATGameingGroups: BTreeMap<u64, Rc<RefCell<ATGame>>> = BTreeMap::new();
+        let mut ActiveGames: BTreeMap<u64, ActiveGame> = BTreeMap::new();
         let mut TotalUsers: BTreeMap<String, Rc<RefCell<User>>> = BTreeMap::new();
         let mut RestrictedUsers: BTreeMap<String, Rc<RefCell<RestrictedData>>> = BTreeMap::new();
         let mut JumpUsers: BTreeMap<String, Rc<RefCell<JumpCountData>>> = BTreeMap::new();
+        let mut AutoPickUsers: BTreeMap<String, Rc<RefCell<JumpCountData>>> = BTreeMap::new();
+        let mut LowPriorityUsers: BTreeMap<String, i16> = BTreeMap::new();
         let mut InGameUsers: BTreeMap<String, Rc<RefCell<User>>> = BTreeMap::new();
         let mut GameingRoom: BTreeMap<u64, Rc<RefCell<GameRoomData>>> = BTreeMap::new();
         let mut LossSend: Vec<MqttMsg> = vec![];
         let mut AbandonGames: BTreeMap<u64, bool> = BTreeMap::new();
         let mut HeroSwapping: BTreeMap<String, Rc<RefCell<HeroSwappingData>>> = BTreeMap::new();
+        let mut Spectators = SpectatorHub::default();
+        let mut Servers = ServerRegistry::default();
+        let mut TotalLobby: BTreeMap<u64, Rc<RefCell<LobbyData>>> = BTreeMap::new();
+        let mut CustomGames: BTreeMap<u64, u64> = BTreeMap::new();
+        let mut Tournaments: BTreeMap<u64, Rc<RefCell<Tournament>>> = BTreeMap::new();
+        let mut TournamentGames: BTreeMap<u64, u64> = BTreeMap::new();
+        let mut tournament_id: u64 = 0;
         let mut room_id: u64 = 1;
+        let mut lobby_id: u64 = 0;
         let mut group_id: u64 = 0;
         let mut game_id: u64 = 0;
-        let mut game_port: u16 = 7777;
         let mut ngState = "open";
         let mut bForceCloseNgState = false;
         let mut rkState = "close";
@@ -2277,8 +2295,11 @@ pub fn init(
                 ng: mysql::from_value_opt(a.get("ng").ok_or(Error::from(core::fmt::Error))?)?,
                 rk: mysql::from_value_opt(a.get("rk").ok_or(Error::from(core::fmt::Error))?)?,
                 at: mysql::from_value_opt(a.get("at").ok_or(Error::from(core::fmt::Error))?)?,
+                rk_solo: mysql::from_value_opt(a.get("rk_solo").ok_or(Error::from(core::fmt::Error))?)?,
+                at_solo: mysql::from_value_opt(a.get("at_solo").ok_or(Error::from(core::fmt::Error))?)?,
                 aram: mysql::from_value_opt(a.get("aram").ok_or(Error::from(core::fmt::Error))?)?,
                 raindrop: mysql::from_value_opt(a.get("raindrop").ok_or(Error::from(core::fmt::Error))?)?,
+                reroll: mysql::from_value_opt(a.get("reroll").ok_or(Error::from(core::fmt::Error))?)?,
                 first_win: mysql::from_value_opt(a.get("first_win").ok_or(Error::from(core::fmt::Error))?)?,
                 email: mysql::from_value_opt(a.get("email").ok_or(Error::from(core::fmt::Error))?)?,
                 phone: mysql::from_value_opt(a.get("phone").ok_or(Error::from(core::fmt::Error))?)?,
@@ -2303,6 +2324,15 @@ pub fn init(
             println!("{:?}, line: {}", hero, line!());
             TotalHeros.insert(hero.name.clone(), Rc::new(RefCell::new(hero.clone())));
         }
+        let mut FreeHeros: Vec<String> = load_free_heros(&mut conn)?.1;
+        let sql5 = format!("select * from LowPriority;");
+        let qres4: mysql::QueryResult = conn.query(sql5.clone())?;
+        for row in qres4 {
+            let a = row?.clone();
+            let id: String = mysql::from_value_opt(a.get("id").ok_or(Error::from(core::fmt::Error))?)?;
+            let games: i16 = mysql::from_value_opt(a.get("games").ok_or(Error::from(core::fmt::Error))?)?;
+            LowPriorityUsers.insert(id, games);
+        }
         loop {
             select! {
                 recv(update200ms) -> _ => {
@@ -2317,8 +2347,12 @@ pub fn init(
                                         "game":"{}", "players":{:?}}}"#, r, group.borrow().game_id, &group.borrow().user_names)});
                                 }
                                 group.borrow_mut().next_status();
-                                if group.borrow().mode == "ng" {
+                                let cfg = mode_config(&group.borrow().mode);
+                                let flow = cfg.flow;
+                                if flow == "ng" {
                                     let ngGame = NGGame {
+                                        mode: group.borrow().mode.clone(),
+                                        seed: rand::random(),
                                         teams: group.borrow().teams.clone(),
                                         room_names: group.borrow().room_names.clone(),
                                         user_names: group.borrow().user_names.clone(),
@@ -2326,11 +2360,13 @@ pub fn init(
                                         user_count: group.borrow().user_count,
                                         ..Default::default()
                                     };
-                                    NGGameingGroups.insert(group.borrow().game_id,  Rc::new(RefCell::new(ngGame.clone())));
+                                    info!("game_id: {}, seed: {}, line: {}", ngGame.game_id, ngGame.seed, line!());
+                                    ActiveGames.insert(group.borrow().game_id, ActiveGame::NG(Rc::new(RefCell::new(ngGame.clone()))));
                                     isUpdateCount = true;
                                 }
-                                if group.borrow().mode == "aram" {
+                                if flow == "aram" {
                                     let aramGame = ARAMGame {
+                                        seed: rand::random(),
                                         teams: group.borrow().teams.clone(),
                                         room_names: group.borrow().room_names.clone(),
                                         user_names: group.borrow().user_names.clone(),
@@ -2339,35 +2375,55 @@ pub fn init(
                                         TotalHeros: TotalHeros.clone(),
                                         ..Default::default()
                                     };
-                                    ARAMGameingGroups.insert(group.borrow().game_id,  Rc::new(RefCell::new(aramGame.clone())));
+                                    info!("game_id: {}, seed: {}, line: {}", aramGame.game_id, aramGame.seed, line!());
+                                    ActiveGames.insert(group.borrow().game_id, ActiveGame::ARAM(Rc::new(RefCell::new(aramGame.clone()))));
                                     isUpdateCount = true;
                                 }
-                                if group.borrow().mode == "rk" {
-                                    let rkGame = RKGame {
-                                        teams: group.borrow().teams.clone(),
-                                        room_names: group.borrow().room_names.clone(),
-                                        user_names: group.borrow().user_names.clone(),
-                                        game_id: group.borrow().game_id,
-                                        user_count: group.borrow().user_count,
-                                        ..Default::default()
-                                    };
-                                    RKGameingGroups.insert(group.borrow().game_id,  Rc::new(RefCell::new(rkGame.clone())));
-                                    isUpdateCount = true;
+                                if flow == "rk" || flow == "at" {
+                                    match draft_phases(&draft_formats, flow, cfg.team_size as usize, cfg.team_count) {
+                                        Ok(phases) => {
+                                            let draftGame = DraftGame {
+                                                mode: group.borrow().mode.clone(),
+                                                phases: phases,
+                                                seed: rand::random(),
+                                                teams: group.borrow().teams.clone(),
+                                                room_names: group.borrow().room_names.clone(),
+                                                user_names: group.borrow().user_names.clone(),
+                                                game_id: group.borrow().game_id,
+                                                user_count: group.borrow().user_count,
+                                                ..Default::default()
+                                            };
+                                            info!("game_id: {}, seed: {}, line: {}", draftGame.game_id, draftGame.seed, line!());
+                                            ActiveGames.insert(group.borrow().game_id, ActiveGame::Draft(Rc::new(RefCell::new(draftGame.clone()))));
+                                            isUpdateCount = true;
+                                        }
+                                        Err(e) => error!("game_id: {}, flow: {}, {}, line: {}", group.borrow().game_id, flow, e, line!()),
+                                    }
                                 }
-                                if group.borrow().mode == "at" {
-                                    let atGame = ATGame {
-                                        teams: group.borrow().teams.clone(),
-                                        room_names: group.borrow().room_names.clone(),
-                                        user_names: group.borrow().user_names.clone(),
-                                        game_id: group.borrow().game_id,
-                                        user_count: group.borrow().user_count,
-                                        ..Default::default()
-                                    };
-                                    ATGameingGroups.insert(group.borrow().game_id,  Rc::new(RefCell::new(atGame.clone())));
-                                    isUpdateCount = true;
+                                if flow == "cm" {
+                                    match draft_phases(&draft_formats, flow, cfg.team_size as usize, cfg.team_count) {
+                                        Ok(phases) => {
+                                            let cmGame = CMGame {
+                                                mode: group.borrow().mode.clone(),
+                                                phases: phases,
+                                                seed: rand::random(),
+                                                teams: group.borrow().teams.clone(),
+                                                room_names: group.borrow().room_names.clone(),
+                                                user_names: group.borrow().user_names.clone(),
+                                                game_id: group.borrow().game_id,
+                                                user_count: group.borrow().user_count,
+                                                TotalHeros: TotalHeros.clone(),
+                                                free_heros: FreeHeros.clone(),
+                                                ..Default::default()
+                                            };
+                                            info!("game_id: {}, seed: {}, line: {}", cmGame.game_id, cmGame.seed, line!());
+                                            ActiveGames.insert(group.borrow().game_id, ActiveGame::CM(Rc::new(RefCell::new(cmGame.clone()))));
+                                            isUpdateCount = true;
+                                        }
+                                        Err(e) => error!("game_id: {}, flow: {}, {}, line: {}", group.borrow().game_id, flow, e, line!()),
+                                    }
                                 }
                                 // group.borrow_mut().ban_time = BAN_HERO_TIME;
-                                // GameingGroups.insert(game_id, group.clone());
                                 rm_ids.push(id.clone());
                             },
                             PrestartStatus::Cancel => {
@@ -2458,10 +2514,15 @@ pub fn init(
                             if isNewDay {
                                 isNewDay = false;
                                 let sql = format!("update user set first_win=false;");
-                                let qres = conn.query(sql.clone())?;
+                                conn.query(sql.clone())?;
                                 for (_, u) in &TotalUsers {
                                     u.borrow_mut().first_win = false;
                                 }
+                                // the day rolls over at 16:00, sunday's rollover starts the new week
+                                if now.weekday() == Weekday::Sun {
+                                    FreeHeros = generate_free_rotation(&mut conn, &TotalHeros)?;
+                                    tx2.try_send(RoomEventData::Free());
+                                }
                             }
                         }
                     }
@@ -2534,17 +2595,64 @@ pub fn init(
                     for rest in rest_list {
                         JumpUsers.remove(&rest);
                     }
-                    for (game_id, fg) in &mut NGGameingGroups {
-                        process_ng(msgtx.clone(), tx2.clone(), sender.clone(), TotalUsers.clone(), game_id, fg);
+                    let mut rest_list: Vec<String> = Vec::new();
+                    for (id, autoPickData) in &mut AutoPickUsers {
+                        autoPickData.borrow_mut().time -= 1;
+                        if autoPickData.borrow().time <= 0 {
+                            rest_list.push(id.clone());
+                        }
+                    }
+                    for rest in rest_list {
+                        AutoPickUsers.remove(&rest);
+                    }
+                    // game events pass through the spectator hub on their way out
+                    let (gametx, gamerx): (Sender<MqttMsg>, Receiver<MqttMsg>) = bounded(10000);
+                    for (game_id, game) in &mut ActiveGames {
+                        process_game(gametx.clone(), tx2.clone(), sender.clone(), TotalUsers.clone(), game_id, game);
+                    }
+                    let now = start.elapsed().as_secs();
+                    for m in gamerx.try_iter() {
+                        Spectators.offer(&m, now);
+                        msgtx.try_send(m)?;
+                    }
+                    let closed: Vec<u64> = Spectators.games.keys().filter(|g| !ActiveGames.contains_key(g)).cloned().collect();
+                    for game in closed {
+                        for m in Spectators.close(game) {
+                            msgtx.try_send(m)?;
+                        }
+                    }
+                    for m in Spectators.release(now) {
+                        msgtx.try_send(m)?;
                     }
-                    for (game_id, fg) in &mut ARAMGameingGroups {
-                        process_aram(msgtx.clone(), tx2.clone(), sender.clone(), TotalUsers.clone(), game_id, fg);
+                    for game in Servers.games() {
+                        if !ActiveGames.contains_key(&game) {
+                            Servers.release(game);
+                            let _: () = redis_conn.del(format!("server{}", game))?;
+                        }
                     }
-                    for (game_id, fg) in &mut RKGameingGroups {
-                        process_rk(msgtx.clone(), tx2.clone(), sender.clone(), TotalUsers.clone(), game_id, fg);
+                    for game in Servers.pending.clone() {
+                        if let Some(server) = Servers.allocate(game) {
+                            send_allocation(&msgtx, game, &server, &ActiveGames, redis_conn)?;
+                        }
                     }
-                    for (game_id, fg) in &mut ATGameingGroups {
-                        process_at(msgtx.clone(), tx2.clone(), sender.clone(), TotalUsers.clone(), game_id, fg);
+                    // games still in ready to start move to another server, running ones are closed with it
+                    for dead in Servers.expire(now) {
+                        warn!("game server dead: {}, games: {:?}, line: {}", dead.id, dead.games, line!());
+                        for game in dead.games {
+                            let running = match ActiveGames.get(&game) {
+                                Some(g) => g.state().borrow().check_status() == PhaseKind::Gaming,
+                                None => continue,
+                            };
+                            if !running {
+                                if let Some(server) = Servers.allocate(game) {
+                                    send_allocation(&msgtx, game, &server, &ActiveGames, redis_conn)?;
+                                }
+                                continue;
+                            }
+                            close_lost_game(game, &ActiveGames, &mut InGameUsers, &mut GameingRoom, &mut conn, redis_conn, &tx2)?;
+                            msgtx.try_send(MqttMsg{topic:format!("game/{}/res/game_status", game),
+                                msg: format!(r#"{{"status":"server_lost", "game": {}}}"#, game)})?;
+                        }
                     }
                     let mut rm_swapping_list: Vec<String> = Vec::new();
                     for (user_id, heroSwappingData) in &mut HeroSwapping {
@@ -2562,6 +2670,12 @@ pub fn init(
                 }
 
                 recv(update5000ms) -> _ => {
+                    // bracket matches held back by busy or offline players are retried
+                    for t in Tournaments.values() {
+                        if t.borrow().status == "running" {
+                            start_tournament_matches(t, &mut TournamentGames, &TotalUsers, &InGameUsers, &mut TotalRoom, &mut ReadyGroups, &mut PreStartGroups, &mut room_id, &mut group_id, &mut game_id, &msgtx, &QueueSender)?;
+                        }
+                    }
                     // check isInGame
                     let mut inGameRm_list: Vec<String> = Vec::new();
                     let mut del_list: Vec<u64> = Vec::new();
@@ -2569,76 +2683,53 @@ pub fn init(
                     let qres = conn.query(sql.clone())?;
                     for row in qres {
                         let ea = row?.clone();
+                        let mut steam_ids: Vec<String> = vec![];
+                        let mut heros: Vec<String> = vec![];
+                        for i in 1..11 {
+                            let steam_id: Option<String> = match ea.get(format!("steam_id{}", i).as_str()) {
+                                Some(v) => mysql::from_value_opt(v)?,
+                                None => None,
+                            };
+                            let hero: Option<String> = match ea.get(format!("hero{}", i).as_str()) {
+                                Some(v) => mysql::from_value_opt(v)?,
+                                None => None,
+                            };
+                            if let Some(steam_id) = steam_id {
+                                if steam_id != "" {
+                                    steam_ids.push(steam_id);
+                                    heros.push(hero.unwrap_or_default());
+                                }
+                            }
+                        }
                         let gamingData = GamingData{
                             game: mysql::from_value_opt(ea.get("game").ok_or(Error::from(core::fmt::Error))?)?,
                             mode: mysql::from_value_opt(ea.get("mode").ok_or(Error::from(core::fmt::Error))?)?,
-                            steam_id1: mysql::from_value_opt(ea.get("steam_id1").ok_or(Error::from(core::fmt::Error))?)?,
-                            steam_id2: mysql::from_value_opt(ea.get("steam_id2").ok_or(Error::from(core::fmt::Error))?)?,
-                            steam_id3: mysql::from_value_opt(ea.get("steam_id3").ok_or(Error::from(core::fmt::Error))?)?,
-                            steam_id4: mysql::from_value_opt(ea.get("steam_id4").ok_or(Error::from(core::fmt::Error))?)?,
-                            steam_id5: mysql::from_value_opt(ea.get("steam_id5").ok_or(Error::from(core::fmt::Error))?)?,
-                            steam_id6: mysql::from_value_opt(ea.get("steam_id6").ok_or(Error::from(core::fmt::Error))?)?,
-                            steam_id7: mysql::from_value_opt(ea.get("steam_id7").ok_or(Error::from(core::fmt::Error))?)?,
-                            steam_id8: mysql::from_value_opt(ea.get("steam_id8").ok_or(Error::from(core::fmt::Error))?)?,
-                            steam_id9: mysql::from_value_opt(ea.get("steam_id9").ok_or(Error::from(core::fmt::Error))?)?,
-                            steam_id10: mysql::from_value_opt(ea.get("steam_id10").ok_or(Error::from(core::fmt::Error))?)?,
-                            hero1: mysql::from_value_opt(ea.get("hero1").ok_or(Error::from(core::fmt::Error))?)?,
-                            hero2: mysql::from_value_opt(ea.get("hero2").ok_or(Error::from(core::fmt::Error))?)?,
-                            hero3: mysql::from_value_opt(ea.get("hero3").ok_or(Error::from(core::fmt::Error))?)?,
-                            hero4: mysql::from_value_opt(ea.get("hero4").ok_or(Error::from(core::fmt::Error))?)?,
-                            hero5: mysql::from_value_opt(ea.get("hero5").ok_or(Error::from(core::fmt::Error))?)?,
-                            hero6: mysql::from_value_opt(ea.get("hero6").ok_or(Error::from(core::fmt::Error))?)?,
-                            hero7: mysql::from_value_opt(ea.get("hero7").ok_or(Error::from(core::fmt::Error))?)?,
-                            hero8: mysql::from_value_opt(ea.get("hero8").ok_or(Error::from(core::fmt::Error))?)?,
-                            hero9: mysql::from_value_opt(ea.get("hero9").ok_or(Error::from(core::fmt::Error))?)?,
-                            hero10: mysql::from_value_opt(ea.get("hero10").ok_or(Error::from(core::fmt::Error))?)?,
+                            steam_ids: steam_ids,
+                            heros: heros,
                             status: mysql::from_value_opt(ea.get("status").ok_or(Error::from(core::fmt::Error))?)?,
                             win_team: mysql::from_value_opt(ea.get("win_team").ok_or(Error::from(core::fmt::Error))?)?,
                         };
                         AbandonGames.insert(gamingData.game, true);
+                        let team_size = mode_config(&gamingData.mode).team_size as usize;
                         let mut gameOverData = GameOverData{
                             game: gamingData.game,
-                            mode: gamingData.mode,
+                            mode: gamingData.mode.clone(),
                             win: Vec::new(),
                             lose: Vec::new(),
                             time: 0,
                         };
-                        if gamingData.win_team == 1 {
-                            gameOverData.win.push(gamingData.steam_id1.clone());
-                            gameOverData.win.push(gamingData.steam_id2.clone());
-                            gameOverData.win.push(gamingData.steam_id3.clone());
-                            gameOverData.win.push(gamingData.steam_id4.clone());
-                            gameOverData.win.push(gamingData.steam_id5.clone());
-                            gameOverData.lose.push(gamingData.steam_id6.clone());
-                            gameOverData.lose.push(gamingData.steam_id7.clone());
-                            gameOverData.lose.push(gamingData.steam_id8.clone());
-                            gameOverData.lose.push(gamingData.steam_id9.clone());
-                            gameOverData.lose.push(gamingData.steam_id10.clone());
-                        }
-                        if gamingData.win_team == 2 {
-                            gameOverData.lose.push(gamingData.steam_id1.clone());
-                            gameOverData.lose.push(gamingData.steam_id2.clone());
-                            gameOverData.lose.push(gamingData.steam_id3.clone());
-                            gameOverData.lose.push(gamingData.steam_id4.clone());
-                            gameOverData.lose.push(gamingData.steam_id5.clone());
-                            gameOverData.win.push(gamingData.steam_id6.clone());
-                            gameOverData.win.push(gamingData.steam_id7.clone());
-                            gameOverData.win.push(gamingData.steam_id8.clone());
-                            gameOverData.win.push(gamingData.steam_id9.clone());
-                            gameOverData.win.push(gamingData.steam_id10.clone());
+                        if gamingData.win_team > 0 {
+                            for (i, id) in gamingData.steam_ids.iter().enumerate() {
+                                if i / team_size == (gamingData.win_team - 1) as usize {
+                                    gameOverData.win.push(id.clone());
+                                } else {
+                                    gameOverData.lose.push(id.clone());
+                                }
+                            }
                         }
                         del_list.push(gameOverData.game);
-                        if let Some(fg) = NGGameingGroups.get(&gameOverData.game) {
-                            gameOverData.time = fg.borrow().time;
-                        }
-                        if let Some(fg) = ARAMGameingGroups.get(&gameOverData.game) {
-                            gameOverData.time = fg.borrow().time;
-                        }
-                        if let Some(fg) = RKGameingGroups.get(&gameOverData.game) {
-                            gameOverData.time = fg.borrow().time;
-                        }
-                        if let Some(fg) = ATGameingGroups.get(&gameOverData.game) {
-                            gameOverData.time = fg.borrow().time;
+                        if let Some(game) = ActiveGames.get(&gameOverData.game) {
+                            gameOverData.time = game.state().borrow().time();
                         }
                         tx2.try_send(RoomEventData::GameOver(gameOverData));
                     }
@@ -2648,41 +2739,8 @@ pub fn init(
                             game
                         );
                         let qres2 = conn.query(sql2.clone())?;
-                        if let Some(fg) = NGGameingGroups.get(&game) {
-                            for uid in &fg.borrow().user_names {
-                                if let Some(u) = TotalUsers.get(uid) {
-                                    u.borrow_mut().isLocked = false;
-                                    u.borrow_mut().hero = "".to_string();
-                                    u.borrow_mut().gid = 0;
-                                }
-                            }
-                        }
-                        NGGameingGroups.remove(&game);
-
-                        if let Some(fg) = ARAMGameingGroups.get(&game) {
-                            for uid in &fg.borrow().user_names {
-                                if let Some(u) = TotalUsers.get(uid) {
-                                    u.borrow_mut().isLocked = false;
-                                    u.borrow_mut().hero = "".to_string();
-                                    u.borrow_mut().gid = 0;
-                                }
-                            }
-                        }
-                        ARAMGameingGroups.remove(&game);
-
-                        if let Some(fg) = RKGameingGroups.get(&game) {
-                            for uid in &fg.borrow().user_names {
-                                if let Some(u) = TotalUsers.get(uid) {
-                                    u.borrow_mut().isLocked = false;
-                                    u.borrow_mut().hero = "".to_string();
-                                    u.borrow_mut().gid = 0;
-                                }
-                            }
-                        }
-                        RKGameingGroups.remove(&game);
-
-                        if let Some(fg) = ATGameingGroups.get(&game) {
-                            for uid in &fg.borrow().user_names {
+                        if let Some(game) = ActiveGames.remove(&game) {
+                            for uid in game.state().borrow().user_names() {
                                 if let Some(u) = TotalUsers.get(uid) {
                                     u.borrow_mut().isLocked = false;
                                     u.borrow_mut().hero = "".to_string();
@@ -2690,7 +2748,6 @@ pub fn init(
                                 }
                             }
                         }
-                        ATGameingGroups.remove(&game);
                     }
                     for rm in inGameRm_list {
                         InGameUsers.remove(&rm);
@@ -2705,53 +2762,29 @@ pub fn init(
                     let mut rk_cnt = 0;
                     let mut at_cnt = 0;
                     let mut aram_cnt = 0;
-                    for row in qres2 {
+                    for row in qres2.take(1) {
                         let a = row?.clone();
                         online_cnt = mysql::from_value_opt(a.get("count(*)").ok_or(Error::from(core::fmt::Error))?)?;
-                        break;
                     }
                     let mut timeout_list: Vec<u64> = vec![];
-                    for (game_id, fg) in &mut NGGameingGroups {
-                        fg.borrow_mut().time += 5;
-                        if fg.borrow_mut().time >= 7200 {
+                    for (game_id, game) in &mut ActiveGames {
+                        let fg = game.state();
+                        fg.borrow_mut().add_time(5);
+                        if fg.borrow().time() >= 7200 {
                             let delSql = format!(r#"delete from Gaming where game='{}';"#, game_id);
                             conn.query(delSql.clone())?;
                             timeout_list.push(*game_id);
                         }
-                        ng_cnt += 1;
-                    }
-                    for (game_id, fg) in &mut RKGameingGroups {
-                        fg.borrow_mut().time += 5;
-                        if fg.borrow_mut().time >= 7200 {
-                            let delSql = format!(r#"delete from Gaming where game='{}';"#, game_id);
-                            conn.query(delSql.clone())?;
-                            timeout_list.push(*game_id);
-                        }
-                        rk_cnt += 1;
-                    }
-                    for (game_id, fg) in &mut ATGameingGroups {
-                        fg.borrow_mut().time += 5;
-                        if fg.borrow_mut().time >= 7200 {
-                            let delSql = format!(r#"delete from Gaming where game='{}';"#, game_id);
-                            conn.query(delSql.clone())?;
-                            timeout_list.push(*game_id);
-                        }
-                        at_cnt += 1;
-                    }
-                    for (game_id, fg) in &mut ARAMGameingGroups {
-                        fg.borrow_mut().time += 5;
-                        if fg.borrow_mut().time >= 7200 {
-                            let delSql = format!(r#"delete from Gaming where game='{}';"#, game_id);
-                            conn.query(delSql.clone())?;
-                            timeout_list.push(*game_id);
+                        let mode = fg.borrow().mode();
+                        match mode_config(&mode).flow {
+                            "rk" | "cm" => rk_cnt += 1,
+                            "at" => at_cnt += 1,
+                            "aram" => aram_cnt += 1,
+                            _ => ng_cnt += 1,
                         }
-                        aram_cnt += 1;
                     }
                     for rm in timeout_list {
-                        NGGameingGroups.remove(&rm);
-                        RKGameingGroups.remove(&rm);
-                        ATGameingGroups.remove(&rm);
-                        ARAMGameingGroups.remove(&rm);
+                        ActiveGames.remove(&rm);
                     }
                     if isUpdateCount || online_cnt != current_online_cnt || current_ng_game_cnt != ng_cnt ||
                     current_rk_game_cnt != rk_cnt ||
@@ -2805,22 +2838,41 @@ pub fn init(
                                     //info!("Status TotalUsers {:#?}", TotalUsers);
                                 },
                                 RoomEventData::Reconnect(x) => {
-                                    // let u = get_user(&x.id, &TotalUsers);
-                                    // if let Some(u) = u {
-                                    //     let g = GameingGroups.get(&u.borrow().game_id);
-                                    //     if let Some(g) = g {
-                                    //         mqttmsg = MqttMsg{topic:format!("member/{}/res/reconnect", x.id),
-                                    //             msg: format!(r#"{{"server":"172.104.78.55:{}"}}"#, g.borrow().game_port)};
-                                    //         //msgtx.try_send(MqttMsg{topic:format!("member/{}/res/reconnect", x.id),
-                                    //         //    msg: format!(r#"{{"server":"172.104.78.55:{}"}}"#, g.borrow().game_port)})?;
-                                    //     }
-                                    // }
+                                    mqttmsg = MqttMsg{topic:format!("member/{}/res/reconnect", x.id),
+                                        msg: format!(r#"{{"msg":"no game"}}"#)};
+                                    if let Some(u) = TotalUsers.get(&x.id) {
+                                        // the active games know the player's seat, redis still has games started before a restart
+                                        let inGame: std::result::Result<u64, redis::RedisError> = redis_conn.get(format!("g{}", x.id));
+                                        let gid = reconnect_game(&x.id, &ActiveGames, &TotalUsers, &GameingRoom, inGame.unwrap_or(0));
+                                        let mut server = Servers.server_of(gid).map(|s| s.address.clone()).unwrap_or_default();
+                                        if server == "" && gid > 0 {
+                                            let address: std::result::Result<String, redis::RedisError> = redis_conn.get(format!("server{}", gid));
+                                            server = address.unwrap_or_default();
+                                        }
+                                        if let Some(game) = ActiveGames.get(&gid) {
+                                            let fg = game.state();
+                                            u.borrow_mut().game_id = gid;
+                                            let phase = fg.borrow().check_status();
+                                            let team = fg.borrow().team_of(&x.id).unwrap_or(0);
+                                            if phase == PhaseKind::Ban || phase == PhaseKind::Pick {
+                                                let snapshot = draft_snapshot(&*fg.borrow(), &TotalUsers);
+                                                msgtx.try_send(MqttMsg{topic:format!("member/{}/res/draft_state", x.id),
+                                                    msg: serde_json::to_string(&snapshot)?})?;
+                                            }
+                                            mqttmsg = MqttMsg{topic:format!("member/{}/res/reconnect", x.id),
+                                                msg: format!(r#"{{"msg":"ok", "game":{}, "server":"{}", "team":{}, "hero":"{}", "phase":"{}"}}"#,
+                                                    gid, server, team, u.borrow().hero, phase.name())};
+                                        } else if gid > 0 {
+                                            mqttmsg = MqttMsg{topic:format!("member/{}/res/reconnect", x.id),
+                                                msg: format!(r#"{{"msg":"ok", "game":{}, "server":"{}", "hero":"{}", "phase":"gaming"}}"#, gid, server, u.borrow().hero)};
+                                        }
+                                    }
                                 },
                                 RoomEventData::GameClose(x) => {
                                     //let p = PreStartGroups.remove(&x.game);
-                                    let g = GameingGroups.remove(&x.game);
+                                    let g = ActiveGames.remove(&x.game);
                                     if let Some(g) = g {
-                                        for u in &g.borrow().user_names {
+                                        for u in g.state().borrow().user_names() {
                                             let u = get_user(&u, &TotalUsers);
                                             match u {
                                                 Some(u) => {
@@ -2845,6 +2897,8 @@ pub fn init(
                                                     u.borrow_mut().rid = 0;
                                                     u.borrow_mut().gid = 0;
                                                     u.borrow_mut().game_id = 0;
+                                                    u.borrow_mut().isLocked = false;
+                                                    u.borrow_mut().hero = "".to_string();
                                                 },
                                                 None => {
                                                     //info!("remove fail ");
@@ -2854,16 +2908,44 @@ pub fn init(
                                         //info!("GameClose {}", x.game);
                                         //info!("TotalUsers {:#?}", TotalUsers);
                                     }
-                                    else {
-                                        //info!("GameingGroups {:#?}", GameingGroups);
-                                    }
                                 },
                                 RoomEventData::GameOver(x) => {
-                                    let win = get_users(&x.win, &TotalUsers)?;
-                                    let lose = get_users(&x.lose, &TotalUsers)?;
-                                    settlement_score(&win, &lose, &msgtx, &sender, &mut conn, x.mode, x.time);
-                                    if let Some(fg) = GameingGroups.get(&x.game) {
-                                        fg.borrow_mut().next_status();
+                                    let mut tournament = false;
+                                    if let Some(tid) = TournamentGames.remove(&x.game) {
+                                        tournament = true;
+                                        if let Some(t) = Tournaments.get(&tid) {
+                                            let reported = t.borrow_mut().report(x.game, &x.win);
+                                            if reported {
+                                                start_tournament_matches(t, &mut TournamentGames, &TotalUsers, &InGameUsers, &mut TotalRoom, &mut ReadyGroups, &mut PreStartGroups, &mut room_id, &mut group_id, &mut game_id, &msgtx, &QueueSender)?;
+                                                t.borrow().publish_standings(&msgtx, format!("tournament/{}/res/standings", tid))?;
+                                            }
+                                        }
+                                    }
+                                    if let Some(lid) = CustomGames.remove(&x.game) {
+                                        let sql = format!("insert into CustomGame (game, mode, win, lose) values ({}, '{}', '{}', '{}');", x.game, x.mode, x.win.join(","), x.lose.join(","));
+                                        conn.query(sql.clone())?;
+                                        if let Some(l) = TotalLobby.get(&lid) {
+                                            l.borrow_mut().game_id = 0;
+                                        }
+                                    } else if !tournament {
+                                        let win = get_users(&x.win, &TotalUsers)?;
+                                        let lose = get_users(&x.lose, &TotalUsers)?;
+                                        settlement_score(&win, &lose, &msgtx, &sender, &mut conn, x.mode, x.time);
+                                        for id in x.win.iter().chain(x.lose.iter()) {
+                                            let mut remain = -1;
+                                            if let Some(games) = LowPriorityUsers.get_mut(id) {
+                                                *games -= 1;
+                                                remain = *games;
+                                            }
+                                            if remain == 0 {
+                                                LowPriorityUsers.remove(id);
+                                                let sql = format!("delete from LowPriority where id='{}';", id);
+                                                conn.query(sql.clone())?;
+                                            } else if remain > 0 {
+                                                let sql = format!("update LowPriority set games={} where id='{}';", remain, id);
+                                                conn.query(sql.clone())?;
+                                            }
+                                        }
                                     }
                                     msgtx.try_send(MqttMsg{topic:format!("game/{}/res/game_status", x.game),
                                         msg: format!(r#"{{"status":"finished", "game": {}}}"#,x.game)})?;
@@ -2959,92 +3041,72 @@ pub fn init(
                                     }
                                 },
                                 RoomEventData::ChooseHero(x) => {
-                                    let mut isDup = false;
-                                    let u = TotalUsers.get(&x.id);
-                                    if let Some(u) = u {
-                                        if let Some(fg) = NGGameingGroups.get(&u.borrow().game_id) {
-                                            if fg.borrow().user_names[0..5].contains(&x.id) {
-                                                for uid in &fg.borrow().user_names[0..5] {
-                                                    if *uid != x.id {
-                                                        if let Some(u2) = TotalUsers.get(uid) {
-                                                            if u2.borrow().hero.clone() == x.hero.clone() {
-                                                                isDup = true;
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            if fg.borrow().user_names[5..10].contains(&x.id) {
-                                                for uid in &fg.borrow().user_names[5..10] {
-                                                    if *uid != x.id {
-                                                        if let Some(u2) = TotalUsers.get(uid) {
-                                                            if u2.borrow().hero.clone() == x.hero.clone() {
-                                                                isDup = true;
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        if !isDup {
-                                            println!("hero : {}, line: {}", x.hero.clone(), line!());
-                                            u.borrow_mut().hero = x.hero.clone();
+                                    if let Some(u) = TotalUsers.get(&x.id) {
+                                        let res = validate_choice(&u.borrow(), &x.hero, PhaseKind::Pick, &ActiveGames, &TotalHeros, &FreeHeros);
+                                        match res {
+                                            Ok(()) => {
+                                                println!("hero : {}, line: {}", x.hero.clone(), line!());
+                                                u.borrow_mut().hero = x.hero.clone();
+                                                let _ : () = redis_conn.set(x.id.clone(), x.hero.clone())?;
+                                                log_draft(&sender, u.borrow().game_id, PhaseKind::Pick.name(), &x.id, "pick", &x.hero);
+                                                mqttmsg = MqttMsg{topic:format!("member/{}/res/ng_choose_hero", x.id.clone()),
+                                                    msg: format!(r#"{{"id":"{}", "hero":"{}"}}"#, x.id.clone(), x.hero.clone())};
+                                            },
+                                            Err(reason) => {
+                                                mqttmsg = reject_choice_msg(&x, "ng_choose_hero", reason);
+                                            },
                                         }
                                     }
-                                    if !isDup {
-                                        let _ : () = redis_conn.set(x.id.clone(), x.hero.clone())?;
-                                        mqttmsg = MqttMsg{topic:format!("member/{}/res/ng_choose_hero", x.id.clone()),
-                                            msg: format!(r#"{{"id":"{}", "hero":"{}"}}"#, x.id.clone(), x.hero.clone())};
-                                    }
                                 },
                                 RoomEventData::LockedHero(x) => {
-                                    let mut isDup = false;
                                     let u = TotalUsers.get(&x.id);
                                     if let Some(u) = u {
-                                        if let Some(fg) = NGGameingGroups.get(&u.borrow().game_id) {
-                                            if fg.borrow().user_names[0..5].contains(&x.id) {
-                                                for uid in &fg.borrow().user_names[0..5] {
-                                                    if *uid != x.id {
-                                                        if let Some(u2) = TotalUsers.get(uid) {
-                                                            if u2.borrow().hero.clone() == x.hero.clone() {
-                                                                isDup = true;
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            if fg.borrow().user_names[5..10].contains(&x.id) {
-                                                for uid in &fg.borrow().user_names[5..10] {
-                                                    if *uid != x.id {
-                                                        if let Some(u2) = TotalUsers.get(uid) {
-                                                            if u2.borrow().hero.clone() == x.hero.clone() {
-                                                                isDup = true;
-                                                            }
-                                                        }
-                                                    }
-                                                }
+                                        if let Some(ActiveGame::CM(cm)) = ActiveGames.get(&u.borrow().game_id) {
+                                            mqttmsg = captain_choose_msg(cm, &x, PhaseKind::Pick, "locked_hero", &sender);
+                                        } else {
+                                            // locking the hero already held only confirms it, but still needs the turn
+                                            let res = if u.borrow().hero == x.hero {
+                                                validate_turn(&u.borrow(), PhaseKind::Pick, &ActiveGames)
+                                            } else {
+                                                validate_choice(&u.borrow(), &x.hero, PhaseKind::Pick, &ActiveGames, &TotalHeros, &FreeHeros)
+                                            };
+                                            match res {
+                                                Ok(()) => {
+                                                    u.borrow_mut().isLocked = true;
+                                                    u.borrow_mut().hero = x.hero.clone();
+                                                    log_draft(&sender, u.borrow().game_id, PhaseKind::Pick.name(), &x.id, "lock", &x.hero);
+                                                    mqttmsg = MqttMsg{topic:format!("game/{}/res/locked_hero", u.borrow().game_id),
+                                                        msg: format!(r#"{{"id":"{}", "hero":"{}"}}"#, u.borrow().id, x.hero.clone())};
+                                                },
+                                                Err(reason) => {
+                                                    mqttmsg = reject_choice_msg(&x, "locked_hero", reason);
+                                                },
                                             }
                                         }
-                                        if !isDup {
-                                            u.borrow_mut().isLocked = true;
-                                            u.borrow_mut().hero = x.hero.clone();
-                                            mqttmsg = MqttMsg{topic:format!("game/{}/res/locked_hero", u.borrow().game_id),
-                                                msg: format!(r#"{{"id":"{}", "hero":"{}"}}"#, u.borrow().id, x.hero.clone())};
-                                        }
                                     }
                                 },
                                 RoomEventData::BanHero(x) => {
                                     let u = TotalUsers.get(&x.id);
                                     if let Some(u) = u {
-                                        u.borrow_mut().isLocked = true;
-                                        u.borrow_mut().ban_hero = x.hero.clone();
-                                        // if let Some(fg) = GameingGroups.get(&u.borrow().game_id) {
-                                        //     fg.borrow_mut().lock_cnt += 1;
-                                        // }
-                                        println!("gid : {}, line: {}", u.borrow().game_id, line!());
-                                        mqttmsg = MqttMsg{topic:format!("game/{}/res/ban_hero", u.borrow().game_id),
-                                            msg: format!(r#"{{"id":"{}", "hero":"{}"}}"#, u.borrow().id, x.hero)};
-                                        println!("send ban hero : {}, {}, line: {}", u.borrow().id, x.hero, line!());
+                                        if let Some(ActiveGame::CM(cm)) = ActiveGames.get(&u.borrow().game_id) {
+                                            mqttmsg = captain_choose_msg(cm, &x, PhaseKind::Ban, "ban_hero", &sender);
+                                        } else {
+                                            let res = validate_choice(&u.borrow(), &x.hero, PhaseKind::Ban, &ActiveGames, &TotalHeros, &FreeHeros);
+                                            match res {
+                                                Ok(()) => {
+                                                    u.borrow_mut().isLocked = true;
+                                                    u.borrow_mut().ban_hero = x.hero.clone();
+                                                    log_draft(&sender, u.borrow().game_id, PhaseKind::Ban.name(), &x.id, "ban", &x.hero);
+                                                    println!("gid : {}, line: {}", u.borrow().game_id, line!());
+                                                    mqttmsg = MqttMsg{topic:format!("game/{}/res/ban_hero", u.borrow().game_id),
+                                                        msg: format!(r#"{{"id":"{}", "hero":"{}"}}"#, u.borrow().id, x.hero)};
+                                                    println!("send ban hero : {}, {}, line: {}", u.borrow().id, x.hero, line!());
+                                                },
+                                                Err(reason) => {
+                                                    mqttmsg = reject_choice_msg(&x, "ban_hero", reason);
+                                                },
+                                            }
+                                        }
                                     }
                                 },
                                 RoomEventData::GetHeros(x) => {
@@ -3059,10 +3121,56 @@ pub fn init(
                                     mqttmsg = MqttMsg{topic:format!("game/{}/res/get_heros", x.game_id),
                                         msg: format!(r#"{{"heros":"{}"}}"#, serde_json::to_string(&heros)?)};
                                 },
+                                RoomEventData::Reroll(x) => {
+                                    if let Some(u) = TotalUsers.get(&x.id) {
+                                        let gid = u.borrow().game_id;
+                                        if let Some(ActiveGame::ARAM(ag)) = ActiveGames.get(&gid) {
+                                            let res = ag.borrow_mut().reroll(&x.id);
+                                            match res {
+                                                Ok((team, hero)) => {
+                                                    log_draft(&sender, gid, PhaseKind::Pick.name(), &x.id, "reroll", &hero);
+                                                    let sql = format!("UPDATE user SET reroll={} WHERE id='{}';", u.borrow().reroll, x.id);
+                                                    conn.query(sql.clone())?;
+                                                    mqttmsg = MqttMsg{topic:format!("game/{}/res/reroll", gid),
+                                                        msg: format!(r#"{{"id":"{}", "hero":"{}", "team":{}, "bench":{:?}, "reroll":{}}}"#, x.id, hero, team, ag.borrow().bench[team], u.borrow().reroll)};
+                                                },
+                                                Err(reason) => {
+                                                    mqttmsg = MqttMsg{topic:format!("member/{}/res/reroll", x.id),
+                                                        msg: format!(r#"{{"msg":"{}"}}"#, reason)};
+                                                },
+                                            }
+                                        }
+                                    }
+                                },
+                                RoomEventData::BenchSwap(x) => {
+                                    if let Some(u) = TotalUsers.get(&x.id) {
+                                        let gid = u.borrow().game_id;
+                                        if let Some(ActiveGame::ARAM(ag)) = ActiveGames.get(&gid) {
+                                            let res = ag.borrow_mut().take_bench(&x.id, &x.hero);
+                                            match res {
+                                                Ok(team) => {
+                                                    log_draft(&sender, gid, PhaseKind::Pick.name(), &x.id, "bench_swap", &x.hero);
+                                                    mqttmsg = MqttMsg{topic:format!("game/{}/res/bench_swap", gid),
+                                                        msg: format!(r#"{{"id":"{}", "hero":"{}", "team":{}, "bench":{:?}}}"#, x.id, x.hero, team, ag.borrow().bench[team])};
+                                                },
+                                                Err(reason) => {
+                                                    mqttmsg = MqttMsg{topic:format!("member/{}/res/bench_swap", x.id),
+                                                        msg: format!(r#"{{"msg":"{}"}}"#, reason)};
+                                                },
+                                            }
+                                        }
+                                    }
+                                },
                                 RoomEventData::SwapHero(x) => {
                                     if let Some(u) = TotalUsers.get(&x.id) {
                                         if let Some(u2) = TotalUsers.get(&x.from) {
-                                            if x.action == "response" {
+                                            let trade = check_trade(u, u2, &ActiveGames);
+                                            if let Err(reason) = trade {
+                                                HeroSwapping.remove(&x.id);
+                                                HeroSwapping.remove(&x.from);
+                                                mqttmsg = MqttMsg{topic:format!("game/{}/res/swap_hero", x.game_id),
+                                                    msg: format!(r#"{{"id":"{}", "from":"{}", "action":"fail", "msg":"{}"}}"#, x.id, x.from, reason)};
+                                            } else if x.action == "response" {
                                                 HeroSwapping.remove(&x.id);
                                                 HeroSwapping.remove(&x.from);
                                                 if x.is_accept {
@@ -3070,6 +3178,16 @@ pub fn init(
                                                         let hero_tmp = u.borrow().hero.clone();
                                                         u.borrow_mut().hero = u2.borrow().hero.clone();
                                                         u2.borrow_mut().hero = hero_tmp;
+                                                        let phase = draft_phase(&ActiveGames, u.borrow().game_id);
+                                                        log_draft(&sender, u.borrow().game_id, phase, &x.id, "swap", &u.borrow().hero);
+                                                        log_draft(&sender, u.borrow().game_id, phase, &x.from, "swap", &u2.borrow().hero);
+                                                        // the picks were already written when the game became ready to start
+                                                        if let Some(game) = ActiveGames.get(&u.borrow().game_id) {
+                                                            let game = game.state();
+                                                            if game.borrow().check_status() == PhaseKind::ReadyToStart {
+                                                                sender.try_send(SqlData::UpdateGameInfo(choose_info(&*game.borrow(), &TotalUsers)));
+                                                            }
+                                                        }
                                                         let msgtx2 = msgtx.clone();
                                                         let mqttmsg1 = MqttMsg{topic:format!("member/{}/res/ng_choose_hero", u.borrow().id.clone()),
                                                             msg: format!(r#"{{"id":"{}", "hero":"{}"}}"#, u.borrow().id.clone(), u.borrow().hero.clone())};
@@ -3155,7 +3273,7 @@ pub fn init(
                                                 println!("len : {}, line: {}", r.borrow().users.len(), line!());
                                                 println!("ready : {}, line: {}", r.borrow().ready, line!());
                                                 if r.borrow().mode == "rk"{
-                                                    if r.borrow().ready == 0 && r.borrow().users.len() < TEAM_SIZE as usize {
+                                                    if r.borrow().ready == 0 && r.borrow().users.len() < mode_config(&r.borrow().mode).team_size as usize {
                                                         r.borrow_mut().add_user(Rc::clone(j));
                                                         let m = r.borrow().master.clone();
                                                         r.borrow().publish_update(&msgtx, m)?;
@@ -3174,7 +3292,7 @@ pub fn init(
                                                         let rid = u.borrow().rid;
                                                         u.borrow_mut().rid = rid;
                                                     }
-                                                }else if r.borrow().ready == 0 && r.borrow().users.len() < TEAM_SIZE as usize {
+                                                }else if r.borrow().ready == 0 && r.borrow().users.len() < mode_config(&r.borrow().mode).team_size as usize {
                                                     r.borrow_mut().add_user(Rc::clone(j));
                                                     let m = r.borrow().master.clone();
                                                     r.borrow().publish_update(&msgtx, m)?;
@@ -3302,107 +3420,215 @@ pub fn init(
                                 },
                                 RoomEventData::Jump(x) => {
                                     println!("jump : {:?}", x);
-                                    if let Some(u) = TotalUsers.get(&x.id) {
-                                        if let Some(gr) = ReadyGroups.get(&u.borrow().gid) {
-                                            gr.borrow_mut().user_cancel(&x.id);
-                                            for r in &gr.borrow().rooms {
-                                                info!("r_rid: {}, u_rid: {}, u_uid: {}, queue_cnt: {}, line: {}", r.borrow().rid, u.borrow().rid, u.borrow().id.clone(), r.borrow().queue_cnt.clone(), line!());
-                                                if r.borrow().rid != u.borrow().rid {
-                                                    let mut user_ids: Vec<String> = Vec::new();
-                                                    for user in &r.borrow().users {
-                                                        user_ids.push(user.borrow().id.clone());
+                                    if x.msg == "jump" && ActiveGames.contains_key(&x.game) {
+                                        let reason = if x.reason == "" { &x.msg } else { &x.reason };
+                                        log_draft(&sender, x.game, draft_phase(&ActiveGames, x.game), &x.id, reason, "");
+                                    }
+                                    let mut backfill = false;
+                                    if x.msg == "jump" && !CustomGames.contains_key(&x.game) && !TournamentGames.contains_key(&x.game) {
+                                        if let Some(ActiveGame::NG(fg)) = ActiveGames.get(&x.game) {
+                                            if let Some(u) = TotalUsers.get(&x.id) {
+                                                if fg.borrow().game_status < 4 {
+                                                    let gid = u.borrow().gid;
+                                                    let position = fg.borrow_mut().open_backfill(&x.id, gid);
+                                                    if let Some(position) = position {
+                                                        tx2.try_send(RoomEventData::BanUser(BanUserData{id: x.id.clone()}));
+                                                        u.borrow_mut().isLocked = false;
+                                                        u.borrow_mut().hero = "".to_string();
+                                                        u.borrow_mut().ban_hero = "".to_string();
+                                                        u.borrow_mut().gid = 0;
+                                                        u.borrow_mut().game_id = 0;
+                                                        leave_party(u, &mut TotalRoom, &msgtx, &QueueSender)?;
+                                                        QueueSender.send(QueueData::Backfill(BackfillData{
+                                                            game: x.game,
+                                                            mode: fg.borrow().mode.clone(),
+                                                            ng: u.borrow().ng,
+                                                            wait: 0,
+                                                        }));
+                                                        mqttmsg = MqttMsg{topic:format!("game/{}/res/backfill", x.game),
+                                                            msg: format!(r#"{{"id":"{}", "position":{}, "msg":"searching"}}"#, x.id.clone(), position)};
+                                                        backfill = true;
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                    let mut gid = 0;
+                                    if x.msg == "backfill_timeout" {
+                                        if let Some(ActiveGame::NG(fg)) = ActiveGames.get(&x.game) {
+                                            if let Some(slot) = fg.borrow().backfill.first() {
+                                                gid = slot.gid;
+                                            }
+                                        }
+                                        QueueSender.send(QueueData::CancelBackfill(CancelBackfillData{game: x.game}));
+                                    } else if let Some(u) = TotalUsers.get(&x.id) {
+                                        gid = u.borrow().gid;
+                                    }
+                                    if !backfill {
+                                        if let Some(u) = TotalUsers.get(&x.id) {
+                                            if let Some(gr) = ReadyGroups.get(&gid) {
+                                                gr.borrow_mut().user_cancel(&x.id);
+                                                for r in &gr.borrow().rooms {
+                                                    info!("r_rid: {}, u_rid: {}, u_uid: {}, queue_cnt: {}, line: {}", r.borrow().rid, u.borrow().rid, u.borrow().id.clone(), r.borrow().queue_cnt.clone(), line!());
+                                                    if r.borrow().rid != u.borrow().rid && r.borrow().users.len() > 0 {
+                                                        let mut user_ids: Vec<String> = Vec::new();
+                                                        for user in &r.borrow().users {
+                                                            user_ids.push(user.borrow().id.clone());
+                                                        }
+                                                        let low_priority = user_ids.iter().any(|id| LowPriorityUsers.contains_key(id));
+                                                        let mut data = QueueRoomData {
+                                                            rid: r.borrow().rid.clone(),
+                                                            gid: 0,
+                                                            user_len: r.borrow().users.len().clone() as i16,
+                                                            user_ids: user_ids,
+                                                            avg_ng: r.borrow().avg_ng.clone(),
+                                                            avg_rk: r.borrow().avg_rk.clone(),
+                                                            avg_at: r.borrow().avg_at.clone(),
+                                                            avg_aram: r.borrow().avg_aram.clone(),
+                                                            ready: 0,
+                                                            notify: false,
+                                                            queue_cnt: r.borrow().queue_cnt.clone(),
+                                                            mode: r.borrow().mode.clone(),
+                                                            modes: r.borrow().queue_modes.clone(),
+                                                            priority: true,
+                                                            low_priority: low_priority,
+                                                        };
+                                                        QueueSender.send(QueueData::UpdateRoom(data));
+                                                    }
+                                                }
+                                                ReadyGroups.remove(&gid);
+                                            }
+                                        }
+                                        if TotalUsers.contains_key(&x.id) {
+                                            if x.msg == "jump" && !AbandonGames.contains_key(&x.game) && !CustomGames.contains_key(&x.game) {
+                                                tx2.try_send(RoomEventData::BanUser(BanUserData{id: x.id.clone()}));
+                                                AbandonGames.insert(x.game, true);
+                                            }
+                                            let mut rm_list: Vec<u64> = Vec::new();
+                                            if let Some(game) = ActiveGames.get(&x.game) {
+                                                let fg = game.state();
+                                                for uid in fg.borrow().user_names() {
+                                                    if let Some(u) = TotalUsers.get(uid) {
+                                                        u.borrow_mut().isLocked = false;
+                                                        u.borrow_mut().hero = "".to_string();
+                                                        u.borrow_mut().ban_hero = "".to_string();
+                                                        u.borrow_mut().gid = 0;
                                                     }
-                                                    let mut data = QueueRoomData {
-                                                        rid: r.borrow().rid.clone(),
-                                                        gid: 0,
-                                                        user_len: r.borrow().users.len().clone() as i16,
-                                                        user_ids: user_ids,
-                                                        avg_ng: r.borrow().avg_ng.clone(),
-                                                        avg_rk: r.borrow().avg_rk.clone(),
-                                                        avg_at: r.borrow().avg_at.clone(),
-                                                        avg_aram: r.borrow().avg_aram.clone(),
-                                                        ready: 0,
-                                                        notify: false,
-                                                        queue_cnt: r.borrow().queue_cnt.clone(),
-                                                        mode: r.borrow().mode.clone(),
-                                                    };
-                                                    QueueSender.send(QueueData::UpdateRoom(data));
                                                 }
+                                                rm_list.push(fg.borrow().game_id());
+                                                mqttmsg = MqttMsg{topic:format!("game/{}/res/jump", x.game.clone()),
+                                                    msg: format!(r#"{{"id":"{}","mgs":"jump"}}"#, x.id.clone())};
                                             }
-                                            ReadyGroups.remove(&u.borrow().gid);
-                                        }
-                                    }
-                                    if TotalUsers.contains_key(&x.id) {
-                                        if !AbandonGames.contains_key(&x.game) {
-                                            tx2.try_send(RoomEventData::BanUser(BanUserData{id: x.id.clone()}));
-                                            AbandonGames.insert(x.game, true);
-                                        }
-                                        let mut rm_list: Vec<u64> = Vec::new();
-                                        if let Some(fg) = NGGameingGroups.get(&x.game) {
-                                            for uid in &fg.borrow().user_names {
-                                                if let Some(u) = TotalUsers.get(uid) {
-                                                    u.borrow_mut().isLocked = false;
-                                                    u.borrow_mut().hero = "".to_string();
-                                                    u.borrow_mut().ban_hero = "".to_string();
-                                                    u.borrow_mut().gid = 0;
+                                            for rm in rm_list {
+                                                let sql = format!(
+                                                    "DELETE FROM Gaming where game={} and status='wait';",
+                                                    rm
+                                                );
+                                                let qres = conn.query(sql.clone())?;
+                                                ActiveGames.remove(&rm);
+                                                if let Some(tid) = TournamentGames.remove(&rm) {
+                                                    if let Some(t) = Tournaments.get(&tid) {
+                                                        let reported = t.borrow_mut().forfeit(rm, &x.id);
+                                                        if reported {
+                                                            start_tournament_matches(t, &mut TournamentGames, &TotalUsers, &InGameUsers, &mut TotalRoom, &mut ReadyGroups, &mut PreStartGroups, &mut room_id, &mut group_id, &mut game_id, &msgtx, &QueueSender)?;
+                                                            t.borrow().publish_standings(&msgtx, format!("tournament/{}/res/standings", tid))?;
+                                                        }
+                                                    }
                                                 }
                                             }
-                                            rm_list.push(fg.borrow().game_id);
-                                            mqttmsg = MqttMsg{topic:format!("game/{}/res/jump", x.game.clone()),
-                                                msg: format!(r#"{{"id":"{}","mgs":"jump"}}"#, x.id.clone())};
                                         }
-                                        if let Some(fg) = ARAMGameingGroups.get(&x.game) {
-                                            for uid in &fg.borrow().user_names {
-                                                if let Some(u) = TotalUsers.get(uid) {
-                                                    u.borrow_mut().isLocked = false;
-                                                    u.borrow_mut().hero = "".to_string();
-                                                    u.borrow_mut().ban_hero = "".to_string();
-                                                    u.borrow_mut().gid = 0;
+                                    }
+                                    isUpdateCount = true;
+                                },
+                                RoomEventData::BackfillFound(x) => {
+                                    let mut filled = false;
+                                    if let Some(r) = TotalRoom.get(&x.rid) {
+                                        if let Some(ActiveGame::NG(fg)) = ActiveGames.get(&x.game) {
+                                            if r.borrow().users.len() == 1 {
+                                                let u = Rc::clone(&r.borrow().users[0]);
+                                                let slot = fg.borrow_mut().fill_backfill(&u.borrow().id);
+                                                if let Some(slot) = slot {
+                                                    fg.borrow().teams[slot.team].borrow_mut().add_room(Rc::clone(r));
+                                                    fg.borrow_mut().room_names.push(r.borrow().master.clone());
+                                                    u.borrow_mut().gid = slot.gid;
+                                                    u.borrow_mut().game_id = x.game;
+                                                    msgtx.try_send(MqttMsg{topic:format!("room/{}/res/start_get", r.borrow().master.clone()), msg: format!(r#"{{"msg":"start", "room":"{}",
+                                                        "game":"{}", "players":{:?}}}"#, r.borrow().master.clone(), x.game, &fg.borrow().user_names)})?;
+                                                    mqttmsg = MqttMsg{topic:format!("game/{}/res/backfill", x.game),
+                                                        msg: format!(r#"{{"id":"{}", "replace":"{}", "position":{}, "msg":"filled"}}"#, u.borrow().id.clone(), slot.id, slot.position)};
+                                                    filled = true;
                                                 }
                                             }
-                                            rm_list.push(fg.borrow().game_id);
-                                            mqttmsg = MqttMsg{topic:format!("game/{}/res/jump", x.game.clone()),
-                                                msg: format!(r#"{{"id":"{}","mgs":"jump"}}"#, x.id.clone())};
                                         }
-                                        if let Some(fg) = RKGameingGroups.get(&x.game) {
-                                            for uid in &fg.borrow().user_names {
-                                                if let Some(u) = TotalUsers.get(uid) {
-                                                    u.borrow_mut().isLocked = false;
-                                                    u.borrow_mut().hero = "".to_string();
-                                                    u.borrow_mut().ban_hero = "".to_string();
-                                                    u.borrow_mut().gid = 0;
-                                                }
+                                        if !filled {
+                                            // the game was closed meanwhile, the player goes back to the front of the queue
+                                            let mut user_ids: Vec<String> = Vec::new();
+                                            for user in &r.borrow().users {
+                                                user_ids.push(user.borrow().id.clone());
                                             }
-                                            rm_list.push(fg.borrow().game_id);
-                                            mqttmsg = MqttMsg{topic:format!("game/{}/res/jump", x.game.clone()),
-                                                msg: format!(r#"{{"id":"{}","mgs":"jump"}}"#, x.id.clone())};
+                                            let low_priority = user_ids.iter().any(|id| LowPriorityUsers.contains_key(id));
+                                            let mut data = QueueRoomData {
+                                                rid: r.borrow().rid.clone(),
+                                                gid: 0,
+                                                user_len: r.borrow().users.len().clone() as i16,
+                                                user_ids: user_ids,
+                                                avg_ng: r.borrow().avg_ng.clone(),
+                                                avg_rk: r.borrow().avg_rk.clone(),
+                                                avg_at: r.borrow().avg_at.clone(),
+                                                avg_aram: r.borrow().avg_aram.clone(),
+                                                ready: 0,
+                                                notify: false,
+                                                queue_cnt: r.borrow().queue_cnt.clone(),
+                                                mode: r.borrow().mode.clone(),
+                                                modes: r.borrow().queue_modes.clone(),
+                                                priority: true,
+                                                low_priority: low_priority,
+                                            };
+                                            QueueSender.send(QueueData::UpdateRoom(data));
                                         }
-                                        if let Some(fg) = ATGameingGroups.get(&x.game) {
-                                            for uid in &fg.borrow().user_names {
-                                                if let Some(u) = TotalUsers.get(uid) {
-                                                    u.borrow_mut().isLocked = false;
-                                                    u.borrow_mut().hero = "".to_string();
-                                                    u.borrow_mut().ban_hero = "".to_string();
-                                                    u.borrow_mut().gid = 0;
+                                    }
+                                },
+                                RoomEventData::AutoPick(x) => {
+                                    if let Some(ActiveGame::NG(fg)) = ActiveGames.get(&x.game) {
+                                        if let Some(u) = TotalUsers.get(&x.id) {
+                                            if u.borrow().hero == "" {
+                                                let mut taken: Vec<String> = vec![];
+                                                for uid in &fg.borrow().teammates(&x.id) {
+                                                    if let Some(u2) = TotalUsers.get(uid) {
+                                                        if u2.borrow().hero != "" {
+                                                            taken.push(u2.borrow().hero.clone());
+                                                        }
+                                                    }
+                                                }
+                                                for name in TotalHeros.keys() {
+                                                    if !can_play(&u.borrow(), name, &FreeHeros) {
+                                                        taken.push(name.clone());
+                                                    }
+                                                }
+                                                let hero = fg.borrow().auto_pick_hero(&u.borrow().hero_usage, &taken, &TotalHeros);
+                                                if let Some(hero) = hero {
+                                                    info!("auto pick id: {}, hero: {}, game: {}, line: {}", x.id, hero, x.game, line!());
+                                                    u.borrow_mut().hero = hero.clone();
+                                                    let _ : () = redis_conn.set(x.id.clone(), hero.clone())?;
+                                                    log_draft(&sender, x.game, fg.borrow().check_status().name(), &x.id, "auto_pick", &hero);
+                                                    // a first timeout is forgiven, repeated ones within the reset time are punished like a jump
+                                                    let reset_time = 7200;
+                                                    let autoPickData = AutoPickUsers.entry(x.id.clone()).or_insert(Rc::new(RefCell::new(JumpCountData{
+                                                        count: 0,
+                                                        time: reset_time,
+                                                    })));
+                                                    autoPickData.borrow_mut().count += 1;
+                                                    autoPickData.borrow_mut().time = reset_time;
+                                                    let repeated = autoPickData.borrow().count > 1;
+                                                    if repeated && !CustomGames.contains_key(&x.game) && !TournamentGames.contains_key(&x.game) {
+                                                        tx2.try_send(RoomEventData::BanUser(BanUserData{id: x.id.clone()}));
+                                                    }
+                                                    mqttmsg = MqttMsg{topic:format!("member/{}/res/ng_choose_hero", x.id.clone()),
+                                                        msg: format!(r#"{{"id":"{}", "hero":"{}", "auto":true}}"#, x.id.clone(), hero)};
                                                 }
                                             }
-                                            rm_list.push(fg.borrow().game_id);
-                                            mqttmsg = MqttMsg{topic:format!("game/{}/res/jump", x.game.clone()),
-                                                msg: format!(r#"{{"id":"{}","mgs":"jump"}}"#, x.id.clone())};
-                                        }
-                                        for rm in rm_list {
-                                            let sql = format!(
-                                                "DELETE FROM Gaming where game={} and status='wait';",
-                                                rm
-                                            );
-                                            let qres = conn.query(sql.clone())?;
-                                            NGGameingGroups.remove(&rm);
-                                            ARAMGameingGroups.remove(&rm);
-                                            RKGameingGroups.remove(&rm);
-                                            ATGameingGroups.remove(&rm);
                                         }
                                     }
-                                    isUpdateCount = true;
                                 },
                                 RoomEventData::BanUser(x) => {
                                     info!("ban user : {:?}, line: {}", x, line!());
@@ -3437,28 +3663,17 @@ pub fn init(
                                             );
                                         }
                                     }
+                                    if let Some(j) = JumpUsers.get(&x.id) {
+                                        if j.borrow().count >= LOW_PRIORITY_JUMP_CNT {
+                                            LowPriorityUsers.insert(x.id.clone(), LOW_PRIORITY_GAMES);
+                                            let sql = format!("replace into LowPriority (id, games) values ('{}', {});", x.id.clone(), LOW_PRIORITY_GAMES);
+                                            conn.query(sql.clone())?;
+                                        }
+                                    }
                                     tx2.try_send(RoomEventData::CheckRestriction(CheckRestrctionData{id: x.id.clone()}));
                                 },
                                 RoomEventData::Loading(x) => {
-                                    if let Some(fg) = NGGameingGroups.get(&x.game) {
-                                        if let Some(u) = TotalUsers.get(&x.id) {
-                                            u.borrow_mut().isLoading = true;
-                                            info!("id : {}, isLoading : {}, line : {}", x.id, u.borrow_mut().isLoading, line!());
-                                        }
-                                    }
-                                    if let Some(fg) = ARAMGameingGroups.get(&x.game) {
-                                        if let Some(u) = TotalUsers.get(&x.id) {
-                                            u.borrow_mut().isLoading = true;
-                                            info!("id : {}, isLoading : {}, line : {}", x.id, u.borrow_mut().isLoading, line!());
-                                        }
-                                    }
-                                    if let Some(fg) = RKGameingGroups.get(&x.game) {
-                                        if let Some(u) = TotalUsers.get(&x.id) {
-                                            u.borrow_mut().isLoading = true;
-                                            info!("id : {}, isLoading : {}, line : {}", x.id, u.borrow_mut().isLoading, line!());
-                                        }
-                                    }
-                                    if let Some(fg) = ATGameingGroups.get(&x.game) {
+                                    if ActiveGames.contains_key(&x.game) {
                                         if let Some(u) = TotalUsers.get(&x.id) {
                                             u.borrow_mut().isLoading = true;
                                             info!("id : {}, isLoading : {}, line : {}", x.id, u.borrow_mut().isLoading, line!());
@@ -3466,6 +3681,7 @@ pub fn init(
                                     }
                                 },
                                 RoomEventData::CheckRestriction(x) => {
+                                    let low_games = LowPriorityUsers.get(&x.id).cloned().unwrap_or(0);
                                     let sql = format!(r#"select UNIX_TIMESTAMP(end) from BAN where id="{}";"#, x.id.clone());
                                     let qres: mysql::QueryResult = conn.query(sql.clone())?;
                                     let mut isBan = false;
@@ -3488,7 +3704,7 @@ pub fn init(
                                             }
                                         }
                                         mqttmsg = MqttMsg{topic:format!("member/{}/res/check_restriction", x.id.clone()),
-                                            msg: format!(r#"{{"time":"{}"}}"#, duration.as_secs())};
+                                            msg: format!(r#"{{"time":"{}", "low_priority":{}}}"#, duration.as_secs(), low_games)};
                                     }
                                     for rm in rm_list {
                                         let sql2 = format!(r#"delete from BAN where id="{}";"#, rm);
@@ -3502,7 +3718,10 @@ pub fn init(
                                                 tx2.try_send(RoomEventData::Leave(LeaveData{room: u.borrow().rid.to_string(), id: x.id.clone()}));
                                             }
                                             mqttmsg = MqttMsg{topic:format!("member/{}/res/check_restriction", x.id.clone()),
-                                                msg: format!(r#"{{"time":"{}"}}"#, r.borrow().time)};
+                                                msg: format!(r#"{{"time":"{}", "low_priority":{}}}"#, r.borrow().time, low_games)};
+                                        } else if low_games > 0 {
+                                            mqttmsg = MqttMsg{topic:format!("member/{}/res/check_restriction", x.id.clone()),
+                                                msg: format!(r#"{{"time":"0", "low_priority":{}}}"#, low_games)};
                                         }
                                     }
                                 },
@@ -3533,6 +3752,7 @@ pub fn init(
                                             id: u.borrow().id.clone(),
                                             game: x.game,
                                             msg: "jump".to_string(),
+                                            reason: "leave".to_string(),
                                         };
                                         tx2.try_send(RoomEventData::Jump(jumpData));
                                     }
@@ -3543,7 +3763,7 @@ pub fn init(
                                     //QueueRoom.clear();
                                     ReadyGroups.clear();
                                     PreStartGroups.clear();
-                                    GameingGroups.clear();
+                                    ActiveGames.clear();
                                     TotalUsers.clear();
                                     room_id = 0;
                                 },
@@ -3574,6 +3794,7 @@ pub fn init(
                                                                 for user in &r.borrow().users {
                                                                     user_ids.push(user.borrow().id.clone());
                                                                 }
+                                                                let low_priority = user_ids.iter().any(|id| LowPriorityUsers.contains_key(id));
                                                                 let mut data = QueueRoomData {
                                                                     rid: r.borrow().rid.clone(),
                                                                     gid: 0,
@@ -3587,6 +3808,9 @@ pub fn init(
                                                                     notify: false,
                                                                     queue_cnt: r.borrow().queue_cnt.clone(),
                                                                     mode: r.borrow().mode.clone(),
+                                                                    modes: r.borrow().queue_modes.clone(),
+                                                                    priority: true,
+                                                                    low_priority: low_priority,
                                                                 };
                                                                 QueueSender.send(QueueData::UpdateRoom(data));
                                                             }
@@ -3621,6 +3845,7 @@ pub fn init(
                                         for rid in r {
                                             let room = TotalRoom.get(&rid);
                                             if let Some(room) = room {
+                                                room.borrow_mut().mode = x.mode.clone();
                                                 g.add_room(Rc::clone(&room));
                                             }
                                         }
@@ -3650,6 +3875,10 @@ pub fn init(
                                     fg.set_game_id(game_id);
                                     fg.set_mode(x.mode);
                                     fg.ready_cnt = 0.0;
+                                    let quality = match_quality(&fg);
+                                    info!("match quality : {:?}, line: {}", quality, line!());
+                                    msgtx.try_send(MqttMsg{topic:"metrics/matchmaking".to_string(), msg: serde_json::to_string(&quality)?})?;
+                                    sender.send(SqlData::MatchQuality(quality));
                                     info!("PreStartGroups : {:?}, line: {}", fg, line!());
                                     // info!("game_id: {}, game_mode: {}, game_player: {:?} line: {}", fg.game_id, fg.mode, fg.user_names, line!());
                                     PreStartGroups.insert(game_id, Rc::new(RefCell::new(fg)));
@@ -3683,35 +3912,68 @@ pub fn init(
                                         if hasRoom {
                                             let r = TotalRoom.get(&rid);
                                             if let Some(y) = r {
-                                                y.borrow_mut().update_avg();
-                                                println!("xmode : {}", x.mode.clone());
-                                                y.borrow_mut().mode = x.mode.clone();
-                                                let mut user_ids: Vec<String> = Vec::new();
-                                                for user in &y.borrow().users {
-                                                    user_ids.push(user.borrow().id.clone());
+                                                let mode = queue_mode(&x.mode, &x.queue).unwrap_or_default();
+                                                info!("mode: {}, queue: {}, line: {}", x.mode, x.queue, line!());
+                                                let mut modes = vec![mode.clone()];
+                                                for m in &x.modes {
+                                                    if !modes.contains(m) {
+                                                        modes.push(m.clone());
+                                                    }
+                                                }
+                                                let mut reason = None;
+                                                for m in &modes {
+                                                    if reason.is_none() {
+                                                        reason = party_restriction(&y.borrow(), m, &party_rules);
+                                                    }
+                                                    if y.borrow().users.len() > mode_config(m).team_size as usize {
+                                                        reason = Some("party size");
+                                                    }
+                                                }
+                                                if mode.ends_with("_solo") && y.borrow().users.len() > SOLO_QUEUE_SIZE {
+                                                    reason = Some("solo queue size");
+                                                }
+                                                // the solo ratings share the room averages, so a solo queue can't be combined
+                                                if mode == "" || (modes.len() > 1 && mode.ends_with("_solo")) {
+                                                    reason = Some("queue type");
+                                                }
+                                                if modes[1..].iter().any(|m| m != "sng" && !is_valid_mode(m)) {
+                                                    reason = Some("queue type");
+                                                }
+                                                if reason.is_none() {
+                                                    y.borrow_mut().mode = mode.clone();
+                                                    y.borrow_mut().queue_modes = modes.clone();
+                                                    y.borrow_mut().update_avg();
+                                                    let mut user_ids: Vec<String> = Vec::new();
+                                                    for user in &y.borrow().users {
+                                                        user_ids.push(user.borrow().id.clone());
+                                                    }
+                                                    let low_priority = user_ids.iter().any(|id| LowPriorityUsers.contains_key(id));
+                                                    let mut data = QueueRoomData {
+                                                        rid: y.borrow().rid.clone(),
+                                                        gid: 0,
+                                                        user_len: y.borrow().users.len().clone() as i16,
+                                                        user_ids: user_ids,
+                                                        avg_ng: y.borrow().avg_ng.clone(),
+                                                        avg_rk: y.borrow().avg_rk.clone(),
+                                                        avg_at: y.borrow().avg_at.clone(),
+                                                        avg_aram: y.borrow().avg_aram.clone(),
+                                                        ready: 0,
+                                                        notify: false,
+                                                        queue_cnt: 1,
+                                                        mode: mode.clone(),
+                                                        modes: modes.clone(),
+                                                        priority: false,
+                                                        low_priority: low_priority,
+                                                    };
+                                                    QueueSender.send(QueueData::UpdateRoom(data));
+                                                    success = true;
                                                 }
-                                                let mut data = QueueRoomData {
-                                                    rid: y.borrow().rid.clone(),
-                                                    gid: 0,
-                                                    user_len: y.borrow().users.len().clone() as i16,
-                                                    user_ids: user_ids,
-                                                    avg_ng: y.borrow().avg_ng.clone(),
-                                                    avg_rk: y.borrow().avg_rk.clone(),
-                                                    avg_at: y.borrow().avg_at.clone(),
-                                                    avg_aram: y.borrow().avg_aram.clone(),
-                                                    ready: 0,
-                                                    notify: false,
-                                                    queue_cnt: 1,
-                                                    mode: x.mode.clone(),
-                                                };
-                                                QueueSender.send(QueueData::UpdateRoom(data));
-                                                success = true;
                                                 if success {
                                                     mqttmsg = MqttMsg{topic:format!("room/{}/res/start_queue", y.borrow().master.clone()),
-                                                        msg: format!(r#"{{"msg":"ok", "mode": "{}"}}"#, x.mode.clone())};
+                                                        msg: format!(r#"{{"msg":"ok", "mode": "{}", "queue": "{}", "modes": {}}}"#, x.mode.clone(), x.queue.clone(), serde_json::to_string(&modes)?)};
                                                 } else {
                                                     mqttmsg = MqttMsg{topic:format!("room/{}/res/start_queue", y.borrow().master.clone()),
-                                                        msg: format!(r#"{{"msg":"fail"}}"#)}
+                                                        msg: format!(r#"{{"msg":"fail", "reason":"{}"}}"#, reason.unwrap_or(""))}
                                                 }
                                             }
                                         }else{
@@ -3735,6 +3997,7 @@ pub fn init(
                                                 user_ids.push(user.borrow().id.clone());
                                             }
                                             let mut ready = y.borrow().ready;
+                                            let low_priority = user_ids.iter().any(|id| LowPriorityUsers.contains_key(id));
                                             let mut data = QueueRoomData {
                                                 rid: y.borrow().rid.clone(),
                                                 gid: 0,
@@ -3748,6 +4011,9 @@ pub fn init(
                                                 notify: true,
                                                 queue_cnt: 1,
                                                 mode: y.borrow().mode.clone(),
+                                                modes: y.borrow().queue_modes.clone(),
+                                                priority: false,
+                                                low_priority: low_priority,
                                             };
                                             QueueSender.send(QueueData::UpdateRoom(data));
                                         }
@@ -3788,14 +4054,8 @@ pub fn init(
                                     if TotalUsers.contains_key(&x.u.id) {
                                         let u2 = TotalUsers.get(&x.u.id);
                                         if let Some(u2) = u2 {
-                                            let sql = format!(r#"SELECT hero FROM Hero_usage WHERE steam_id='{}' ORDER BY choose_count DESC LIMIT 1;"#, u2.borrow().id.clone());
-                                            let qres2: mysql::QueryResult = conn.query(sql.clone())?;
-                                            let mut hero = "".to_string();
-                                            for row in qres2 {
-                                                let a = row?.clone();
-                                                hero = mysql::from_value_opt(a.get("hero").ok_or(Error::from(core::fmt::Error))?)?;
-                                                break;
-                                            }
+                                            load_user_heros(&mut u2.borrow_mut(), &mut conn)?;
+                                            let hero = u2.borrow().hero_usage.first().cloned().unwrap_or_default();
                                             let sql = format!(r#"SELECT * FROM user where id='{}';"#, u2.borrow().id.clone());
                                             let qres2: mysql::QueryResult = conn.query(sql.clone())?;
                                             for row in qres2 {
@@ -3803,69 +4063,49 @@ pub fn init(
                                                 u2.borrow_mut().ng = mysql::from_value_opt(a.get("ng").ok_or(Error::from(core::fmt::Error))?)?;
                                                 u2.borrow_mut().rk = mysql::from_value_opt(a.get("rk").ok_or(Error::from(core::fmt::Error))?)?;
                                                 u2.borrow_mut().at = mysql::from_value_opt(a.get("at").ok_or(Error::from(core::fmt::Error))?)?;
+                                                u2.borrow_mut().rk_solo = mysql::from_value_opt(a.get("rk_solo").ok_or(Error::from(core::fmt::Error))?)?;
+                                                u2.borrow_mut().at_solo = mysql::from_value_opt(a.get("at_solo").ok_or(Error::from(core::fmt::Error))?)?;
                                                 u2.borrow_mut().aram = mysql::from_value_opt(a.get("aram").ok_or(Error::from(core::fmt::Error))?)?;
                                                 u2.borrow_mut().raindrop = mysql::from_value_opt(a.get("raindrop").ok_or(Error::from(core::fmt::Error))?)?;
+                                                u2.borrow_mut().reroll = mysql::from_value_opt(a.get("reroll").ok_or(Error::from(core::fmt::Error))?)?;
                                                 u2.borrow_mut().phone = mysql::from_value_opt(a.get("phone").ok_or(Error::from(core::fmt::Error))?)?;
                                                 u2.borrow_mut().email = mysql::from_value_opt(a.get("email").ok_or(Error::from(core::fmt::Error))?)?;
                                             }
                                             u2.borrow_mut().online = true;
                                             mqttmsg = MqttMsg{topic:format!("member/{}/res/login", u2.borrow().id.clone()),
-                                                msg: format!(r#"{{"msg":"ok", "ng":{}, "rk":{}, "at":{},"aram":{}, "raindrop": {}, "hero":"{}", "phone":"{}", "email":"{}"}}"#, u2.borrow().ng, u2.borrow().rk, u2.borrow().at, u2.borrow().aram, u2.borrow().raindrop, hero, u2.borrow().phone, u2.borrow().email)};
+                                                msg: format!(r#"{{"msg":"ok", "ng":{}, "rk":{}, "at":{}, "rk_solo":{}, "at_solo":{},"aram":{}, "raindrop": {}, "reroll": {}, "hero":"{}", "phone":"{}", "email":"{}"}}"#, u2.borrow().ng, u2.borrow().rk, u2.borrow().at, u2.borrow().rk_solo, u2.borrow().at_solo, u2.borrow().aram, u2.borrow().raindrop, u2.borrow().reroll, hero, u2.borrow().phone, u2.borrow().email)};
                                         }
                                     }
                                     else {
-                                        TotalUsers.insert(x.u.id.clone(), Rc::new(RefCell::new(x.u.clone())));
+                                        let mut u = x.u.clone();
+                                        load_user_heros(&mut u, &mut conn)?;
+                                        TotalUsers.insert(x.u.id.clone(), Rc::new(RefCell::new(u)));
                                         sender.send(SqlData::Login(SqlLoginData {id: x.dataid.clone(), name: name.clone()}));
                                         mqttmsg = MqttMsg{topic:format!("member/{}/res/login", x.u.id.clone()),
-                                            msg: format!(r#"{{"msg":"ok", "ng":{}, "rk":{}, "at":{}, "aram":{}, "raindrop": {}, "hero":"", "phone":"", "email":""}}"#, 1200, 1200, 1200, 0, 1200)};
+                                            msg: format!(r#"{{"msg":"ok", "ng":{}, "rk":{}, "at":{}, "rk_solo":{}, "at_solo":{}, "aram":{}, "raindrop": {}, "reroll": {}, "hero":"", "phone":"", "email":""}}"#, 1200, 1200, 1200, 1200, 1200, 0, 1200, 0)};
                                     }
                                 },
                                 RoomEventData::Logout(x) => {
                                     let mut success = false;
                                     isUpdateCount = true;
+                                    Spectators.unwatch(&x.id);
                                     let u = TotalUsers.get(&x.id);
                                     let u2 = get_user(&x.id, &TotalUsers);
                                     if let Some(u2) = u2 {
                                         u2.borrow_mut().online = false;
                                     }
                                     if let Some(u) = u {
-                                        let ng_game_id = get_ng_game_id_by_id(&u.borrow().id, &NGGameingGroups, &TotalUsers);
-                                        let aram_game_id = get_aram_game_id_by_id(&u.borrow().id, &ARAMGameingGroups, &TotalUsers);
-                                        let rk_game_id = get_rk_game_id_by_id(&u.borrow().id, &RKGameingGroups, &TotalUsers);
-                                        let at_game_id = get_at_game_id_by_id(&u.borrow().id, &ATGameingGroups, &TotalUsers);
-                                        if ng_game_id > 0 {
-                                            println!("ng game id : {}", ng_game_id);
-                                            let jumpData = JumpData{
-                                                id: u.borrow().id.clone(),
-                                                game: ng_game_id,
-                                                msg: "jump".to_string(),
-                                            };
-                                            tx2.try_send(RoomEventData::Jump(jumpData));
-                                        }
-                                        if aram_game_id > 0 {
-                                            println!("aram game id : {}", aram_game_id);
-                                            let jumpData = JumpData{
-                                                id: u.borrow().id.clone(),
-                                                game: aram_game_id,
-                                                msg: "jump".to_string(),
-                                            };
-                                            tx2.try_send(RoomEventData::Jump(jumpData));
-                                        }
-                                        if rk_game_id > 0 {
-                                            println!("rk game id : {}", rk_game_id);
-                                            let jumpData = JumpData{
-                                                id: u.borrow().id.clone(),
-                                                game: rk_game_id,
-                                                msg: "jump".to_string(),
-                                            };
-                                            tx2.try_send(RoomEventData::Jump(jumpData));
+                                        if u.borrow().lid != 0 {
+                                            tx2.try_send(RoomEventData::LeaveLobby(LeaveLobbyData{id: x.id.clone()}));
                                         }
-                                        if at_game_id > 0 {
-                                            println!("at game id : {}", at_game_id);
+                                        let jump_game = get_game_id_by_id(&u.borrow().id, &ActiveGames, &TotalUsers);
+                                        if jump_game > 0 {
+                                            println!("game id : {}", jump_game);
                                             let jumpData = JumpData{
                                                 id: u.borrow().id.clone(),
-                                                game: at_game_id,
+                                                game: jump_game,
                                                 msg: "jump".to_string(),
+                                                reason: "logout".to_string(),
                                             };
                                             tx2.try_send(RoomEventData::Jump(jumpData));
                                         }
@@ -3910,21 +4150,6 @@ pub fn init(
                                             //     //println!("Totalroom rid: {}", &u.borrow().rid);
                                             //     //TotalRoom.remove(&rid);
                                             // }
-                                            let mut rm_list: Vec<u64> = Vec::new();
-                                            if let Some(fg) = GameingGroups.get(&gid) {
-                                                for t in &fg.borrow().teams {
-                                                    for r in &t.borrow().rooms {
-                                                        for user in &r.borrow().users {
-                                                            user.borrow_mut().isLocked = false;
-                                                            user.borrow_mut().gid = 0;
-                                                        }
-                                                    }
-                                                }
-                                                rm_list.push(fg.borrow().game_id);
-                                            }
-                                            for rm in rm_list {
-                                                GameingGroups.remove(&rm);
-                                            }
                                         }
                                         //println!("{:?}", TotalRoom);
                                         //TotalRoom.remove(&u.borrow().rid);
@@ -3982,6 +4207,7 @@ pub fn init(
                                             ready: 0,
                                             queue_cnt: 1,
                                             mode: x.mode.clone(),
+                                            queue_modes: vec![],
                                         };
                                         let mut u = TotalUsers.get(&x.id);
                                         if let Some(u) = u {
@@ -4075,87 +4301,439 @@ pub fn init(
                                                 atState = "open";
                                             }
                                         }
-                                        mqttmsg = MqttMsg{topic:format!("server/res/check_state"),
-                                            msg: format!(r#"{{"ng":"{}", "rk":"{}", "at":"{}", "aram":"{}"}}"#, ngState, rkState, atState, aramState)};
+                                        mqttmsg = MqttMsg{topic:format!("server/res/check_state"),
+                                            msg: format!(r#"{{"ng":"{}", "rk":"{}", "at":"{}", "aram":"{}"}}"#, ngState, rkState, atState, aramState)};
+                                    }
+                                },
+                                RoomEventData::CheckState(x) => {
+                                    if let Some(u) = TotalUsers.get(&x.id) {
+                                        mqttmsg = MqttMsg{topic:format!("member/{}/res/check_state", x.id),
+                                                msg: format!(r#"{{"ng":"{}", "rk":"{}", "at":"{}", "aram":"{}"}}"#, ngState, rkState, atState, aramState)};
+                                    }
+                                },
+                                RoomEventData::Free() => {
+                                    let sql = format!(
+                                        "select * from Free order by week DESC limit 1;",
+                                    );
+                                    let qres = conn.query(sql.clone())?;
+                                    let mut hero1 = "".to_string();
+                                    let mut hero2 = "".to_string();
+                                    let mut hero3 = "".to_string();
+                                    let mut hero4 = "".to_string();
+                                    let mut hero5 = "".to_string();
+                                    let mut hero6 = "".to_string();
+                                    let mut hero7 = "".to_string();
+                                    let mut hero8 = "".to_string();
+                                    let mut hero9 = "".to_string();
+                                    let mut hero10 = "".to_string();
+                                    let mut hero11 = "".to_string();
+                                    let mut hero12 = "".to_string();
+                                    let mut hero13 = "".to_string();
+                                    let mut hero14 = "".to_string();
+                                    let mut hero15 = "".to_string();
+                                    let mut hero16 = "".to_string();
+                                    let mut hero17 = "".to_string();
+                                    let mut hero18 = "".to_string();
+                                    let mut hero19 = "".to_string();
+                                    let mut hero20 = "".to_string();
+                                    for row in qres.take(1) {
+                                        let a = row?.clone();
+                                        hero1 = mysql::from_value_opt(a.get("hero1").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero2 = mysql::from_value_opt(a.get("hero2").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero3 = mysql::from_value_opt(a.get("hero3").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero4 = mysql::from_value_opt(a.get("hero4").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero5 = mysql::from_value_opt(a.get("hero5").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero6 = mysql::from_value_opt(a.get("hero6").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero7 = mysql::from_value_opt(a.get("hero7").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero8 = mysql::from_value_opt(a.get("hero8").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero9 = mysql::from_value_opt(a.get("hero9").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero10 = mysql::from_value_opt(a.get("hero10").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero11 = mysql::from_value_opt(a.get("hero11").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero12 = mysql::from_value_opt(a.get("hero12").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero13 = mysql::from_value_opt(a.get("hero13").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero14 = mysql::from_value_opt(a.get("hero14").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero15 = mysql::from_value_opt(a.get("hero15").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero16 = mysql::from_value_opt(a.get("hero16").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero17 = mysql::from_value_opt(a.get("hero17").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero18 = mysql::from_value_opt(a.get("hero18").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero19 = mysql::from_value_opt(a.get("hero19").ok_or(Error::from(core::fmt::Error))?)?;
+                                        hero20 = mysql::from_value_opt(a.get("hero20").ok_or(Error::from(core::fmt::Error))?)?;
+                                    }
+                                    mqttmsg = MqttMsg{topic:format!("server/res/free"),
+                                            msg: format!(r#"{{"hero1":"{}", "hero2":"{}", "hero3":"{}"
+                                            , "hero4":"{}", "hero5":"{}", "hero6":"{}", "hero7":"{}", "hero8":"{}", "hero9":"{}"
+                                            , "hero10":"{}", "hero11":"{}", "hero12":"{}", "hero13":"{}", "hero14":"{}", "hero15":"{}"
+                                            , "hero16":"{}", "hero17":"{}", "hero18":"{}", "hero19":"{}", "hero20":"{}"}}"#
+                                            , hero1, hero2, hero3, hero4, hero5, hero6, hero7, hero8, hero9, hero10
+                                            , hero11, hero12, hero13, hero14, hero15, hero16, hero17, hero18, hero19, hero20)};
+                                },
+                                RoomEventData::UpdateQueue(x) => {
+                                    ng_solo_queue_cnt = x.ng_solo;
+                                    ng_queue_cnt = x.ng;
+                                    rk_queue_cnt = x.rk;
+                                    at_queue_cnt = x.at;
+                                    aram_queue_cnt = x.aram;
+                                },
+                                RoomEventData::SystemBan(x) => {
+                                    if (x.password == "HibikiHibiki") {
+                                        let sql = format!(
+                                            "replace BAN values ('{}', date_add(now(), interval {} second));",
+                                            x.id.clone(), x.time.clone()
+                                        );
+                                        conn.query(sql.clone())?;
+                                    }
+                                },
+                                RoomEventData::CreateLobby(x) => {
+                                    let mut success = false;
+                                    if let Some(u) = TotalUsers.get(&x.id) {
+                                        let valid_mode = is_valid_mode(&x.mode);
+                                        if valid_mode && u.borrow().lid == 0 && u.borrow().gid == 0 && u.borrow().game_id == 0 {
+                                            leave_party(u, &mut TotalRoom, &msgtx, &QueueSender)?;
+                                            lobby_id += 1;
+                                            let mut lobby = LobbyData {
+                                                lid: lobby_id,
+                                                host: x.id.clone(),
+                                                mode: x.mode.clone(),
+                                                password: x.password.clone(),
+                                                ..Default::default()
+                                            };
+                                            lobby.add_player(Rc::clone(u));
+                                            lobby.publish_update(&msgtx)?;
+                                            TotalLobby.insert(lobby_id, Rc::new(RefCell::new(lobby)));
+                                            success = true;
+                                        }
+                                    }
+                                    if success {
+                                        mqttmsg = MqttMsg{topic:format!("lobby/{}/res/create_lobby", x.id.clone()),
+                                            msg: format!(r#"{{"msg":"ok", "lobby":{}}}"#, lobby_id)};
+                                    } else {
+                                        mqttmsg = MqttMsg{topic:format!("lobby/{}/res/create_lobby", x.id.clone()),
+                                            msg: format!(r#"{{"msg":"fail"}}"#)};
+                                    }
+                                },
+                                RoomEventData::JoinLobby(x) => {
+                                    let mut reason = "";
+                                    if let Some(l) = TotalLobby.get(&x.lobby) {
+                                        if let Some(u) = TotalUsers.get(&x.id) {
+                                            if u.borrow().lid != 0 || u.borrow().gid != 0 || u.borrow().game_id != 0 {
+                                                reason = "busy";
+                                            } else if l.borrow().password != x.password {
+                                                reason = "wrong password";
+                                            } else if l.borrow().game_id != 0 {
+                                                reason = "started";
+                                            } else if x.spectator && l.borrow().spectators.len() >= LOBBY_SPECTATOR_SIZE {
+                                                reason = "full";
+                                            } else if !x.spectator && l.borrow().players.len() >= LOBBY_PLAYER_SIZE {
+                                                reason = "full";
+                                            } else {
+                                                leave_party(u, &mut TotalRoom, &msgtx, &QueueSender)?;
+                                                if x.spectator {
+                                                    l.borrow_mut().add_spectator(Rc::clone(u));
+                                                } else {
+                                                    l.borrow_mut().add_player(Rc::clone(u));
+                                                }
+                                                l.borrow().publish_update(&msgtx)?;
+                                            }
+                                        } else {
+                                            reason = "no user";
+                                        }
+                                    } else {
+                                        reason = "no lobby";
+                                    }
+                                    if reason == "" {
+                                        mqttmsg = MqttMsg{topic:format!("lobby/{}/res/join_lobby", x.id.clone()),
+                                            msg: format!(r#"{{"msg":"ok", "lobby":{}}}"#, x.lobby)};
+                                    } else {
+                                        mqttmsg = MqttMsg{topic:format!("lobby/{}/res/join_lobby", x.id.clone()),
+                                            msg: format!(r#"{{"msg":"fail", "reason":"{}"}}"#, reason)};
+                                    }
+                                },
+                                RoomEventData::LeaveLobby(x) => {
+                                    if let Some(u) = TotalUsers.get(&x.id) {
+                                        let lid = u.borrow().lid;
+                                        let mut is_null = false;
+                                        if let Some(l) = TotalLobby.get(&lid) {
+                                            l.borrow_mut().rm_user(&x.id);
+                                            if l.borrow().players.len() > 0 {
+                                                l.borrow().publish_update(&msgtx)?;
+                                            } else {
+                                                is_null = true;
+                                            }
+                                        }
+                                        if is_null {
+                                            if let Some(l) = TotalLobby.remove(&lid) {
+                                                for s in &l.borrow().spectators {
+                                                    s.borrow_mut().lid = 0;
+                                                    msgtx.try_send(MqttMsg{topic:format!("lobby/{}/res/leave_lobby", s.borrow().id),
+                                                        msg: format!(r#"{{"msg":"lobby closed"}}"#)})?;
+                                                }
+                                            }
+                                        }
+                                        u.borrow_mut().lid = 0;
+                                        mqttmsg = MqttMsg{topic:format!("lobby/{}/res/leave_lobby", x.id.clone()),
+                                            msg: format!(r#"{{"msg":"ok"}}"#)};
+                                    }
+                                },
+                                RoomEventData::AssignTeam(x) => {
+                                    let mut success = false;
+                                    if let Some(u) = TotalUsers.get(&x.id) {
+                                        if let Some(l) = TotalLobby.get(&u.borrow().lid) {
+                                            if l.borrow().host == x.id && l.borrow().game_id == 0 {
+                                                let team_size = mode_config(&l.borrow().mode).team_size as usize;
+                                                success = l.borrow_mut().assign_team(&x.target, x.team, team_size);
+                                                if success {
+                                                    l.borrow().publish_update(&msgtx)?;
+                                                }
+                                            }
+                                        }
+                                    }
+                                    if success {
+                                        mqttmsg = MqttMsg{topic:format!("lobby/{}/res/assign_team", x.id.clone()),
+                                            msg: format!(r#"{{"msg":"ok"}}"#)};
+                                    } else {
+                                        mqttmsg = MqttMsg{topic:format!("lobby/{}/res/assign_team", x.id.clone()),
+                                            msg: format!(r#"{{"msg":"fail"}}"#)};
+                                    }
+                                },
+                                RoomEventData::StartLobby(x) => {
+                                    let mut reason = "";
+                                    let mut lid = 0;
+                                    if let Some(u) = TotalUsers.get(&x.id) {
+                                        lid = u.borrow().lid;
+                                    }
+                                    if let Some(l) = TotalLobby.get(&lid) {
+                                        if l.borrow().host != x.id {
+                                            reason = "not host";
+                                        } else if l.borrow().game_id != 0 {
+                                            reason = "started";
+                                        } else if l.borrow().team1.len() != mode_config(&l.borrow().mode).team_size as usize || l.borrow().team2.len() != mode_config(&l.borrow().mode).team_size as usize {
+                                            reason = "teams not full";
+                                        } else {
+                                            let teams = vec![l.borrow().team1.clone(), l.borrow().team2.clone()];
+                                            let mode = l.borrow().mode.clone();
+                                            let started = create_direct_game(&teams, mode, &TotalUsers, &InGameUsers, &mut TotalRoom, &mut ReadyGroups, &mut PreStartGroups, &mut room_id, &mut group_id, &mut game_id, &msgtx, &QueueSender)?;
+                                            if let Some(gid) = started {
+                                                CustomGames.insert(gid, lid);
+                                                l.borrow_mut().game_id = gid;
+                                                for u in l.borrow().players.iter().chain(l.borrow().spectators.iter()) {
+                                                    msgtx.try_send(MqttMsg{topic:format!("lobby/{}/res/start_lobby", u.borrow().id),
+                                                        msg: format!(r#"{{"msg":"ok", "game":{}}}"#, gid)})?;
+                                                }
+                                            } else {
+                                                reason = "players busy";
+                                            }
+                                        }
+                                    } else {
+                                        reason = "no lobby";
+                                    }
+                                    if reason != "" {
+                                        mqttmsg = MqttMsg{topic:format!("lobby/{}/res/start_lobby", x.id.clone()),
+                                            msg: format!(r#"{{"msg":"fail", "reason":"{}"}}"#, reason)};
+                                    }
+                                },
+                                RoomEventData::CreateTournament(x) => {
+                                    let valid_format = x.format == "single" || x.format == "double" || x.format == "swiss";
+                                    let valid_mode = is_valid_mode(&x.mode);
+                                    if x.password == "HibikiHibiki" && valid_format && valid_mode {
+                                        tournament_id += 1;
+                                        let t = Tournament::new(tournament_id, x.name.clone(), x.format.clone(), x.mode.clone());
+                                        Tournaments.insert(tournament_id, Rc::new(RefCell::new(t)));
+                                        mqttmsg = MqttMsg{topic:format!("tournament/res/create_tournament"),
+                                            msg: format!(r#"{{"msg":"ok", "tournament":{}}}"#, tournament_id)};
+                                    } else {
+                                        mqttmsg = MqttMsg{topic:format!("tournament/res/create_tournament"),
+                                            msg: format!(r#"{{"msg":"fail"}}"#)};
+                                    }
+                                },
+                                RoomEventData::RegisterTeam(x) => {
+                                    let mut success = false;
+                                    if let Some(t) = Tournaments.get(&x.tournament) {
+                                        if x.roster.contains(&x.id) {
+                                            let team_size = mode_config(&t.borrow().mode).team_size as usize;
+                                            success = t.borrow_mut().register(x.name.clone(), x.roster.clone(), team_size);
+                                        }
+                                        if success {
+                                            t.borrow().publish_standings(&msgtx, format!("tournament/{}/res/standings", x.tournament))?;
+                                        }
+                                    }
+                                    if success {
+                                        mqttmsg = MqttMsg{topic:format!("tournament/{}/res/register_team", x.id.clone()),
+                                            msg: format!(r#"{{"msg":"ok", "tournament":{}}}"#, x.tournament)};
+                                    } else {
+                                        mqttmsg = MqttMsg{topic:format!("tournament/{}/res/register_team", x.id.clone()),
+                                            msg: format!(r#"{{"msg":"fail"}}"#)};
+                                    }
+                                },
+                                RoomEventData::StartTournament(x) => {
+                                    let mut success = false;
+                                    if x.password == "HibikiHibiki" {
+                                        if let Some(t) = Tournaments.get(&x.tournament) {
+                                            let started = t.borrow_mut().start();
+                                            if started {
+                                                start_tournament_matches(t, &mut TournamentGames, &TotalUsers, &InGameUsers, &mut TotalRoom, &mut ReadyGroups, &mut PreStartGroups, &mut room_id, &mut group_id, &mut game_id, &msgtx, &QueueSender)?;
+                                                t.borrow().publish_standings(&msgtx, format!("tournament/{}/res/standings", x.tournament))?;
+                                                success = true;
+                                            }
+                                        }
+                                    }
+                                    if success {
+                                        mqttmsg = MqttMsg{topic:format!("tournament/res/start_tournament"),
+                                            msg: format!(r#"{{"msg":"ok", "tournament":{}}}"#, x.tournament)};
+                                    } else {
+                                        mqttmsg = MqttMsg{topic:format!("tournament/res/start_tournament"),
+                                            msg: format!(r#"{{"msg":"fail"}}"#)};
                                     }
                                 },
-                                RoomEventData::CheckState(x) => {
-                                    if let Some(u) = TotalUsers.get(&x.id) {
-                                        mqttmsg = MqttMsg{topic:format!("member/{}/res/check_state", x.id),
-                                                msg: format!(r#"{{"ng":"{}", "rk":"{}", "at":"{}", "aram":"{}"}}"#, ngState, rkState, atState, aramState)};
+                                RoomEventData::GetStandings(x) => {
+                                    if let Some(t) = Tournaments.get(&x.tournament) {
+                                        t.borrow().publish_standings(&msgtx, format!("tournament/{}/res/standings", x.id.clone()))?;
+                                    } else {
+                                        mqttmsg = MqttMsg{topic:format!("tournament/{}/res/standings", x.id.clone()),
+                                            msg: format!(r#"{{"msg":"fail"}}"#)};
                                     }
                                 },
-                                RoomEventData::Free() => {
+                                RoomEventData::RegisterServer(x) => {
+                                    info!("register game server: {:?}, line: {}", x, line!());
+                                    Servers.register(&x.id, &x.address, x.capacity, start.elapsed().as_secs());
+                                    mqttmsg = MqttMsg{topic:format!("gameserver/{}/res/register", x.id),
+                                        msg: format!(r#"{{"msg":"ok"}}"#)};
+                                },
+                                RoomEventData::ServerHeartbeat(x) => {
+                                    if !Servers.heartbeat(&x.id, start.elapsed().as_secs()) {
+                                        mqttmsg = MqttMsg{topic:format!("gameserver/{}/res/heartbeat", x.id),
+                                            msg: format!(r#"{{"msg":"unknown server"}}"#)};
+                                    }
+                                },
+                                RoomEventData::AllocateServer(x) => {
+                                    if ActiveGames.contains_key(&x.game) {
+                                        match Servers.allocate(x.game) {
+                                            Some(server) => {
+                                                send_allocation(&msgtx, x.game, &server, &ActiveGames, redis_conn)?;
+                                            },
+                                            None => {
+                                                warn!("no game server for game: {}, line: {}", x.game, line!());
+                                                mqttmsg = MqttMsg{topic:format!("game/{}/res/start_game", x.game),
+                                                    msg: format!(r#"{{"game":{}, "msg":"waiting for server"}}"#, x.game)};
+                                            },
+                                        }
+                                    }
+                                },
+                                RoomEventData::GetDraftLog(x) => {
                                     let sql = format!(
-                                        "select * from Free order by week DESC limit 1;",
+                                        "select id, action, hero, phase, time from DraftLog where game={} order by time;",
+                                        x.game
                                     );
                                     let qres = conn.query(sql.clone())?;
-                                    let mut hero1 = "".to_string();
-                                    let mut hero2 = "".to_string();
-                                    let mut hero3 = "".to_string();
-                                    let mut hero4 = "".to_string();
-                                    let mut hero5 = "".to_string();
-                                    let mut hero6 = "".to_string();
-                                    let mut hero7 = "".to_string();
-                                    let mut hero8 = "".to_string();
-                                    let mut hero9 = "".to_string();
-                                    let mut hero10 = "".to_string();
-                                    let mut hero11 = "".to_string();
-                                    let mut hero12 = "".to_string();
-                                    let mut hero13 = "".to_string();
-                                    let mut hero14 = "".to_string();
-                                    let mut hero15 = "".to_string();
-                                    let mut hero16 = "".to_string();
-                                    let mut hero17 = "".to_string();
-                                    let mut hero18 = "".to_string();
-                                    let mut hero19 = "".to_string();
-                                    let mut hero20 = "".to_string();
+                                    let mut log: Vec<DraftLogData> = vec![];
                                     for row in qres {
                                         let a = row?.clone();
-                                        hero1 = mysql::from_value_opt(a.get("hero1").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero2 = mysql::from_value_opt(a.get("hero2").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero3 = mysql::from_value_opt(a.get("hero3").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero4 = mysql::from_value_opt(a.get("hero4").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero5 = mysql::from_value_opt(a.get("hero5").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero6 = mysql::from_value_opt(a.get("hero6").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero7 = mysql::from_value_opt(a.get("hero7").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero8 = mysql::from_value_opt(a.get("hero8").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero9 = mysql::from_value_opt(a.get("hero9").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero10 = mysql::from_value_opt(a.get("hero10").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero11 = mysql::from_value_opt(a.get("hero11").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero12 = mysql::from_value_opt(a.get("hero12").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero13 = mysql::from_value_opt(a.get("hero13").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero14 = mysql::from_value_opt(a.get("hero14").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero15 = mysql::from_value_opt(a.get("hero15").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero16 = mysql::from_value_opt(a.get("hero16").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero17 = mysql::from_value_opt(a.get("hero17").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero18 = mysql::from_value_opt(a.get("hero18").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero19 = mysql::from_value_opt(a.get("hero19").ok_or(Error::from(core::fmt::Error))?)?;
-                                        hero20 = mysql::from_value_opt(a.get("hero20").ok_or(Error::from(core::fmt::Error))?)?;
-                                        break;
+                                        log.push(DraftLogData {
+                                            game: x.game,
+                                            id: mysql::from_value_opt(a.get("id").ok_or(Error::from(core::fmt::Error))?)?,
+                                            action: mysql::from_value_opt(a.get("action").ok_or(Error::from(core::fmt::Error))?)?,
+                                            hero: mysql::from_value_opt(a.get("hero").ok_or(Error::from(core::fmt::Error))?)?,
+                                            phase: mysql::from_value_opt(a.get("phase").ok_or(Error::from(core::fmt::Error))?)?,
+                                            time: mysql::from_value_opt(a.get("time").ok_or(Error::from(core::fmt::Error))?)?,
+                                        });
                                     }
-                                    mqttmsg = MqttMsg{topic:format!("server/res/free"),
-                                            msg: format!(r#"{{"hero1":"{}", "hero2":"{}", "hero3":"{}"
-                                            , "hero4":"{}", "hero5":"{}", "hero6":"{}", "hero7":"{}", "hero8":"{}", "hero9":"{}"
-                                            , "hero10":"{}", "hero11":"{}", "hero12":"{}", "hero13":"{}", "hero14":"{}", "hero15":"{}"
-                                            , "hero16":"{}", "hero17":"{}", "hero18":"{}", "hero19":"{}", "hero20":"{}"}}"#
-                                            , hero1, hero2, hero3, hero4, hero5, hero6, hero7, hero8, hero9, hero10
-                                            , hero11, hero12, hero13, hero14, hero15, hero16, hero17, hero18, hero19, hero20)};
+                                    mqttmsg = MqttMsg{topic:format!("member/{}/res/draft_log", x.id),
+                                        msg: format!(r#"{{"game":{}, "log":{}}}"#, x.game, serde_json::to_string(&log)?)};
                                 },
-                                RoomEventData::UpdateQueue(x) => {
-                                    ng_solo_queue_cnt = x.ng_solo;
-                                    ng_queue_cnt = x.ng;
-                                    rk_queue_cnt = x.rk;
-                                    at_queue_cnt = x.at;
-                                    aram_queue_cnt = x.aram;
+                                RoomEventData::Spectate(x) => {
+                                    let mut game_id = x.game;
+                                    if x.friend != "" {
+                                        game_id = get_game_id_by_id(&x.friend, &ActiveGames, &TotalUsers);
+                                    }
+                                    let res = if get_game_id_by_id(&x.id, &ActiveGames, &TotalUsers) > 0 {
+                                        Err("player")
+                                    } else if let Some(game) = ActiveGames.get(&game_id) {
+                                        let fg = game.state();
+                                        let delay = match mode_config(&fg.borrow().mode()).flow {
+                                            "rk" | "at" | "cm" => RANKED_SPECTATE_DELAY,
+                                            _ => 0,
+                                        };
+                                        let snapshot = draft_snapshot(&*fg.borrow(), &TotalUsers);
+                                        Spectators.watch(game_id, &x.id, delay);
+                                        Spectators.snapshot(game_id, &x.id, &serde_json::to_string(&snapshot)?, start.elapsed().as_secs());
+                                        Ok(delay)
+                                    } else {
+                                        Err("no game")
+                                    };
+                                    match res {
+                                        Ok(delay) => {
+                                            mqttmsg = MqttMsg{topic:format!("member/{}/res/spectate", x.id),
+                                                msg: format!(r#"{{"msg":"ok", "game":{}, "delay":{}}}"#, game_id, delay)};
+                                        }
+                                        Err(reason) => {
+                                            mqttmsg = MqttMsg{topic:format!("member/{}/res/spectate", x.id),
+                                                msg: format!(r#"{{"msg":"fail", "reason":"{}"}}"#, reason)};
+                                        }
+                                    }
                                 },
-                                RoomEventData::SystemBan(x) => {
-                                    if (x.password == "HibikiHibiki") {
-                                        let sql = format!(
-                                            "replace BAN values ('{}', date_add(now(), interval {} second));",
-                                            x.id.clone(), x.time.clone()
-                                        );
+                                RoomEventData::StopSpectate(x) => {
+                                    let game = Spectators.unwatch(&x.id);
+                                    mqttmsg = MqttMsg{topic:format!("member/{}/res/stop_spectate", x.id),
+                                        msg: format!(r#"{{"msg":"ok", "game":{}}}"#, game.unwrap_or(0))};
+                                },
+                                RoomEventData::DraftControl(x) => {
+                                    let mut success = false;
+                                    if x.password == "HibikiHibiki" {
+                                        if let Some(game) = ActiveGames.get(&x.game) {
+                                            let fg = game.state();
+                                            let phase = fg.borrow().check_status();
+                                            let drafting = phase == PhaseKind::Ban || phase == PhaseKind::Pick || phase == PhaseKind::Trade || phase == PhaseKind::ReadyToStart;
+                                            if drafting && x.action == "pause" {
+                                                fg.borrow_mut().set_paused(true);
+                                                success = true;
+                                            } else if drafting && x.action == "resume" {
+                                                fg.borrow_mut().set_paused(false);
+                                                success = true;
+                                            } else if drafting && x.action == "reset" {
+                                                // choices made by the players on turn are dropped with the phase
+                                                if phase == PhaseKind::Ban || phase == PhaseKind::Pick {
+                                                    for index in fg.borrow().pick_position() {
+                                                        if let Some(u) = TotalUsers.get(&fg.borrow().user_names()[*index]) {
+                                                            u.borrow_mut().isLocked = false;
+                                                            if phase == PhaseKind::Ban {
+                                                                u.borrow_mut().ban_hero = "".to_string();
+                                                            } else {
+                                                                u.borrow_mut().hero = "".to_string();
+                                                            }
+                                                        }
+                                                    }
+                                                }
+                                                fg.borrow_mut().restart_phase();
+                                                success = true;
+                                            }
+                                            if success {
+                                                info!("draft control game: {}, action: {}, phase: {}, line: {}", x.game, x.action, fg.borrow().status_name(), line!());
+                                                mqttmsg = MqttMsg{topic:format!("game/{}/res/game_status", x.game),
+                                                    msg: format!(r#"{{"status":"{}", "game":{}, "phase":"{}", "paused":{}}}"#, x.action, x.game, fg.borrow().status_name(), fg.borrow().paused())};
+                                            }
+                                        }
+                                    }
+                                    if !success {
+                                        mqttmsg = MqttMsg{topic:format!("game/res/draft_control"),
+                                            msg: format!(r#"{{"msg":"fail", "game":{}, "action":"{}"}}"#, x.game, x.action)};
+                                    }
+                                },
+                                RoomEventData::UnlockHero(x) => {
+                                    if x.password == "HibikiHibiki" && TotalHeros.contains_key(&x.hero) {
+                                        let sql = format!("replace into Hero_owned (steam_id, hero) values ('{}', '{}');", x.id, x.hero);
                                         conn.query(sql.clone())?;
+                                        if let Some(u) = TotalUsers.get(&x.id) {
+                                            if !u.borrow().owned_heros.contains(&x.hero) {
+                                                u.borrow_mut().owned_heros.push(x.hero.clone());
+                                            }
+                                        }
+                                        mqttmsg = MqttMsg{topic:format!("member/{}/res/unlock_hero", x.id),
+                                            msg: format!(r#"{{"msg":"ok", "hero":"{}"}}"#, x.hero)};
+                                    }
+                                },
+                                RoomEventData::FreeRotation(x) => {
+                                    if x.password == "HibikiHibiki" {
+                                        FreeHeros = generate_free_rotation(&mut conn, &TotalHeros)?;
+                                        tx2.try_send(RoomEventData::Free());
                                     }
                                 },
                                 RoomEventData::UpdateHeros(x) => {
@@ -4180,6 +4758,13 @@ pub fn init(
                                 msgtx.try_send(mqttmsg.clone())?;
                             }
                         }
+                        if !isBackup || (isBackup && isServerLive == false) {
+                            let now = start.elapsed().as_secs();
+                            Spectators.offer(&mqttmsg, now);
+                            for m in Spectators.release(now) {
+                                msgtx.try_send(m)?;
+                            }
+                        }
                         if msgtx.is_full() {
                             println!("FULL!!");
                             //thread::sleep(Duration::from_millis(5000));
@@ -4388,6 +4973,26 @@ pub fn swap_hero(
     Ok(())
 }
 
+pub fn reroll(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let data: RerollData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::Reroll(data));
+    Ok(())
+}
+
+pub fn bench_swap(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let data: BenchSwapData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::BenchSwap(data));
+    Ok(())
+}
+
 pub fn lock_hero(
     id: String,
     v: Value,
@@ -4499,6 +5104,62 @@ pub fn control(v: Value, sender: Sender<RoomEventData>) -> std::result::Result<(
     Ok(())
 }
 
+pub fn register_server(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let mut data: RegisterServerData = serde_json::from_value(v)?;
+    data.id = id;
+    sender.try_send(RoomEventData::RegisterServer(data));
+    Ok(())
+}
+
+pub fn server_heartbeat(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    sender.try_send(RoomEventData::ServerHeartbeat(ServerHeartbeatData { id: id }));
+    Ok(())
+}
+
+pub fn get_draft_log(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let data: GetDraftLogData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::GetDraftLog(data));
+    Ok(())
+}
+
+pub fn spectate(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let data: SpectateData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::Spectate(data));
+    Ok(())
+}
+
+pub fn stop_spectate(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let data: StopSpectateData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::StopSpectate(data));
+    Ok(())
+}
+
+pub fn draft_control(v: Value, sender: Sender<RoomEventData>) -> std::result::Result<(), Error> {
+    let data: DraftControlData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::DraftControl(data));
+    Ok(())
+}
+
 pub fn checkState(v: Value, sender: Sender<RoomEventData>) -> std::result::Result<(), Error> {
     let data: CheckStateData = serde_json::from_value(v)?;
     sender.try_send(RoomEventData::CheckState(data));
@@ -4516,6 +5177,18 @@ pub fn systemBan(v: Value, sender: Sender<RoomEventData>) -> std::result::Result
     Ok(())
 }
 
+pub fn unlockHero(v: Value, sender: Sender<RoomEventData>) -> std::result::Result<(), Error> {
+    let data: UnlockHeroData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::UnlockHero(data));
+    Ok(())
+}
+
+pub fn freeRotation(v: Value, sender: Sender<RoomEventData>) -> std::result::Result<(), Error> {
+    let data: FreeRotationData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::FreeRotation(data));
+    Ok(())
+}
+
 pub fn updateHeros(v: Value, sender: Sender<RoomEventData>) -> std::result::Result<(), Error> {
     let data: UpdateHerosData = serde_json::from_value(v)?;
     sender.try_send(RoomEventData::UpdateHeros(data));
@@ -4531,3 +5204,157 @@ pub fn loading(
     sender.try_send(RoomEventData::Loading(data));
     Ok(())
 }
+
+pub fn create_lobby(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let data: CreateLobbyData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::CreateLobby(data));
+    Ok(())
+}
+
+pub fn join_lobby(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let data: JoinLobbyData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::JoinLobby(data));
+    Ok(())
+}
+
+pub fn leave_lobby(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let data: LeaveLobbyData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::LeaveLobby(data));
+    Ok(())
+}
+
+pub fn assign_team(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let data: AssignTeamData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::AssignTeam(data));
+    Ok(())
+}
+
+pub fn start_lobby(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let data: StartLobbyData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::StartLobby(data));
+    Ok(())
+}
+
+pub fn create_tournament(v: Value, sender: Sender<RoomEventData>) -> std::result::Result<(), Error> {
+    let data: CreateTournamentData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::CreateTournament(data));
+    Ok(())
+}
+
+pub fn register_team(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let data: RegisterTeamData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::RegisterTeam(data));
+    Ok(())
+}
+
+pub fn start_tournament(v: Value, sender: Sender<RoomEventData>) -> std::result::Result<(), Error> {
+    let data: StartTournamentData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::StartTournament(data));
+    Ok(())
+}
+
+pub fn get_standings(
+    id: String,
+    v: Value,
+    sender: Sender<RoomEventData>,
+) -> std::result::Result<(), Error> {
+    let data: GetStandingsData = serde_json::from_value(v)?;
+    sender.try_send(RoomEventData::GetStandings(data));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_room(ratings: &[i16]) -> RoomData {
+        let mut r = RoomData {
+            rid: 1,
+            users: vec![],
+            master: "p0".to_string(),
+            last_master: "".to_owned(),
+            avg_ng: 0,
+            avg_rk: 0,
+            avg_at: 0,
+            avg_aram: 0,
+            ready: 0,
+            queue_cnt: 0,
+            mode: "rk".to_string(),
+            queue_modes: vec![],
+        };
+        for (i, rk) in ratings.iter().enumerate() {
+            r.add_user(Rc::new(RefCell::new(User {
+                id: format!("p{}", i),
+                rk: *rk,
+                ..Default::default()
+            })));
+        }
+        r
+    }
+
+    #[test]
+    fn test_party_restriction() {
+        let rules = PartyRules::default();
+        assert_eq!(party_restriction(&new_room(&[2000, 1100]), "rk", &rules), Some("rating spread"));
+        assert_eq!(party_restriction(&new_room(&[2000, 1100]), "ng", &rules), None);
+        assert_eq!(party_restriction(&new_room(&[1900, 1850]), "rk", &rules), None);
+        assert_eq!(party_restriction(&new_room(&[1900, 1850, 1800]), "rk", &rules), Some("duo only"));
+        assert_eq!(party_restriction(&new_room(&[1200, 1100, 1000]), "rk", &rules), None);
+        let rules = PartyRules {
+            spread: 50,
+            duo_only_rating: 1000,
+            duo_only_size: 1,
+        };
+        assert_eq!(party_restriction(&new_room(&[1200, 1100]), "rk", &rules), Some("rating spread"));
+        assert_eq!(party_restriction(&new_room(&[1200, 1180]), "rk", &rules), Some("duo only"));
+        assert_eq!(party_restriction(&new_room(&[900, 880]), "rk", &rules), None);
+    }
+
+    #[test]
+    fn test_reconnect_game() {
+        let r = new_room(&[1000, 1000]);
+        let mut TotalUsers: BTreeMap<String, Rc<RefCell<User>>> = BTreeMap::new();
+        for u in &r.users {
+            TotalUsers.insert(u.borrow().id.clone(), u.clone());
+        }
+        let mut ActiveGames: BTreeMap<u64, ActiveGame> = BTreeMap::new();
+        ActiveGames.insert(7, ActiveGame::NG(Rc::new(RefCell::new(NGGame {
+            game_id: 7,
+            user_names: vec!["p0".to_string()],
+            ..Default::default()
+        }))));
+        let mut GameingRoom: BTreeMap<u64, Rc<RefCell<GameRoomData>>> = BTreeMap::new();
+        GameingRoom.insert(9, Rc::new(RefCell::new(GameRoomData { master: "p1".to_string(), isOpen: false })));
+        // an active game wins over whatever redis says
+        assert_eq!(reconnect_game(&"p0".to_string(), &ActiveGames, &TotalUsers, &GameingRoom, 9), 7);
+        // a running game only redis remembers
+        assert_eq!(reconnect_game(&"p1".to_string(), &ActiveGames, &TotalUsers, &GameingRoom, 9), 9);
+        // a stale redis key and no game at all
+        assert_eq!(reconnect_game(&"p1".to_string(), &ActiveGames, &TotalUsers, &GameingRoom, 8), 0);
+        assert_eq!(reconnect_game(&"p1".to_string(), &ActiveGames, &TotalUsers, &GameingRoom, 0), 0);
+    }
+}
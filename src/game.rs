@@ -1,13 +1,15 @@
 use crate::msg::*;
 use crate::room::*;
 use crate::event_room::*;
+use crate::draft::*;
 use crossbeam_channel::{bounded, select, tick, Receiver, Sender};
 use failure::Error;
 use log::{error, info, trace, warn};
 use serde_derive::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::rc::Rc;
-use rand::{seq::IteratorRandom, thread_rng};
+use rand::rngs::StdRng;
+use rand::{seq::IteratorRandom, SeedableRng};
 use std::collections::{BTreeMap, HashMap};
 
 pub const BUFFER: i16 = -5;
@@ -15,7 +17,191 @@ pub const CHOOSE_HERO_TIME: i16 = 30;
 pub const NG_CHOOSE_HERO_TIME: i16 = 90;
 pub const BAN_HERO_TIME: i16 = 25;
 pub const READY_TO_START_TIME: i16 = 10;
+pub const TRADE_TIME: i16 = 20;
 pub const ARAM_READY_TO_START_TIME: i16 = 40;
+pub const ARAM_PICK_TIME: i16 = 30;
+pub const BACKFILL_TIME: i16 = 30;
+pub const CM_TURN_TIME: i16 = 30;
+pub const CM_RESERVE_TIME: i16 = 130;
+
+// a hero can be picked when the player unlocked it or it is in this week's free rotation
+pub fn can_play(u: &User, hero: &String, free_heros: &Vec<String>) -> bool {
+    u.owned_heros.contains(hero) || free_heros.contains(hero)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BackfillSlot {
+    pub id: String,
+    pub position: usize,
+    pub team: usize,
+    pub gid: u64,
+}
+
+// what every running game exposes, mode-specific behavior stays on the concrete type
+pub trait GameState {
+    fn teams(&self) -> &Vec<Rc<RefCell<FightGroup>>>;
+    fn user_names(&self) -> &Vec<String>;
+    fn pick_position(&self) -> &Vec<usize>;
+    fn game_id(&self) -> u64;
+    fn mode(&self) -> String;
+    fn game_status(&self) -> u16;
+    fn check_status(&self) -> PhaseKind;
+    fn time(&self) -> u64;
+    fn add_time(&mut self, time: u64);
+    fn paused(&self) -> bool;
+    fn set_paused(&mut self, paused: bool);
+    fn seed(&self) -> u64;
+    // re-enter the current phase with a full timer
+    fn restart_phase(&mut self);
+    fn status_name(&self) -> &'static str {
+        self.check_status().name()
+    }
+    fn check_loading(&self) -> bool {
+        let mut res = true;
+        for team in self.teams() {
+            if !team.borrow_mut().check_loading() {
+                res = false;
+            }
+        }
+        res
+    }
+    fn check_lock(&self) -> bool {
+        let mut res = true;
+        for team in self.teams() {
+            if !team.borrow_mut().check_lock() {
+                res = false;
+            }
+        }
+        res
+    }
+    fn trade_open(&self) -> bool {
+        self.check_status() == PhaseKind::Trade
+    }
+    // trades need both players on the same team, inside the trade window, and no banned hero
+    fn check_trade(&self, a: &User, b: &User) -> Result<(), &'static str> {
+        if self.paused() {
+            return Err("paused");
+        }
+        if !self.trade_open() {
+            return Err("not trade phase");
+        }
+        let team = self.team_of(&a.id);
+        if team.is_none() || team != self.team_of(&b.id) {
+            return Err("not teammates");
+        }
+        let bans = self.ban_list();
+        if bans.contains(&a.hero) || bans.contains(&b.hero) {
+            return Err("banned");
+        }
+        Ok(())
+    }
+    fn ban_list(&self) -> Vec<String> {
+        vec![]
+    }
+    fn team_of(&self, id: &String) -> Option<usize> {
+        self.teams().iter().position(|t| {
+            t.borrow()
+                .rooms
+                .iter()
+                .any(|r| r.borrow().users.iter().any(|u| u.borrow().id == *id))
+        })
+    }
+    fn teammates(&self, id: &String) -> Vec<String> {
+        for t in self.teams() {
+            let mut names: Vec<String> = vec![];
+            for r in &t.borrow().rooms {
+                for u in &r.borrow().users {
+                    names.push(u.borrow().id.clone());
+                }
+            }
+            if names.contains(id) {
+                return names;
+            }
+        }
+        vec![]
+    }
+    // heroes a pick may not duplicate, by default whatever the player's teammates hold
+    fn taken(&self, id: &String) -> Vec<String> {
+        let mut res: Vec<String> = vec![];
+        let team = match self.team_of(id) {
+            Some(team) => team,
+            None => return res,
+        };
+        for r in &self.teams()[team].borrow().rooms {
+            for u in &r.borrow().users {
+                if u.borrow().id != *id && u.borrow().hero != "" {
+                    res.push(u.borrow().hero.clone());
+                }
+            }
+        }
+        res
+    }
+    // whether the player may act in this phase at all, whatever the hero
+    fn validate_turn(&self, id: &String, kind: PhaseKind) -> Result<(), &'static str> {
+        if self.paused() {
+            return Err("paused");
+        }
+        if self.check_status() != kind {
+            return Err("wrong phase");
+        }
+        let position = self.user_names().iter().position(|u| u == id).ok_or("not in game")?;
+        if !self.pick_position().contains(&position) {
+            return Err("not your turn");
+        }
+        Ok(())
+    }
+    fn validate_choice(
+        &self,
+        id: &String,
+        hero: &String,
+        kind: PhaseKind,
+        heros: &BTreeMap<String, Rc<RefCell<HeroData>>>,
+    ) -> Result<(), &'static str> {
+        self.validate_turn(id, kind)?;
+        let enable = heros.get(hero).map(|h| h.borrow().enable).ok_or("unknown hero")?;
+        if !enable {
+            return Err("disabled");
+        }
+        if self.ban_list().contains(hero) {
+            return Err("banned");
+        }
+        if kind == PhaseKind::Pick && self.taken(id).contains(hero) {
+            return Err("taken");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ActiveGame {
+    NG(Rc<RefCell<NGGame>>),
+    Draft(Rc<RefCell<DraftGame>>),
+    ARAM(Rc<RefCell<ARAMGame>>),
+    CM(Rc<RefCell<CMGame>>),
+}
+
+impl ActiveGame {
+    pub fn state(&self) -> Rc<RefCell<dyn GameState>> {
+        match self {
+            ActiveGame::NG(g) => g.clone(),
+            ActiveGame::Draft(g) => g.clone(),
+            ActiveGame::ARAM(g) => g.clone(),
+            ActiveGame::CM(g) => g.clone(),
+        }
+    }
+}
+
+// ng and aram ban and pick all at once: loading, ban, pick, ready, gaming, finished
+fn simultaneous_status(game_status: u16) -> PhaseKind {
+    match game_status {
+        0 => PhaseKind::Loading,
+        1 => PhaseKind::Ban,
+        2 => PhaseKind::Pick,
+        3 => PhaseKind::ReadyToStart,
+        4 => PhaseKind::Gaming,
+        _ => PhaseKind::Finished,
+    }
+}
 
 #[derive(Clone, Debug, Default)]
 pub struct NGGame {
@@ -32,109 +218,137 @@ pub struct NGGame {
     pub ready_to_start_time: i16,
     pub pick_status: u16,
     pub time: u64,
+    pub paused: bool,
     pub ban_heros: Vec<Vec<String>>,
+    pub mode: String,
+    pub backfill: Vec<BackfillSlot>,
+    pub backfill_time: i16,
+    pub seed: u64,
 }
 
-#[derive(Clone, Debug)]
-pub enum NGGameStatus {
-    Loading,
-    Ban,
-    Pick,
-    ReadyToStart,
-    Gaming,
-    Finished,
+impl GameState for NGGame {
+    fn teams(&self) -> &Vec<Rc<RefCell<FightGroup>>> {
+        &self.teams
+    }
+    fn user_names(&self) -> &Vec<String> {
+        &self.user_names
+    }
+    fn pick_position(&self) -> &Vec<usize> {
+        &self.pick_position
+    }
+    fn game_id(&self) -> u64 {
+        self.game_id
+    }
+    fn mode(&self) -> String {
+        self.mode.clone()
+    }
+    fn game_status(&self) -> u16 {
+        self.game_status
+    }
+    fn check_status(&self) -> PhaseKind {
+        simultaneous_status(self.game_status)
+    }
+    fn time(&self) -> u64 {
+        self.time
+    }
+    fn add_time(&mut self, time: u64) {
+        self.time += time;
+    }
+    fn paused(&self) -> bool {
+        self.paused
+    }
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+    fn restart_phase(&mut self) {
+        if self.game_status > 0 {
+            self.game_status -= 1;
+            self.next_status();
+        }
+    }
+    // ng picks blind and all at once, so trades stay open while everyone readies up
+    fn trade_open(&self) -> bool {
+        self.check_status() == PhaseKind::ReadyToStart
+    }
+    fn ban_list(&self) -> Vec<String> {
+        self.ban_heros.concat()
+    }
 }
 
 impl NGGame {
-    pub fn check_status(&mut self) -> NGGameStatus {
-        let mut res = NGGameStatus::Loading;
-        if self.game_status == 0 {
-            res = NGGameStatus::Loading;
-        }
-        if self.game_status == 1 {
-            res = NGGameStatus::Ban;
-        }
-        if self.game_status == 2 {
-            res = NGGameStatus::Pick;
+    // the dropped player's slot stays empty and the draft waits until it is filled
+    pub fn open_backfill(&mut self, id: &String, gid: u64) -> Option<usize> {
+        let position = self.user_names.iter().position(|x| x == id)?;
+        let team = self.team_of(id)?;
+        self.user_names[position] = "".to_string();
+        self.backfill.push(BackfillSlot {
+            id: id.clone(),
+            position: position,
+            team: team,
+            gid: gid,
+        });
+        self.backfill_time = BACKFILL_TIME;
+        Some(position)
+    }
+    pub fn fill_backfill(&mut self, id: &String) -> Option<BackfillSlot> {
+        if self.backfill.len() == 0 {
+            return None;
         }
-        if self.game_status == 3 {
-            res = NGGameStatus::ReadyToStart;
+        let slot = self.backfill.remove(0);
+        self.user_names[slot.position] = id.clone();
+        if self.backfill.len() == 0 {
+            self.resume_phase();
         }
-        if self.game_status == 4 {
-            res = NGGameStatus::Gaming;
+        Some(slot)
+    }
+    // restart the current phase timer so the new player gets a full turn, picks already made are kept
+    fn resume_phase(&mut self) {
+        if self.game_status == 0 {
+            return;
         }
-        if self.game_status == 5 {
-            res = NGGameStatus::Finished;
+        if self.game_status > 2 {
+            self.game_status = 2;
         }
-        res
+        self.game_status -= 1;
+        self.next_status();
     }
     pub fn next_status(&mut self) {
-        let mut res = NGGameStatus::Loading;
         self.game_status += 1;
         info!(
             "NG game_id : {}, status: {}, status_code: {}, line: {}",
             self.game_id.clone(),
-            self.get_status_name(),
+            self.status_name(),
             self.game_status,
             line!()
         );
         if self.game_status == 0 {}
         if self.game_status == 1 {
             self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+            self.pick_position = (0..self.user_names.len()).collect();
         }
         if self.game_status == 2 {
             self.choose_time = NG_CHOOSE_HERO_TIME;
-            self.pick_position = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+            self.pick_position = (0..self.user_names.len()).collect();
         }
         if self.game_status == 3 {
             self.ready_to_start_time = READY_TO_START_TIME;
-            self.pick_position = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+            self.pick_position = (0..self.user_names.len()).collect();
         }
         if self.game_status == 4 {}
         if self.game_status == 5 {}
     }
-    pub fn get_status_name(&mut self) -> String {
-        let mut res = "loading";
-        if self.game_status == 0 {
-            res = "loading"
-        }
-        if self.game_status == 1 {
-            res = "ban"
-        }
-        if self.game_status == 2 {
-            res = "pick"
-        }
-        if self.game_status == 3 {
-            res = "readyToStart";
-        }
-        if self.game_status == 4 {
-            res = "gaming";
-        }
-        if self.game_status == 5 {
-            res = "finished";
-        }
-        res.to_string()
-    }
-    pub fn check_loading(&mut self) -> bool {
-        let mut res = true;
-        for team in &self.teams {
-            if !team.borrow_mut().check_loading() {
-                res = false;
-            }
-        }
-        res
-    }
-    pub fn check_lock(&mut self) -> bool {
-        let mut res = true;
-        for team in &self.teams {
-            if !team.borrow_mut().check_lock() {
-                res = false;
-            }
-        }
-        res
+    // rolls only draw from the game seed, so a stored seed replays the same bans
+    pub fn rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.seed)
     }
     pub fn rollBanHero(&mut self) {
+        let mut rng = self.rng();
+        self.rollBanHeroWith(&mut rng);
+    }
+    pub fn rollBanHeroWith(&mut self, rng: &mut StdRng) {
         let mut result: Vec<Vec<String>> = Vec::new();
         for team in &self.teams {
             let mut heros: Vec<String> = Vec::new();
@@ -146,9 +360,8 @@ impl NGGame {
             let mut done: bool = false;
             let mut team_ban: Vec<String> = Vec::new();
             while !done {
-                let mut rng = thread_rng();
                 let mut hero: String = "".to_string();
-                match heros.iter().choose(&mut rng) {
+                match heros.iter().choose(rng) {
                     None => println!("no hero, line: {}", line!()),
                     Some(h) => {
                         team_ban.push(h.to_string());
@@ -164,10 +377,33 @@ impl NGGame {
         }
         self.ban_heros = result;
     }
+    // most played hero that is still free, otherwise a seeded random one
+    pub fn auto_pick_hero(
+        &self,
+        usage: &Vec<String>,
+        taken: &Vec<String>,
+        heros: &BTreeMap<String, Rc<RefCell<HeroData>>>,
+    ) -> Option<String> {
+        let available: Vec<String> = heros
+            .iter()
+            .filter(|(name, hero)| {
+                hero.borrow().enable
+                    && !taken.contains(name)
+                    && !self.ban_heros.iter().any(|b| b.contains(name))
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        if let Some(hero) = usage.iter().find(|h| available.contains(h)) {
+            return Some(hero.clone());
+        }
+        let mut rng = self.rng();
+        available.iter().choose(&mut rng).cloned()
+    }
 }
 
+// rk and at drafts, driven by the phase list from draft_phases; game_status is the phase index
 #[derive(Clone, Debug, Default)]
-pub struct RKGame {
+pub struct DraftGame {
     pub teams: Vec<Rc<RefCell<FightGroup>>>,
     pub room_names: Vec<String>,
     pub user_names: Vec<String>,
@@ -181,119 +417,96 @@ pub struct RKGame {
     pub ready_to_start_time: i16,
     pub pick_status: u16,
     pub time: u64,
+    pub paused: bool,
     pub ban_heros: Vec<Vec<String>>,
+    pub mode: String,
+    pub phases: Vec<DraftPhase>,
+    pub trade_time: i16,
+    pub seed: u64,
 }
 
-#[derive(Clone, Debug)]
-pub enum RKGameStatus {
-    Loading,
-    Ban,
-    Pick,
-    ReadyToStart,
-    Gaming,
-    Finished,
-}
+pub type RKGame = DraftGame;
+pub type ATGame = DraftGame;
 
-impl RKGame {
-    pub fn check_status(&mut self) -> RKGameStatus {
-        let mut res = RKGameStatus::Loading;
-        if self.game_status == 0 {
-            res = RKGameStatus::Loading;
-        }
-        if self.game_status == 1 {
-            res = RKGameStatus::Ban;
-        }
-        if self.game_status == 2 {
-            res = RKGameStatus::Pick;
-        }
-        if self.game_status == 3 {
-            res = RKGameStatus::Pick;
-        }
-        if self.game_status == 4 {
-            res = RKGameStatus::Pick;
-        }
-        if self.game_status == 5 {
-            res = RKGameStatus::Pick;
-        }
-        if self.game_status == 6 {
-            res = RKGameStatus::Pick;
-        }
-        if self.game_status == 7 {
-            res = RKGameStatus::Pick;
-        }
-        if self.game_status == 8 {
-            res = RKGameStatus::ReadyToStart;
-        }
-        if self.game_status == 9 {
-            res = RKGameStatus::Gaming;
+impl GameState for DraftGame {
+    fn teams(&self) -> &Vec<Rc<RefCell<FightGroup>>> {
+        &self.teams
+    }
+    fn user_names(&self) -> &Vec<String> {
+        &self.user_names
+    }
+    fn pick_position(&self) -> &Vec<usize> {
+        &self.pick_position
+    }
+    fn game_id(&self) -> u64 {
+        self.game_id
+    }
+    fn mode(&self) -> String {
+        self.mode.clone()
+    }
+    fn game_status(&self) -> u16 {
+        self.game_status
+    }
+    fn check_status(&self) -> PhaseKind {
+        match self.phase() {
+            Some(phase) => phase.kind,
+            None => PhaseKind::Finished,
         }
-        if self.game_status == 10 {
-            res = RKGameStatus::Finished;
+    }
+    fn time(&self) -> u64 {
+        self.time
+    }
+    fn add_time(&mut self, time: u64) {
+        self.time += time;
+    }
+    fn paused(&self) -> bool {
+        self.paused
+    }
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+    fn restart_phase(&mut self) {
+        if self.game_status > 0 {
+            self.game_status -= 1;
+            self.next_status();
         }
-        res
+    }
+    fn ban_list(&self) -> Vec<String> {
+        self.ban_heros.concat()
+    }
+}
+
+impl DraftGame {
+    pub fn phase(&self) -> Option<&DraftPhase> {
+        self.phases.get(self.game_status as usize)
     }
 
     pub fn next_status(&mut self) {
         self.game_status += 1;
         info!(
-            "RK game_id : {}, status: {}, line: {}",
+            "{} game_id : {}, status: {}, line: {}",
+            self.mode,
             self.game_id,
             self.game_status,
             line!()
         );
-        if self.game_status == 0 {}
-        if self.game_status == 1 {
-            self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
-        }
-        if self.game_status == 2 {
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![0];
+        let phase = match self.phase() {
+            Some(phase) => phase.clone(),
+            None => return,
+        };
+        match phase.kind {
+            PhaseKind::Ban => self.ban_time = phase.time,
+            PhaseKind::Pick => self.choose_time = phase.time,
+            PhaseKind::Trade => self.trade_time = phase.time,
+            PhaseKind::ReadyToStart => self.ready_to_start_time = phase.time,
+            _ => {}
+        }
+        if !phase.positions.is_empty() {
+            self.pick_position = phase.positions;
         }
-        if self.game_status == 3 {
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![5, 6];
-        }
-        if self.game_status == 4 {
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![1, 2];
-        }
-        if self.game_status == 5 {
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![7, 8];
-        }
-        if self.game_status == 6 {
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![3, 4];
-        }
-        if self.game_status == 7 {
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![9];
-        }
-        if self.game_status == 8 {
-            self.ready_to_start_time = READY_TO_START_TIME;
-            self.pick_position = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
-        }
-        if self.game_status == 9 {}
-        if self.game_status == 10 {}
-    }
-    pub fn check_loading(&mut self) -> bool {
-        let mut res = true;
-        for team in &self.teams {
-            if !team.borrow_mut().check_loading() {
-                res = false;
-            }
-        }
-        res
-    }
-    pub fn check_lock(&mut self) -> bool {
-        let mut res = true;
-        for team in &self.teams {
-            if !team.borrow_mut().check_lock() {
-                res = false;
-            }
-        }
-        res
     }
     pub fn updateBanHeros(&mut self) {
         let mut result: Vec<Vec<String>> = Vec::new();
@@ -309,8 +522,9 @@ impl RKGame {
     }
 }
 
+// captain's mode, walks the cm phase list like DraftGame but a captain acts for the whole team
 #[derive(Clone, Debug, Default)]
-pub struct ATGame {
+pub struct CMGame {
     pub teams: Vec<Rc<RefCell<FightGroup>>>,
     pub room_names: Vec<String>,
     pub user_names: Vec<String>,
@@ -319,257 +533,226 @@ pub struct ATGame {
     pub user_count: u16,
     pub winteam: i16,
     pub game_status: u16,
-    pub choose_time: i16,
-    pub ban_time: i16,
     pub ready_to_start_time: i16,
-    pub pick_status: u16,
+    pub trade_time: i16,
     pub time: u64,
+    pub paused: bool,
+    pub mode: String,
+    pub captains: Vec<String>,
+    pub phases: Vec<DraftPhase>,
+    pub turn_time: i16,
+    pub reserve_time: Vec<i16>,
     pub ban_heros: Vec<Vec<String>>,
+    pub pick_heros: Vec<Vec<String>>,
+    pub TotalHeros: BTreeMap<String, Rc<RefCell<HeroData>>>,
+    pub free_heros: Vec<String>,
+    pub seed: u64,
 }
 
-#[derive(Clone, Debug)]
-pub enum ATGameStatus {
-    Loading,
-    Ban,
-    Pick,
-    ReadyToStart,
-    Gaming,
-    Finished,
+impl GameState for CMGame {
+    fn teams(&self) -> &Vec<Rc<RefCell<FightGroup>>> {
+        &self.teams
+    }
+    fn user_names(&self) -> &Vec<String> {
+        &self.user_names
+    }
+    fn pick_position(&self) -> &Vec<usize> {
+        &self.pick_position
+    }
+    fn game_id(&self) -> u64 {
+        self.game_id
+    }
+    fn mode(&self) -> String {
+        self.mode.clone()
+    }
+    fn game_status(&self) -> u16 {
+        self.game_status
+    }
+    fn check_status(&self) -> PhaseKind {
+        match self.phase() {
+            Some(phase) => phase.kind,
+            None => PhaseKind::Finished,
+        }
+    }
+    fn time(&self) -> u64 {
+        self.time
+    }
+    fn add_time(&mut self, time: u64) {
+        self.time += time;
+    }
+    fn paused(&self) -> bool {
+        self.paused
+    }
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+    fn restart_phase(&mut self) {
+        self.enter_phase();
+    }
+    fn ban_list(&self) -> Vec<String> {
+        self.ban_heros.concat()
+    }
+    // the captain picks for the whole team, so neither team may repeat a pick
+    fn taken(&self, id: &String) -> Vec<String> {
+        self.pick_heros.concat()
+    }
 }
 
-impl ATGame {
-    pub fn check_status(&mut self) -> ATGameStatus {
-        let mut res = ATGameStatus::Loading;
-        if self.game_status == 0 {
-            res = ATGameStatus::Loading;
-        }
-        if self.game_status == 1 {
-            res = ATGameStatus::Ban;
-        }
-        if self.game_status == 2 {
-            res = ATGameStatus::Ban;
-        }
-        if self.game_status == 3 {
-            res = ATGameStatus::Ban;
-        }
-        if self.game_status == 4 {
-            res = ATGameStatus::Ban;
-        }
-        if self.game_status == 5 {
-            res = ATGameStatus::Ban;
-        }
-        if self.game_status == 6 {
-            res = ATGameStatus::Ban;
-        }
-        if self.game_status == 7 {
-            res = ATGameStatus::Pick;
-        }
-        if self.game_status == 8 {
-            res = ATGameStatus::Pick;
-        }
-        if self.game_status == 9 {
-            res = ATGameStatus::Pick;
-        }
-        if self.game_status == 10 {
-            res = ATGameStatus::Pick;
-        }
-        if self.game_status == 11 {
-            res = ATGameStatus::Pick;
-        }
-        if self.game_status == 12 {
-            res = ATGameStatus::Pick;
-        }
-        if self.game_status == 13 {
-            res = ATGameStatus::Ban;
-        }
-        if self.game_status == 14 {
-            res = ATGameStatus::Ban;
-        }
-        if self.game_status == 15 {
-            res = ATGameStatus::Ban;
-        }
-        if self.game_status == 16 {
-            res = ATGameStatus::Ban;
-        }
-        if self.game_status == 17 {
-            res = ATGameStatus::Pick;
+impl CMGame {
+    pub fn phase(&self) -> Option<&DraftPhase> {
+        self.phases.get(self.game_status as usize)
+    }
+    fn team_users(&self, team: usize) -> Vec<Rc<RefCell<User>>> {
+        let mut res: Vec<Rc<RefCell<User>>> = vec![];
+        for r in &self.teams[team].borrow().rooms {
+            for u in &r.borrow().users {
+                res.push(u.clone());
+            }
         }
-        if self.game_status == 18 {
-            res = ATGameStatus::Pick;
+        res
+    }
+    // a party that fills the whole team keeps its master, otherwise the highest rated player leads
+    pub fn choose_captains(&mut self) {
+        let mut captains: Vec<String> = vec![];
+        for (i, team) in self.teams.iter().enumerate() {
+            let team = team.borrow();
+            if team.rooms.len() == 1 {
+                captains.push(team.rooms[0].borrow().master.clone());
+                continue;
+            }
+            let captain = self
+                .team_users(i)
+                .iter()
+                .max_by_key(|u| u.borrow().rk)
+                .map_or("".to_string(), |u| u.borrow().id.clone());
+            captains.push(captain);
+        }
+        self.captains = captains;
+    }
+    pub fn turn_team(&self) -> usize {
+        self.phase().and_then(|p| p.captain).unwrap_or(0)
+    }
+    // captains pick for the team, so the captain has to own the hero like any other picker
+    fn captain_can_play(&self, team: usize, hero: &String) -> bool {
+        self.team_users(team)
+            .iter()
+            .filter(|u| self.captains.get(team) == Some(&u.borrow().id))
+            .any(|u| can_play(&u.borrow(), hero, &self.free_heros))
+    }
+    fn is_taken(&self, hero: &String) -> bool {
+        self.ban_heros.iter().any(|b| b.contains(hero)) || self.pick_heros.iter().any(|p| p.contains(hero))
+    }
+    pub fn captain_choose(&mut self, id: &String, hero: &String, kind: PhaseKind) -> Result<usize, &'static str> {
+        let team = self.turn_team();
+        if self.phase().and_then(|p| p.captain).is_none() || self.captains.get(team) != Some(id) {
+            return Err("not captain");
         }
-        if self.game_status == 19 {
-            res = ATGameStatus::Pick;
+        self.validate_choice(id, hero, kind, &self.TotalHeros)?;
+        if self.is_taken(hero) {
+            return Err("taken");
         }
-        if self.game_status == 20 {
-            res = ATGameStatus::Pick;
+        if kind == PhaseKind::Pick && !self.captain_can_play(team, hero) {
+            return Err("not owned");
         }
-        if self.game_status == 21 {
-            res = ATGameStatus::ReadyToStart;
+        match kind {
+            PhaseKind::Ban => self.ban_heros[team].push(hero.clone()),
+            _ => self.pick_heros[team].push(hero.clone()),
         }
-        if self.game_status == 22 {
-            res = ATGameStatus::Gaming;
+        self.next_status();
+        Ok(team)
+    }
+    // one second of the turn clock, the team's reserve only runs once the turn time is used up,
+    // true when both are spent and the turn times out
+    pub fn tick_turn(&mut self) -> bool {
+        let team = self.turn_team();
+        if self.turn_time > 0 {
+            self.turn_time -= 1;
+            return false;
+        }
+        self.reserve_time[team] -= 1;
+        if self.reserve_time[team] < 0 {
+            self.reserve_time[team] = 0;
+            return true;
+        }
+        false
+    }
+    // a captain out of reserve time skips the ban or gets a seeded random pick
+    pub fn timeout_turn(&mut self) {
+        if self.check_status() == PhaseKind::Pick {
+            let team = self.turn_team();
+            let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(self.game_status as u64));
+            let hero = self
+                .TotalHeros
+                .iter()
+                .filter(|(name, hero)| {
+                    hero.borrow().enable && !self.is_taken(name) && self.captain_can_play(team, name)
+                })
+                .map(|(name, _)| name.clone())
+                .choose(&mut rng);
+            if let Some(hero) = hero {
+                self.pick_heros[team].push(hero);
+            }
         }
-        if self.game_status == 23 {
-            res = ATGameStatus::Finished;
+        self.next_status();
+    }
+    // picks and bans go to the team in slot order, players can still swap picks afterwards
+    fn assign_heros(&mut self) {
+        for team in 0..self.teams.len() {
+            for (u, hero) in self.team_users(team).iter().zip(self.pick_heros[team].iter()) {
+                u.borrow_mut().hero = hero.clone();
+                u.borrow_mut().isLocked = true;
+            }
+            for (u, hero) in self.team_users(team).iter().zip(self.ban_heros[team].iter()) {
+                u.borrow_mut().ban_hero = hero.clone();
+            }
         }
-        res
     }
     pub fn next_status(&mut self) {
-        // let mut res = ATGameStatus::Loading;
         self.game_status += 1;
         info!(
-            "AT game_id : {}, status: {}, line: {}",
+            "CM game_id : {}, status: {}, status_code: {}, line: {}",
             self.game_id,
+            self.status_name(),
             self.game_status,
             line!()
         );
-        if self.game_status == 0 {
-            // res = ATGameStatus::Loading;
-        }
-        if self.game_status == 1 {
-            // res = ATGameStatus::Ban;
-            self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![0];
-        }
-        if self.game_status == 2 {
-            // res = ATGameStatus::Ban;
-            self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![5];
-        }
-        if self.game_status == 3 {
-            // res = ATGameStatus::Ban;
-            self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![1];
-        }
-        if self.game_status == 4 {
-            // res = ATGameStatus::Ban;
-            self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![6];
-        }
-        if self.game_status == 5 {
-            // res = ATGameStatus::Ban;
-            self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![2];
-        }
-        if self.game_status == 6 {
-            // res = ATGameStatus::Ban;
-            self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![7];
-        }
-        if self.game_status == 7 {
-            // res = ATGameStatus::Pick;
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![0];
-        }
-        if self.game_status == 8 {
-            // res = ATGameStatus::Pick;
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![5];
-        }
-        if self.game_status == 9 {
-            // res = ATGameStatus::Pick;
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![6];
-        }
-        if self.game_status == 10 {
-            // res = ATGameStatus::Pick;
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![1];
-        }
-        if self.game_status == 11 {
-            // res = ATGameStatus::Pick;
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![2];
-        }
-        if self.game_status == 12 {
-            // res = ATGameStatus::Pick;
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![7];
-        }
-        if self.game_status == 13 {
-            // res = ATGameStatus::Ban;
-            self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![8];
-        }
-        if self.game_status == 14 {
-            // res = ATGameStatus::Ban;
-            self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![3];
-        }
-        if self.game_status == 15 {
-            // res = ATGameStatus::Ban;
-            self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![9];
-        }
-        if self.game_status == 16 {
-            // res = ATGameStatus::Ban;
-            self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![4];
-        }
-        if self.game_status == 17 {
-            // res = ATGameStatus::Pick;
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![8];
-        }
-        if self.game_status == 18 {
-            // res = ATGameStatus::Pick;
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![3];
-        }
-        if self.game_status == 19 {
-            // res = ATGameStatus::Pick;
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![4];
-        }
-        if self.game_status == 20 {
-            // res = ATGameStatus::Pick;
-            self.choose_time = CHOOSE_HERO_TIME;
-            self.pick_position = vec![9];
-        }
-        if self.game_status == 21 {
-            self.ready_to_start_time = READY_TO_START_TIME;
-            self.pick_position = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
-            // res = ATGameStatus::ReadyToStart;
-        }
-        if self.game_status == 22 {
-            // res = ATGameStatus::Gaming;
-        }
-        if self.game_status == 23 {
-            // res = ATGameStatus::Finished;
-        }
-        // res
+        if self.captains.is_empty() {
+            self.choose_captains();
+            self.reserve_time = vec![CM_RESERVE_TIME; self.teams.len()];
+            self.ban_heros = vec![vec![]; self.teams.len()];
+            self.pick_heros = vec![vec![]; self.teams.len()];
+        }
+        let last = self.game_status as usize - 1;
+        let drafting = |p: Option<&DraftPhase>| p.map_or(false, |p| p.captain.is_some());
+        if drafting(self.phases.get(last)) && !drafting(self.phase()) {
+            self.assign_heros();
+        }
+        self.enter_phase();
     }
-    pub fn check_loading(&mut self) -> bool {
-        let mut res = true;
-        for team in &self.teams {
-            if !team.borrow_mut().check_loading() {
-                res = false;
-            }
+    // a captain's turn is for the captain alone, the other phases use the positions in the list
+    fn enter_phase(&mut self) {
+        let phase = match self.phase() {
+            Some(phase) => phase.clone(),
+            None => return,
+        };
+        match phase.kind {
+            PhaseKind::Trade => self.trade_time = phase.time,
+            PhaseKind::ReadyToStart => self.ready_to_start_time = phase.time,
+            PhaseKind::Ban | PhaseKind::Pick => self.turn_time = phase.time,
+            _ => {}
+        }
+        if let Some(team) = phase.captain {
+            let captain = self.captains[team].clone();
+            self.pick_position = self.user_names.iter().position(|u| *u == captain).into_iter().collect();
+        } else if !phase.positions.is_empty() {
+            self.pick_position = phase.positions;
         }
-        res
-    }
-    pub fn check_lock(&mut self) -> bool {
-        let mut res = true;
-        for team in &self.teams {
-            if !team.borrow_mut().check_lock() {
-                res = false;
-            }
-        }
-        res
-    }
-    pub fn updateBanHeros(&mut self) {
-        let mut result: Vec<Vec<String>> = Vec::new();
-        for team in &self.teams {
-            let mut heros: Vec<String> = Vec::new();
-            for hero in team.borrow_mut().get_users_ban_hero() {
-                println!("{}", hero);
-                heros.push(hero);
-            }
-            result.push(heros);
-        }
-        self.ban_heros = result;
     }
 }
+
 #[derive(Clone, Debug, Default)]
 pub struct ARAMGame {
     pub teams: Vec<Rc<RefCell<FightGroup>>>,
@@ -585,110 +768,115 @@ pub struct ARAMGame {
     pub ready_to_start_time: i16,
     pub pick_status: u16,
     pub time: u64,
+    pub paused: bool,
     pub heros: Vec<String>,
     pub TotalHeros: BTreeMap<String, Rc<RefCell<HeroData>>>,
+    pub seed: u64,
+    pub pool: Vec<String>,
+    pub bench: Vec<Vec<String>>,
+    pub rerolls: u64,
+    pub spent: BTreeMap<String, i16>,
 }
 
-#[derive(Clone, Debug)]
-pub enum ARAMGameStatus {
-    Loading,
-    Ban,
-    Pick,
-    ReadyToStart,
-    Gaming,
-    Finished,
-}
-
-impl ARAMGame {
-    pub fn check_status(&mut self) -> ARAMGameStatus {
-        let mut res = ARAMGameStatus::Loading;
-        if self.game_status == 0 {
-            res = ARAMGameStatus::Loading;
-        }
-        if self.game_status == 1 {
-            res = ARAMGameStatus::Ban;
-        }
+impl GameState for ARAMGame {
+    fn teams(&self) -> &Vec<Rc<RefCell<FightGroup>>> {
+        &self.teams
+    }
+    fn user_names(&self) -> &Vec<String> {
+        &self.user_names
+    }
+    fn pick_position(&self) -> &Vec<usize> {
+        &self.pick_position
+    }
+    fn game_id(&self) -> u64 {
+        self.game_id
+    }
+    fn mode(&self) -> String {
+        "aram".to_string()
+    }
+    fn game_status(&self) -> u16 {
+        self.game_status
+    }
+    fn check_status(&self) -> PhaseKind {
+        simultaneous_status(self.game_status)
+    }
+    fn time(&self) -> u64 {
+        self.time
+    }
+    fn add_time(&mut self, time: u64) {
+        self.time += time;
+    }
+    fn paused(&self) -> bool {
+        self.paused
+    }
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+    // tokens spent in a reset pick phase are given back
+    fn restart_phase(&mut self) {
         if self.game_status == 2 {
-            res = ARAMGameStatus::Pick;
-        }
-        if self.game_status == 3 {
-            res = ARAMGameStatus::ReadyToStart;
-        }
-        if self.game_status == 4 {
-            res = ARAMGameStatus::Gaming;
+            self.refund_rerolls();
         }
-        if self.game_status == 5 {
-            res = ARAMGameStatus::Finished;
+        if self.game_status > 0 {
+            self.game_status -= 1;
+            self.next_status();
         }
-        res
     }
+    // dealt heroes can change hands while players reroll and until the game starts
+    fn trade_open(&self) -> bool {
+        let status = self.check_status();
+        status == PhaseKind::Pick || status == PhaseKind::ReadyToStart
+    }
+    // aram heroes are dealt, players change them through rerolls and the bench
+    fn validate_choice(
+        &self,
+        id: &String,
+        hero: &String,
+        kind: PhaseKind,
+        heros: &BTreeMap<String, Rc<RefCell<HeroData>>>,
+    ) -> Result<(), &'static str> {
+        Err("random pool")
+    }
+}
+
+impl ARAMGame {
     pub fn next_status(&mut self) {
-        let mut res = ARAMGameStatus::Loading;
         self.game_status += 1;
         info!(
             "NG game_id : {}, status: {}, status_code: {}, line: {}",
             self.game_id.clone(),
-            self.get_status_name(),
+            self.status_name(),
             self.game_status,
             line!()
         );
         if self.game_status == 0 {}
         if self.game_status == 1 {
             self.ban_time = BAN_HERO_TIME;
-            self.pick_position = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+            self.pick_position = (0..self.user_names.len()).collect();
         }
         if self.game_status == 2 {
-            self.choose_time = NG_CHOOSE_HERO_TIME;
-            self.pick_position = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+            self.choose_time = ARAM_PICK_TIME;
+            self.pick_position = (0..self.user_names.len()).collect();
         }
         if self.game_status == 3 {
             self.ready_to_start_time = ARAM_READY_TO_START_TIME;
-            self.pick_position = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+            self.pick_position = (0..self.user_names.len()).collect();
+            self.spent.clear();
         }
         if self.game_status == 4 {}
         if self.game_status == 5 {}
     }
-    pub fn get_status_name(&mut self) -> String {
-        let mut res = "loading";
-        if self.game_status == 0 {
-            res = "loading"
-        }
-        if self.game_status == 1 {
-            res = "ban"
-        }
-        if self.game_status == 2 {
-            res = "pick"
-        }
-        if self.game_status == 3 {
-            res = "readyToStart";
-        }
-        if self.game_status == 4 {
-            res = "gaming";
-        }
-        if self.game_status == 5 {
-            res = "finished";
-        }
-        res.to_string()
-    }
-    pub fn check_loading(&mut self) -> bool {
-        let mut res = true;
-        for team in &self.teams {
-            if !team.borrow_mut().check_loading() {
-                res = false;
-            }
-        }
-        res
-    }
-    pub fn check_lock(&mut self) -> bool {
-        let mut res = true;
-        for team in &self.teams {
-            if !team.borrow_mut().check_lock() {
-                res = false;
-            }
-        }
-        res
+    pub fn rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.seed)
     }
     pub fn rollHeros(&mut self) {
+        let mut rng = self.rng();
+        self.rollHerosWith(&mut rng);
+    }
+    pub fn rollHerosWith(&mut self, rng: &mut StdRng) {
         let mut result: Vec<String> = Vec::new();
         let mut heros: Vec<String> = Vec::new();
         for (name, hero) in &self.TotalHeros {
@@ -701,14 +889,14 @@ impl ARAMGame {
                 for user in &room.borrow().users {
                     let mut done: bool = false;
                     while !done {
-                        let mut rng = thread_rng();
                         let mut hero: String = "".to_string();
-                        match heros.iter().choose(&mut rng) {
+                        match heros.iter().choose(rng) {
                             None => println!("no hero, line: {}", line!()),
                             Some(h) => {
                                 done = true;
                                 hero = h.to_string();
                                 user.borrow_mut().hero = hero.clone();
+                                result.push(hero.clone());
                             },
                         }
                         heros.retain(|h| *h != hero);
@@ -717,5 +905,355 @@ impl ARAMGame {
             }
         }
         self.heros = result;
+        self.pool = heros;
+        self.bench = vec![vec![]; self.teams.len()];
+    }
+    fn find_user(&self, id: &String) -> Option<(usize, Rc<RefCell<User>>)> {
+        for (i, team) in self.teams.iter().enumerate() {
+            for room in &team.borrow().rooms {
+                for user in &room.borrow().users {
+                    if user.borrow().id == *id {
+                        return Some((i, user.clone()));
+                    }
+                }
+            }
+        }
+        None
+    }
+    // the old hero goes to the team bench, every reroll has its own seed so replays stay stable
+    pub fn reroll(&mut self, id: &String) -> Result<(usize, String), &'static str> {
+        if self.game_status != 2 {
+            return Err("not pick");
+        }
+        if self.paused {
+            return Err("paused");
+        }
+        let (team, user) = self.find_user(id).ok_or("not in game")?;
+        if user.borrow().reroll <= 0 {
+            return Err("no token");
+        }
+        self.rerolls += 1;
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(self.rerolls));
+        let hero = self.pool.iter().choose(&mut rng).cloned().ok_or("no hero")?;
+        self.pool.retain(|h| *h != hero);
+        let old = user.borrow().hero.clone();
+        if old != "" {
+            self.heros.retain(|h| *h != old);
+            self.bench[team].push(old);
+        }
+        self.heros.push(hero.clone());
+        user.borrow_mut().hero = hero.clone();
+        user.borrow_mut().reroll -= 1;
+        *self.spent.entry(id.clone()).or_insert(0) += 1;
+        Ok((team, hero))
+    }
+    fn refund_rerolls(&mut self) {
+        for (id, spent) in std::mem::replace(&mut self.spent, BTreeMap::new()) {
+            if let Some((_, user)) = self.find_user(&id) {
+                user.borrow_mut().reroll += spent;
+            }
+        }
+    }
+    pub fn take_bench(&mut self, id: &String, hero: &String) -> Result<usize, &'static str> {
+        if self.game_status != 2 {
+            return Err("not pick");
+        }
+        if self.paused {
+            return Err("paused");
+        }
+        let (team, user) = self.find_user(id).ok_or("not in game")?;
+        let index = self.bench[team].iter().position(|h| h == hero).ok_or("not on bench")?;
+        let old = user.borrow().hero.clone();
+        self.bench[team].remove(index);
+        if old != "" {
+            self.heros.retain(|h| *h != old);
+            self.bench[team].push(old);
+        }
+        self.heros.push(hero.clone());
+        user.borrow_mut().hero = hero.clone();
+        Ok(team)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_teams(size: usize) -> (Vec<Rc<RefCell<FightGroup>>>, Vec<String>) {
+        let mut teams = vec![];
+        let mut names = vec![];
+        for t in 0..2 {
+            let mut g = FightGroup::default();
+            for i in 0..size {
+                let id = format!("p{}_{}", t, i);
+                let u = User {
+                    id: id.clone(),
+                    ban_hero: format!("hero{}", t * size + i),
+                    reroll: 1,
+                    ..Default::default()
+                };
+                let mut r = RoomData {
+                    rid: (t * size + i + 1) as u64,
+                    users: vec![],
+                    master: id.clone(),
+                    last_master: "".to_owned(),
+                    avg_ng: 0,
+                    avg_rk: 0,
+                    avg_at: 0,
+                    avg_aram: 0,
+                    ready: 0,
+                    queue_cnt: 0,
+                    mode: "ng".to_string(),
+                    queue_modes: vec![],
+                };
+                r.add_user(Rc::new(RefCell::new(u)));
+                g.add_room(Rc::new(RefCell::new(r)));
+                names.push(id);
+            }
+            teams.push(Rc::new(RefCell::new(g)));
+        }
+        (teams, names)
+    }
+
+    fn new_heros(n: usize) -> BTreeMap<String, Rc<RefCell<HeroData>>> {
+        (0..n)
+            .map(|i| {
+                let name = format!("hero{}", i);
+                (name.clone(), Rc::new(RefCell::new(HeroData { name: name, enable: true })))
+            })
+            .collect()
+    }
+
+    fn phases(flow: &str) -> Vec<DraftPhase> {
+        draft_phases(&default_draft_formats(), flow, 5, 2).unwrap()
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_seed_replays_rolls() {
+        let (teams, names) = new_teams(5);
+        let mut ng = NGGame { seed: 42, teams: teams, user_names: names, ..Default::default() };
+        ng.rollBanHero();
+        let bans = ng.ban_heros.clone();
+        assert_eq!(bans.len(), 2);
+        ng.rollBanHero();
+        assert_eq!(ng.ban_heros, bans);
+
+        let (teams, names) = new_teams(5);
+        let mut aram = ARAMGame { seed: 42, teams: teams, user_names: names, TotalHeros: new_heros(30), ..Default::default() };
+        aram.rollHeros();
+        let heros = aram.heros.clone();
+        let pool = aram.pool.clone();
+        assert_eq!(heros.len(), 10);
+        aram.rollHeros();
+        assert_eq!(aram.heros, heros);
+        assert_eq!(aram.pool, pool);
+        aram.seed = 43;
+        aram.rollHeros();
+        assert_ne!(aram.heros, heros);
+    }
+
+    #[test]
+    fn test_auto_pick_hero() {
+        let heros = new_heros(6);
+        heros["hero5"].borrow_mut().enable = false;
+        let mut ng = NGGame { seed: 7, ban_heros: vec![vec!["hero0".to_string()], vec![]], ..Default::default() };
+        let usage: Vec<String> = vec!["hero0", "hero5", "hero1", "hero2"].iter().map(|h| h.to_string()).collect();
+        let taken = vec!["hero1".to_string()];
+        // banned, disabled and taken heroes are skipped in usage order
+        assert_eq!(ng.auto_pick_hero(&usage, &taken, &heros), Some("hero2".to_string()));
+        // without usage the seeded fallback stays within the free heroes and replays
+        let pick = ng.auto_pick_hero(&vec![], &taken, &heros).unwrap();
+        assert!(pick == "hero2" || pick == "hero3" || pick == "hero4");
+        assert_eq!(ng.auto_pick_hero(&vec![], &taken, &heros), Some(pick));
+        ng.ban_heros = vec![vec!["hero0".to_string(), "hero2".to_string()], vec!["hero3".to_string(), "hero4".to_string()]];
+        assert_eq!(ng.auto_pick_hero(&usage, &taken, &heros), None);
+    }
+
+    #[test]
+    fn test_captain_choose() {
+        let (teams, names) = new_teams(5);
+        let heros = new_heros(20);
+        let mut cm = CMGame {
+            seed: 7,
+            teams: teams,
+            user_names: names,
+            free_heros: heros.keys().cloned().collect(),
+            TotalHeros: heros,
+            phases: phases("cm"),
+            ..Default::default()
+        };
+        cm.next_status();
+        let captains = cm.captains.clone();
+        assert_eq!(captains.len(), 2);
+        let other = cm.team_users(0).iter().map(|u| u.borrow().id.clone()).find(|id| *id != captains[0]).unwrap();
+        assert_eq!(cm.captain_choose(&other, &"hero0".to_string(), PhaseKind::Ban), Err("not captain"));
+        assert_eq!(cm.captain_choose(&captains[1], &"hero0".to_string(), PhaseKind::Ban), Err("not captain"));
+        assert_eq!(cm.captain_choose(&captains[0], &"hero0".to_string(), PhaseKind::Pick), Err("wrong phase"));
+        let turns: Vec<(PhaseKind, usize)> =
+            cm.phases.iter().filter_map(|p| p.captain.map(|team| (p.kind, team))).collect();
+        assert_eq!(turns.len(), 20);
+        for (i, (kind, team)) in turns.iter().enumerate() {
+            assert_eq!(cm.game_status as usize, i + 1);
+            assert_eq!(cm.turn_team(), *team);
+            assert_eq!(cm.check_status(), *kind);
+            let hero = format!("hero{}", i);
+            assert_eq!(cm.captain_choose(&captains[1 - *team], &hero, *kind), Err("not captain"));
+            // a hero already banned or picked by either team is gone for both
+            if i > 0 {
+                let reason = if turns[i - 1].0 == PhaseKind::Ban { "banned" } else { "taken" };
+                assert_eq!(cm.captain_choose(&captains[*team], &format!("hero{}", i - 1), *kind), Err(reason));
+            }
+            assert_eq!(cm.captain_choose(&captains[*team], &hero, *kind), Ok(*team));
+        }
+        assert_eq!(cm.check_status(), PhaseKind::Trade);
+        assert_eq!(cm.captain_choose(&captains[0], &"hero30".to_string(), PhaseKind::Pick), Err("not captain"));
+        for team in 0..2 {
+            let users = cm.team_users(team);
+            for (u, hero) in users.iter().zip(cm.pick_heros[team].iter()) {
+                assert_eq!(u.borrow().hero, *hero);
+            }
+            for (u, hero) in users.iter().zip(cm.ban_heros[team].iter()) {
+                assert_eq!(u.borrow().ban_hero, *hero);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spent_reserve_keeps_turn_time() {
+        let (teams, names) = new_teams(5);
+        let mut cm = CMGame { teams: teams, user_names: names, TotalHeros: new_heros(20), phases: phases("cm"), ..Default::default() };
+        cm.next_status();
+        let ticks = |cm: &mut CMGame| (1..).find(|_| cm.tick_turn()).unwrap();
+        cm.reserve_time[0] = 2;
+        assert_eq!(ticks(&mut cm), CM_TURN_TIME as usize + 3);
+        cm.timeout_turn();
+        assert_eq!(cm.reserve_time[0], 0);
+        let captain = cm.captains[1].clone();
+        assert_eq!(cm.captain_choose(&captain, &"hero0".to_string(), PhaseKind::Ban), Ok(1));
+        // the spent reserve is not charged before the next turn's own time runs out
+        assert_eq!(cm.turn_team(), 0);
+        assert_eq!(cm.turn_time, CM_TURN_TIME);
+        assert_eq!(ticks(&mut cm), CM_TURN_TIME as usize + 1);
+    }
+
+    #[test]
+    fn test_reroll_and_bench() {
+        let (teams, names) = new_teams(2);
+        let mut aram = ARAMGame { seed: u64::max_value(), teams: teams, user_names: names, TotalHeros: new_heros(6), ..Default::default() };
+        aram.rollHeros();
+        let id = "p0_0".to_string();
+        let mate = "p0_1".to_string();
+        assert_eq!(aram.reroll(&id), Err("not pick"));
+        aram.next_status();
+        aram.next_status();
+        let (_, user) = aram.find_user(&id).unwrap();
+        let first = user.borrow().hero.clone();
+        let (team, hero) = aram.reroll(&id).unwrap();
+        assert_eq!(team, 0);
+        assert_ne!(hero, first);
+        assert_eq!(user.borrow().hero, hero);
+        assert_eq!(user.borrow().reroll, 0);
+        assert_eq!(aram.bench[0], vec![first.clone()]);
+        assert!(!aram.pool.contains(&hero));
+        assert!(aram.heros.contains(&hero) && !aram.heros.contains(&first));
+        assert_eq!(aram.reroll(&id), Err("no token"));
+        // a teammate takes the benched hero and leaves theirs on the bench
+        let (_, other) = aram.find_user(&mate).unwrap();
+        let theirs = other.borrow().hero.clone();
+        assert_eq!(aram.take_bench(&mate, &first), Ok(0));
+        assert_eq!(other.borrow().hero, first);
+        assert_eq!(aram.bench[0], vec![theirs.clone()]);
+        assert_eq!(aram.take_bench(&"p1_0".to_string(), &theirs), Err("not on bench"));
+        assert_eq!(aram.take_bench(&mate, &"hero9".to_string()), Err("not on bench"));
+        // the last free hero is dealt, then the pool runs dry
+        let (_, enemy) = aram.find_user(&"p1_0".to_string()).unwrap();
+        enemy.borrow_mut().reroll = 5;
+        let (_, last) = aram.reroll(&"p1_0".to_string()).unwrap();
+        assert!(aram.pool.is_empty());
+        assert_eq!(aram.reroll(&"p1_0".to_string()), Err("no hero"));
+        assert_eq!(enemy.borrow().hero, last);
+        assert_eq!(enemy.borrow().reroll, 4);
+    }
+
+    fn assert_trade(closed: &dyn GameState, open: &dyn GameState) {
+        let a = User { id: "p0_0".to_string(), hero: "heroA".to_string(), ..Default::default() };
+        let b = User { id: "p0_1".to_string(), hero: "heroB".to_string(), ..Default::default() };
+        let enemy = User { id: "p1_0".to_string(), hero: "heroC".to_string(), ..Default::default() };
+        assert_eq!(closed.check_trade(&a, &b), Err("not trade phase"), "{}", closed.mode());
+        assert_eq!(open.check_trade(&a, &b), Ok(()), "{}", open.mode());
+        assert_eq!(open.check_trade(&a, &enemy), Err("not teammates"), "{}", open.mode());
+    }
+
+    #[test]
+    fn test_trade_in_every_mode() {
+        let (teams, user_names) = new_teams(5);
+        let ng = |game_status| NGGame { teams: teams.clone(), user_names: user_names.clone(), game_status, ..Default::default() };
+        assert_trade(&ng(2), &ng(3));
+        let aram = |game_status| ARAMGame { teams: teams.clone(), user_names: user_names.clone(), game_status, ..Default::default() };
+        assert_trade(&aram(1), &aram(2));
+        assert_trade(&aram(4), &aram(3));
+        let draft = |flow, game_status| DraftGame { teams: teams.clone(), user_names: user_names.clone(), phases: phases(flow), game_status, ..Default::default() };
+        assert_trade(&draft("rk", 7), &draft("rk", 8));
+        assert_trade(&draft("at", 20), &draft("at", 21));
+        let cm = |game_status| CMGame { teams: teams.clone(), user_names: user_names.clone(), phases: phases("cm"), game_status, ..Default::default() };
+        assert_trade(&cm(20), &cm(21));
+    }
+
+    #[test]
+    fn test_validate_choice() {
+        let (teams, user_names) = new_teams(2);
+        let mut heros = new_heros(6);
+        heros.get("hero5").unwrap().borrow_mut().enable = false;
+        teams[0].borrow().rooms[1].borrow().users[0].borrow_mut().hero = "hero3".to_string();
+        let mut ng = NGGame { teams: teams, user_names: user_names, game_status: 2, ban_heros: vec![vec!["hero4".to_string()]], ..Default::default() };
+        ng.pick_position = vec![0, 1];
+        let id = "p0_0".to_string();
+        let pick = |ng: &NGGame, hero: &str, kind| ng.validate_choice(&id, &hero.to_string(), kind, &heros);
+        assert_eq!(pick(&ng, "hero0", PhaseKind::Pick), Ok(()));
+        assert_eq!(pick(&ng, "hero0", PhaseKind::Ban), Err("wrong phase"));
+        assert_eq!(pick(&ng, "hero4", PhaseKind::Pick), Err("banned"));
+        assert_eq!(pick(&ng, "hero3", PhaseKind::Pick), Err("taken"));
+        assert_eq!(pick(&ng, "hero5", PhaseKind::Pick), Err("disabled"));
+        assert_eq!(pick(&ng, "hero9", PhaseKind::Pick), Err("unknown hero"));
+        assert_eq!(ng.validate_choice(&"p1_0".to_string(), &"hero0".to_string(), PhaseKind::Pick, &heros), Err("not your turn"));
+        assert_eq!(ng.validate_choice(&"nobody".to_string(), &"hero0".to_string(), PhaseKind::Pick, &heros), Err("not in game"));
+        ng.paused = true;
+        assert_eq!(pick(&ng, "hero0", PhaseKind::Pick), Err("paused"));
+        // locking the hero already held still needs the turn
+        assert_eq!(ng.validate_turn(&id, PhaseKind::Pick), Err("paused"));
+        ng.paused = false;
+        ng.game_status = 3;
+        assert_eq!(ng.validate_turn(&id, PhaseKind::Pick), Err("wrong phase"));
+    }
+
+    #[test]
+    fn test_pause_and_reset() {
+        let (teams, user_names) = new_teams(2);
+        let mut aram = ARAMGame { seed: 3, teams: teams, user_names: user_names, TotalHeros: new_heros(10), ..Default::default() };
+        aram.rollHeros();
+        aram.next_status();
+        aram.next_status();
+        let id = "p0_0".to_string();
+        let a = User { id: id.clone(), hero: "heroA".to_string(), ..Default::default() };
+        let b = User { id: "p0_1".to_string(), hero: "heroB".to_string(), ..Default::default() };
+        aram.set_paused(true);
+        assert_eq!(aram.reroll(&id), Err("paused"));
+        assert_eq!(aram.check_trade(&a, &b), Err("paused"));
+        aram.set_paused(false);
+        assert_eq!(aram.check_trade(&a, &b), Ok(()));
+        aram.reroll(&id).unwrap();
+        let (_, user) = aram.find_user(&id).unwrap();
+        assert_eq!(user.borrow().reroll, 0);
+        // resetting the pick phase refunds the token and restarts its timer
+        aram.choose_time = 1;
+        aram.restart_phase();
+        assert_eq!(aram.game_status, 2);
+        assert_eq!(aram.choose_time, ARAM_PICK_TIME);
+        assert_eq!(user.borrow().reroll, 1);
+        // tokens spent before a later phase are kept
+        aram.reroll(&id).unwrap();
+        aram.next_status();
+        aram.restart_phase();
+        assert_eq!(aram.game_status, 3);
+        assert_eq!(user.borrow().reroll, 0);
+    }
+}
@@ -25,6 +25,7 @@ use std::sync::{Arc, Condvar, Mutex, RwLock};
 
 use crate::event_room::*;
 use crate::game::*;
+use crate::draft::*;
 use crate::msg::*;
 use crate::room::*;
 
@@ -44,6 +45,25 @@ pub enum GameingData {
     RmoveARAMGame(RemoveGameData),
 }
 
+pub fn process_game(
+    msgtx: Sender<MqttMsg>,
+    tx2: Sender<RoomEventData>,
+    tx3: Sender<SqlData>,
+    TotalUsers: BTreeMap<String, Rc<RefCell<User>>>,
+    game_id: &u64,
+    game: &mut ActiveGame,
+) -> Result<(), Error> {
+    if game.state().borrow().paused() {
+        return Ok(());
+    }
+    match game {
+        ActiveGame::NG(g) => process_ng(msgtx, tx2, tx3, TotalUsers, game_id, g),
+        ActiveGame::Draft(g) => process_draft(msgtx, tx2, tx3, TotalUsers, game_id, g),
+        ActiveGame::ARAM(g) => process_aram(msgtx, tx2, tx3, TotalUsers, game_id, g),
+        ActiveGame::CM(g) => process_cm(msgtx, tx2, tx3, TotalUsers, game_id, g),
+    }
+}
+
 pub fn process_ng(
     msgtx: Sender<MqttMsg>,
     tx2: Sender<RoomEventData>,
@@ -52,15 +72,28 @@ pub fn process_ng(
     game_id: &u64,
     group: &mut Rc<RefCell<NGGame>>,
 ) -> Result<(), Error> {
-    let mode = "ng";
+    let mode = group.borrow().mode.clone();
+    if group.borrow().backfill.len() > 0 {
+        group.borrow_mut().backfill_time -= 1;
+        if group.borrow().backfill_time < 0 {
+            let jumpData = JumpData {
+                id: group.borrow().backfill[0].id.clone(),
+                game: *game_id,
+                msg: "backfill_timeout".to_string(),
+                reason: "backfill_timeout".to_string(),
+            };
+            tx2.try_send(RoomEventData::Jump(jumpData));
+        }
+        return Ok(());
+    }
     let res = group.borrow_mut().check_status();
     match res {
-        NGGameStatus::Loading => {
+        PhaseKind::Loading => {
             if group.borrow_mut().check_loading() {
                 group.borrow_mut().next_status();
             }
         }
-        NGGameStatus::Ban => {
+        PhaseKind::Ban => {
             if group.borrow_mut().ban_time == BAN_HERO_TIME {
                 send_ban_msg(
                     &msgtx,
@@ -79,11 +112,12 @@ pub fn process_ng(
                 }
             }
             if group.borrow_mut().ban_time <= BUFFER || !isJump {
+                log_ban_timeouts(&tx3, *game_id, &TotalUsers, &group.borrow().user_names, &group.borrow().pick_position);
                 group.borrow_mut().rollBanHero();
                 group.borrow_mut().next_status();
             }
         }
-        NGGameStatus::Pick => {
+        PhaseKind::Pick => {
             if group.borrow_mut().choose_time == NG_CHOOSE_HERO_TIME {
                 send_pick_msg(
                     &msgtx,
@@ -99,11 +133,17 @@ pub fn process_ng(
                 if let Some(u) = TotalUsers.get(&group.borrow().user_names[*index]) {
                     if u.borrow().hero == "" {
                         isJump = true;
-                        if group.borrow().choose_time <= BUFFER {
+                        if group.borrow().choose_time == BUFFER {
+                            tx2.try_send(RoomEventData::AutoPick(AutoPickData {
+                                id: u.borrow().id.clone(),
+                                game: *game_id,
+                            }));
+                        } else if group.borrow().choose_time < BUFFER {
                             let jumpData = JumpData {
                                 id: u.borrow().id.clone(),
                                 game: *game_id,
                                 msg: "jump".to_string(),
+                                reason: "timeout".to_string(),
                             };
                             tx2.try_send(RoomEventData::Jump(jumpData));
                         }
@@ -114,7 +154,7 @@ pub fn process_ng(
                 group.borrow_mut().next_status();
             }
         }
-        NGGameStatus::ReadyToStart => {
+        PhaseKind::ReadyToStart => {
             if group.borrow().ready_to_start_time == READY_TO_START_TIME {
                 let mut isJump = false;
                 for index in &group.borrow().pick_position {
@@ -124,6 +164,7 @@ pub fn process_ng(
                                 id: u.borrow().id.clone(),
                                 game: *game_id,
                                 msg: "jump".to_string(),
+                                reason: "no_hero".to_string(),
                             };
                             tx2.try_send(RoomEventData::Jump(jumpData));
                             isJump = true;
@@ -131,24 +172,8 @@ pub fn process_ng(
                     }
                 }
                 if !isJump {
-                    let mut chooseData: Vec<UserChooseData> = vec![];
-                    let mut values = format!("values ({}, '{}'", game_id, mode);
-                    for user_id in &group.borrow().user_names {
-                        if let Some(u) = TotalUsers.get(user_id) {
-                            let data = UserChooseData {
-                                steam_id: user_id.to_string(),
-                                hero: u.borrow().hero.clone(),
-                                ban_hero: u.borrow().ban_hero.clone(),
-                            };
-                            chooseData.push(data);
-                        }
-                    }
-                    let sqlGameInfoData = SqlGameInfoData {
-                        game: group.borrow().game_id,
-                        mode: mode.to_string(),
-                        chooseData: chooseData,
-                    };
-                    tx3.try_send(SqlData::UpdateGameInfo(sqlGameInfoData));
+                    tx3.try_send(SqlData::UpdateGameInfo(choose_info(&*group.borrow(), &TotalUsers)));
+                    tx2.try_send(RoomEventData::AllocateServer(AllocateServerData { game: *game_id }));
                     send_ready_to_start_msg(
                         &msgtx,
                         *game_id,
@@ -168,30 +193,32 @@ pub fn process_ng(
             }
             group.borrow_mut().ready_to_start_time -= 1;
         }
-        NGGameStatus::Gaming => {}
-        NGGameStatus::Finished => {}
+        PhaseKind::Gaming => {}
+        PhaseKind::Trade => {}
+        PhaseKind::Finished => {}
     }
     Ok(())
 }
 
-pub fn process_rk(
+pub fn process_draft(
     msgtx: Sender<MqttMsg>,
     tx2: Sender<RoomEventData>,
     tx3: Sender<SqlData>,
     TotalUsers: BTreeMap<String, Rc<RefCell<User>>>,
     game_id: &u64,
-    group: &mut Rc<RefCell<RKGame>>,
+    group: &mut Rc<RefCell<DraftGame>>,
 ) -> Result<(), Error> {
-    let mode = "rk";
+    let mode = group.borrow().mode.clone();
     let res = group.borrow_mut().check_status();
+    let phase_time = group.borrow().phase().map_or(0, |p| p.time);
     match res {
-        RKGameStatus::Loading => {
+        PhaseKind::Loading => {
             if group.borrow_mut().check_loading() {
                 group.borrow_mut().next_status();
             }
         }
-        RKGameStatus::Ban => {
-            if group.borrow_mut().ban_time == BAN_HERO_TIME {
+        PhaseKind::Ban => {
+            if group.borrow_mut().ban_time == phase_time {
                 send_ban_msg(
                     &msgtx,
                     *game_id,
@@ -209,12 +236,13 @@ pub fn process_rk(
                 }
             }
             if group.borrow_mut().ban_time <= BUFFER || !isJump {
+                log_ban_timeouts(&tx3, *game_id, &TotalUsers, &group.borrow().user_names, &group.borrow().pick_position);
                 group.borrow_mut().next_status();
             }
         }
-        RKGameStatus::Pick => {
+        PhaseKind::Pick => {
             group.borrow_mut().updateBanHeros();
-            if group.borrow_mut().choose_time == CHOOSE_HERO_TIME {
+            if group.borrow_mut().choose_time == phase_time {
                 send_pick_msg(
                     &msgtx,
                     *game_id,
@@ -234,6 +262,7 @@ pub fn process_rk(
                                 id: u.borrow().id.clone(),
                                 game: *game_id,
                                 msg: "jump".to_string(),
+                                reason: "timeout".to_string(),
                             };
                             tx2.try_send(RoomEventData::Jump(jumpData));
                         }
@@ -244,8 +273,8 @@ pub fn process_rk(
                 group.borrow_mut().next_status();
             }
         }
-        RKGameStatus::ReadyToStart => {
-            if group.borrow().ready_to_start_time == READY_TO_START_TIME {
+        PhaseKind::ReadyToStart => {
+            if group.borrow().ready_to_start_time == phase_time {
                 let mut isJump = false;
                 for index in &group.borrow().pick_position {
                     if let Some(u) = TotalUsers.get(&group.borrow().user_names[*index]) {
@@ -254,6 +283,7 @@ pub fn process_rk(
                                 id: u.borrow().id.clone(),
                                 game: *game_id,
                                 msg: "jump".to_string(),
+                                reason: "no_hero".to_string(),
                             };
                             tx2.try_send(RoomEventData::Jump(jumpData));
                             isJump = true;
@@ -261,24 +291,8 @@ pub fn process_rk(
                     }
                 }
                 if !isJump {
-                    let mut chooseData: Vec<UserChooseData> = vec![];
-                    let mut values = format!("values ({}, '{}'", game_id, mode);
-                    for user_id in &group.borrow().user_names {
-                        if let Some(u) = TotalUsers.get(user_id) {
-                            let data = UserChooseData {
-                                steam_id: user_id.to_string(),
-                                hero: u.borrow().hero.clone(),
-                                ban_hero: u.borrow().ban_hero.clone(),
-                            };
-                            chooseData.push(data);
-                        }
-                    }
-                    let sqlGameInfoData = SqlGameInfoData {
-                        game: group.borrow().game_id,
-                        mode: mode.to_string(),
-                        chooseData: chooseData,
-                    };
-                    tx3.try_send(SqlData::UpdateGameInfo(sqlGameInfoData));
+                    tx3.try_send(SqlData::UpdateGameInfo(choose_info(&*group.borrow(), &TotalUsers)));
+                    tx2.try_send(RoomEventData::AllocateServer(AllocateServerData { game: *game_id }));
                     send_ready_to_start_msg(
                         &msgtx,
                         *game_id,
@@ -298,84 +312,69 @@ pub fn process_rk(
             }
             group.borrow_mut().ready_to_start_time -= 1;
         }
-        RKGameStatus::Gaming => {}
-        RKGameStatus::Finished => {}
+        PhaseKind::Gaming => {}
+        PhaseKind::Trade => {
+            if group.borrow().trade_time == phase_time {
+                send_trade_msg(&msgtx, *game_id, group.borrow().trade_time);
+            }
+            group.borrow_mut().trade_time -= 1;
+            if group.borrow().trade_time < 0 {
+                group.borrow_mut().next_status();
+            }
+        }
+        PhaseKind::Finished => {}
     }
     Ok(())
 }
 
-pub fn process_at(
+pub fn process_aram(
     msgtx: Sender<MqttMsg>,
     tx2: Sender<RoomEventData>,
     tx3: Sender<SqlData>,
     TotalUsers: BTreeMap<String, Rc<RefCell<User>>>,
     game_id: &u64,
-    group: &mut Rc<RefCell<ATGame>>,
+    group: &mut Rc<RefCell<ARAMGame>>,
 ) -> Result<(), Error> {
-    let mode = "at";
+    let mode = "aram";
     let res = group.borrow_mut().check_status();
     match res {
-        ATGameStatus::Loading => {
+        PhaseKind::Loading => {
             if group.borrow_mut().check_loading() {
                 group.borrow_mut().next_status();
+                println!("next");
             }
         }
-        ATGameStatus::Ban => {
-            if group.borrow_mut().ban_time == BAN_HERO_TIME {
-                send_ban_msg(
-                    &msgtx,
-                    *game_id,
-                    group.borrow().ban_time.clone(),
-                    group.borrow().pick_position.clone(),
-                );
-            }
-            group.borrow_mut().ban_time -= 1;
-            let mut isJump = false;
-            for index in &group.borrow().pick_position {
-                if let Some(u) = TotalUsers.get(&group.borrow().user_names[*index]) {
-                    if u.borrow().ban_hero == "" {
-                        isJump = true;
+        PhaseKind::Ban => {
+            send_ban_msg(&msgtx, *game_id, 0, group.borrow().pick_position.clone());
+            group.borrow_mut().next_status();
+            println!("next");
+        }
+        PhaseKind::Pick => {
+            if group.borrow().choose_time == ARAM_PICK_TIME {
+                group.borrow_mut().rollHeros();
+                send_heros_msg(&msgtx, *game_id, group.borrow().heros.clone());
+                for index in &group.borrow().pick_position {
+                    if let Some(u) = TotalUsers.get(&group.borrow().user_names[*index]) {
+                        let mqttmsg = MqttMsg{topic:format!("member/{}/res/ng_choose_hero", u.borrow().id),
+                            msg: format!(r#"{{"id":"{}", "hero":"{}", "reroll":{}}}"#, u.borrow().id, u.borrow().hero.clone(), u.borrow().reroll)};
+                        msgtx.try_send(mqttmsg)?;
                     }
                 }
-            }
-            if group.borrow_mut().ban_time <= BUFFER || !isJump {
-                group.borrow_mut().next_status();
-            }
-        }
-        ATGameStatus::Pick => {
-            group.borrow_mut().updateBanHeros();
-            if group.borrow_mut().choose_time == CHOOSE_HERO_TIME {
                 send_pick_msg(
                     &msgtx,
                     *game_id,
-                    group.borrow().choose_time.clone(),
+                    group.borrow().choose_time,
                     group.borrow().pick_position.clone(),
-                    group.borrow().ban_heros.clone(),
+                    vec![],
                 );
             }
             group.borrow_mut().choose_time -= 1;
-            let mut isJump = false;
-            for index in &group.borrow().pick_position {
-                if let Some(u) = TotalUsers.get(&group.borrow().user_names[*index]) {
-                    if u.borrow().hero == "" {
-                        isJump = true;
-                        if group.borrow().choose_time <= BUFFER {
-                            let jumpData = JumpData {
-                                id: u.borrow().id.clone(),
-                                game: *game_id,
-                                msg: "jump".to_string(),
-                            };
-                            tx2.try_send(RoomEventData::Jump(jumpData));
-                        }
-                    }
-                }
-            }
-            if !isJump {
+            if group.borrow().choose_time < 0 {
                 group.borrow_mut().next_status();
             }
         }
-        ATGameStatus::ReadyToStart => {
-            if group.borrow().ready_to_start_time == READY_TO_START_TIME {
+        PhaseKind::ReadyToStart => {
+            if group.borrow().ready_to_start_time == ARAM_READY_TO_START_TIME {
                 let mut isJump = false;
                 for index in &group.borrow().pick_position {
                     if let Some(u) = TotalUsers.get(&group.borrow().user_names[*index]) {
@@ -384,6 +383,7 @@ pub fn process_at(
                                 id: u.borrow().id.clone(),
                                 game: *game_id,
                                 msg: "jump".to_string(),
+                                reason: "no_hero".to_string(),
                             };
                             tx2.try_send(RoomEventData::Jump(jumpData));
                             isJump = true;
@@ -391,28 +391,11 @@ pub fn process_at(
                     }
                 }
                 if !isJump {
-                    let mut chooseData: Vec<UserChooseData> = vec![];
-                    let mut values = format!("values ({}, '{}'", game_id, mode);
-                    for user_id in &group.borrow().user_names {
-                        if let Some(u) = TotalUsers.get(user_id) {
-                            let data = UserChooseData {
-                                steam_id: user_id.to_string(),
-                                hero: u.borrow().hero.clone(),
-                                ban_hero: u.borrow().ban_hero.clone(),
-                            };
-                            chooseData.push(data);
-                        }
-                    }
-                    let sqlGameInfoData = SqlGameInfoData {
-                        game: group.borrow().game_id,
-                        mode: mode.to_string(),
-                        chooseData: chooseData,
-                    };
-                    tx3.try_send(SqlData::UpdateGameInfo(sqlGameInfoData));
+                    tx2.try_send(RoomEventData::AllocateServer(AllocateServerData { game: *game_id }));
                     send_ready_to_start_msg(
                         &msgtx,
                         *game_id,
-                        group.borrow().ready_to_start_time.clone(),
+                        group.borrow().ready_to_start_time,
                         group.borrow().user_names.clone(),
                     );
                 }
@@ -424,74 +407,58 @@ pub fn process_at(
                         id: id.to_string(),
                     }));
                 }
+                tx3.try_send(SqlData::UpdateGameInfo(choose_info(&*group.borrow(), &TotalUsers)));
                 group.borrow_mut().next_status();
             }
             group.borrow_mut().ready_to_start_time -= 1;
         }
-        ATGameStatus::Gaming => {}
-        ATGameStatus::Finished => {}
+        PhaseKind::Gaming => {}
+        PhaseKind::Trade => {}
+        PhaseKind::Finished => {}
     }
     Ok(())
 }
 
-pub fn process_aram(
+pub fn process_cm(
     msgtx: Sender<MqttMsg>,
     tx2: Sender<RoomEventData>,
     tx3: Sender<SqlData>,
     TotalUsers: BTreeMap<String, Rc<RefCell<User>>>,
     game_id: &u64,
-    group: &mut Rc<RefCell<ARAMGame>>,
+    group: &mut Rc<RefCell<CMGame>>,
 ) -> Result<(), Error> {
-    let mode = "aram";
+    let mode = group.borrow().mode.clone();
     let res = group.borrow_mut().check_status();
+    let phase_time = group.borrow().phase().map_or(0, |p| p.time);
     match res {
-        ARAMGameStatus::Loading => {
+        PhaseKind::Loading => {
             if group.borrow_mut().check_loading() {
                 group.borrow_mut().next_status();
-                println!("next");
             }
         }
-        ARAMGameStatus::Ban => {
-            send_ban_msg(&msgtx, *game_id, 0, group.borrow().pick_position.clone());
-            group.borrow_mut().next_status();
-            println!("next");
-        }
-        ARAMGameStatus::Pick => {
-            group.borrow_mut().rollHeros();
-            send_heros_msg(&msgtx, *game_id, group.borrow().heros.clone());
-            for index in &group.borrow().pick_position {
-                if let Some(u) = TotalUsers.get(&group.borrow().user_names[*index]) {
-                    let mqttmsg = MqttMsg{topic:format!("member/{}/res/ng_choose_hero", u.borrow().id),
-                        msg: format!(r#"{{"id":"{}", "hero":"{}"}}"#, u.borrow().id, u.borrow().hero.clone())};
-                    msgtx.try_send(mqttmsg)?;
-                }
+        PhaseKind::Ban | PhaseKind::Pick => {
+            if group.borrow().turn_time == phase_time {
+                send_cm_turn_msg(&msgtx, *game_id, res.name(), &group.borrow());
+            }
+            let team = group.borrow().turn_team();
+            if group.borrow_mut().tick_turn() {
+                let captain = group.borrow().captains[team].clone();
+                let picked = group.borrow().pick_heros[team].len();
+                group.borrow_mut().timeout_turn();
+                let hero = group.borrow().pick_heros[team].get(picked).cloned().unwrap_or_default();
+                log_draft(&tx3, *game_id, res.name(), &captain, "timeout", &hero);
             }
-            group.borrow_mut().next_status();
         }
-        ARAMGameStatus::ReadyToStart => {
-            if group.borrow().ready_to_start_time == ARAM_READY_TO_START_TIME {
-                let mut isJump = false;
-                for index in &group.borrow().pick_position {
-                    if let Some(u) = TotalUsers.get(&group.borrow().user_names[*index]) {
-                        if u.borrow().hero == "" {
-                            let jumpData = JumpData {
-                                id: u.borrow().id.clone(),
-                                game: *game_id,
-                                msg: "jump".to_string(),
-                            };
-                            tx2.try_send(RoomEventData::Jump(jumpData));
-                            isJump = true;
-                        }
-                    }
-                }
-                if !isJump {
-                    send_ready_to_start_msg(
-                        &msgtx,
-                        *game_id,
-                        group.borrow().ready_to_start_time,
-                        group.borrow().user_names.clone(),
-                    );
-                }
+        PhaseKind::ReadyToStart => {
+            if group.borrow().ready_to_start_time == phase_time {
+                tx3.try_send(SqlData::UpdateGameInfo(choose_info(&*group.borrow(), &TotalUsers)));
+                tx2.try_send(RoomEventData::AllocateServer(AllocateServerData { game: *game_id }));
+                send_ready_to_start_msg(
+                    &msgtx,
+                    *game_id,
+                    group.borrow().ready_to_start_time,
+                    group.borrow().user_names.clone(),
+                );
             }
             if group.borrow_mut().ready_to_start_time < 0 {
                 send_gaming_msg(&msgtx, *game_id, group.borrow().user_names.clone());
@@ -500,34 +467,61 @@ pub fn process_aram(
                         id: id.to_string(),
                     }));
                 }
-                let mut chooseData: Vec<UserChooseData> = vec![];
-                let mut values = format!("values ({}, '{}'", game_id, mode);
-                for user_id in &group.borrow().user_names {
-                    if let Some(u) = TotalUsers.get(user_id) {
-                        let data = UserChooseData {
-                            steam_id: user_id.to_string(),
-                            hero: u.borrow().hero.clone(),
-                            ban_hero: u.borrow().ban_hero.clone(),
-                        };
-                        chooseData.push(data);
-                    }
-                }
-                let sqlGameInfoData = SqlGameInfoData {
-                    game: group.borrow().game_id,
-                    mode: mode.to_string(),
-                    chooseData: chooseData,
-                };
-                tx3.try_send(SqlData::UpdateGameInfo(sqlGameInfoData));
                 group.borrow_mut().next_status();
             }
             group.borrow_mut().ready_to_start_time -= 1;
         }
-        ARAMGameStatus::Gaming => {}
-        ARAMGameStatus::Finished => {}
+        PhaseKind::Gaming => {}
+        PhaseKind::Trade => {
+            if group.borrow().trade_time == phase_time {
+                send_trade_msg(&msgtx, *game_id, group.borrow().trade_time);
+            }
+            group.borrow_mut().trade_time -= 1;
+            if group.borrow().trade_time < 0 {
+                group.borrow_mut().next_status();
+            }
+        }
+        PhaseKind::Finished => {}
     }
     Ok(())
 }
 
+// the picks written for the game server, sent again whenever they change before the game starts
+pub fn choose_info(game: &dyn GameState, TotalUsers: &BTreeMap<String, Rc<RefCell<User>>>) -> SqlGameInfoData {
+    let mut chooseData: Vec<UserChooseData> = vec![];
+    for user_id in game.user_names() {
+        if let Some(u) = TotalUsers.get(user_id) {
+            chooseData.push(UserChooseData {
+                steam_id: user_id.to_string(),
+                hero: u.borrow().hero.clone(),
+                ban_hero: u.borrow().ban_hero.clone(),
+            });
+        }
+    }
+    SqlGameInfoData {
+        game: game.game_id(),
+        mode: game.mode(),
+        chooseData: chooseData,
+        seed: game.seed(),
+    }
+}
+
+fn log_ban_timeouts(
+    tx3: &Sender<SqlData>,
+    game_id: u64,
+    TotalUsers: &BTreeMap<String, Rc<RefCell<User>>>,
+    user_names: &Vec<String>,
+    pick_position: &Vec<usize>,
+) {
+    for index in pick_position {
+        if let Some(u) = TotalUsers.get(&user_names[*index]) {
+            if u.borrow().ban_hero == "" {
+                log_draft(tx3, game_id, "ban", &u.borrow().id, "timeout", "");
+            }
+        }
+    }
+}
+
 fn send_ban_msg(
     msgtx: &Sender<MqttMsg>,
     game_id: u64,
@@ -561,6 +555,38 @@ fn send_pick_msg(
     Ok(())
 }
 
+fn send_cm_turn_msg(
+    msgtx: &Sender<MqttMsg>,
+    game_id: u64,
+    status: &str,
+    game: &CMGame,
+) -> Result<(), Error> {
+    let team = game.turn_team();
+    msgtx.try_send(MqttMsg {
+        topic: format!("game/{}/res/game_status", game_id),
+        msg: json!({
+            "status": status,
+            "time": game.turn_time,
+            "reserve": game.reserve_time[team],
+            "team": team,
+            "captain": game.captains[team],
+            "picker": game.pick_position,
+            "ban": game.ban_heros,
+            "pick": game.pick_heros,
+        })
+        .to_string(),
+    })?;
+    Ok(())
+}
+
+fn send_trade_msg(msgtx: &Sender<MqttMsg>, game_id: u64, trade_time: i16) -> Result<(), Error> {
+    msgtx.try_send(MqttMsg {
+        topic: format!("game/{}/res/game_status", game_id),
+        msg: format!(r#"{{"status":"trade", "time":{}}}"#, trade_time),
+    })?;
+    Ok(())
+}
+
 fn send_ready_to_start_msg(
     msgtx: &Sender<MqttMsg>,
     game_id: u64,
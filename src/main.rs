@@ -8,6 +8,11 @@ mod game_flow;
 mod room;
 mod msg;
 mod elo;
+mod tournament;
+mod queue;
+mod draft;
+mod spectate;
+mod server;
 
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -90,6 +95,26 @@ fn main() -> std::result::Result<(), Error> {
             .long("backup")
             .takes_value(true)
             .help("backup"),
+        ).arg(
+            Arg::with_name("PARTY_SPREAD")
+            .long("party-spread")
+            .takes_value(true)
+            .help("Max rating spread in a ranked party (500)"),
+        ).arg(
+            Arg::with_name("DUO_ONLY_RATING")
+            .long("duo-only-rating")
+            .takes_value(true)
+            .help("Ranked rating from which only small parties may queue (1800)"),
+        ).arg(
+            Arg::with_name("DUO_ONLY_SIZE")
+            .long("duo-only-size")
+            .takes_value(true)
+            .help("Largest party allowed from the duo only rating (2)"),
+        ).arg(
+            Arg::with_name("DRAFT_FORMATS")
+            .long("draft-formats")
+            .takes_value(true)
+            .help("Json file with draft steps by flow, replaces the built in rk, at and cm"),
         ).get_matches();
 
     let server_addr = matches.value_of("SERVER").unwrap_or("172.104.72.206").to_owned();
@@ -101,6 +126,28 @@ fn main() -> std::result::Result<(), Error> {
         .unwrap_or("Elo test Server".to_owned());
     let mut isBackup: bool = matches.value_of("BACKUP").unwrap_or("false").to_owned().parse()?;
     println!("Backup: {}", isBackup);
+    let mut party_rules = event_room::PartyRules::default();
+    if let Some(v) = matches.value_of("PARTY_SPREAD") {
+        party_rules.spread = v.parse()?;
+    }
+    if let Some(v) = matches.value_of("DUO_ONLY_RATING") {
+        party_rules.duo_only_rating = v.parse()?;
+    }
+    if let Some(v) = matches.value_of("DUO_ONLY_SIZE") {
+        party_rules.duo_only_size = v.parse()?;
+    }
+    let draft_formats = match matches.value_of("DRAFT_FORMATS") {
+        Some(path) => draft::load_draft_formats(path)?,
+        None => draft::default_draft_formats(),
+    };
+    // every drafted mode has to build its phase list before any game needs it
+    for mode in event_room::QUEUE_MODES.iter() {
+        let cfg = event_room::mode_config(mode);
+        if cfg.flow == "rk" || cfg.flow == "at" || cfg.flow == "cm" {
+            draft::draft_phases(&draft_formats, cfg.flow, cfg.team_size as usize, cfg.team_count)
+                .map_err(|e| failure::err_msg(format!("draft format {}: {}", mode, e)))?;
+        }
+    }
     let mut mqtt_options = MqttOptions::new(client_id.as_str(), server_addr.as_str(), server_port.parse::<u16>()?);
     mqtt_options = mqtt_options.set_keep_alive(100);
     mqtt_options = mqtt_options.set_request_channel_capacity(10000);
@@ -110,10 +157,15 @@ fn main() -> std::result::Result<(), Error> {
     // Server message
     mqtt_client.subscribe("server/+/res/heartbeat", QoS::AtMostOnce)?;
     mqtt_client.subscribe("server/send/control", QoS::AtMostOnce)?;//doc server.drawio
+    mqtt_client.subscribe("game/send/draft_control", QoS::AtMostOnce)?;
     mqtt_client.subscribe("server/send/check_state", QoS::AtMostOnce)?;//doc server.drawio
     mqtt_client.subscribe("server/send/free", QoS::AtMostOnce)?;
     mqtt_client.subscribe("server/send/system_ban", QoS::AtMostOnce)?;
     mqtt_client.subscribe("server/send/update_heros", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("server/send/unlock_hero", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("server/send/free_rotation", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("gameserver/+/send/register", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("gameserver/+/send/heartbeat", QoS::AtMostOnce)?;
     // Client message
     mqtt_client.subscribe("member/+/send/login", QoS::AtMostOnce)?;//doc login.drawio
     mqtt_client.subscribe("member/+/send/logout", QoS::AtMostOnce)?;//doc login.drwio
@@ -159,6 +211,22 @@ fn main() -> std::result::Result<(), Error> {
     mqtt_client.subscribe("game/+/send/get_heros", QoS::AtMostOnce)?;
     mqtt_client.subscribe("game/+/send/try_swap_hero", QoS::AtMostOnce)?;
     mqtt_client.subscribe("game/+/send/swap_hero", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("game/+/send/reroll", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("game/+/send/bench_swap", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("game/+/send/spectate", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("game/+/send/draft_log", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("game/+/send/stop_spectate", QoS::AtMostOnce)?;
+
+    mqtt_client.subscribe("lobby/+/send/create_lobby", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("lobby/+/send/join_lobby", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("lobby/+/send/leave_lobby", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("lobby/+/send/assign_team", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("lobby/+/send/start_lobby", QoS::AtMostOnce)?;
+
+    mqtt_client.subscribe("tournament/send/create_tournament", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("tournament/send/start_tournament", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("tournament/+/send/register_team", QoS::AtMostOnce)?;
+    mqtt_client.subscribe("tournament/+/send/get_standings", QoS::AtMostOnce)?;
     let mut isServerLive = true;
     
     
@@ -243,6 +311,11 @@ fn main() -> std::result::Result<(), Error> {
     let reban_hero = Regex::new(r"\w+/(\w+)/send/ban_hero")?;
     let reget_hero = Regex::new(r"\w+/(\w+)/send/get_heros")?;
     let reswap_hero = Regex::new(r"\w+/(\w+)/send/swap_hero")?;
+    let rereroll = Regex::new(r"\w+/(\w+)/send/reroll")?;
+    let rebench_swap = Regex::new(r"\w+/(\w+)/send/bench_swap")?;
+    let respectate = Regex::new(r"\w+/(\w+)/send/spectate")?;
+    let redraft_log = Regex::new(r"\w+/(\w+)/send/draft_log")?;
+    let restop_spectate = Regex::new(r"\w+/(\w+)/send/stop_spectate")?;
     let releave = Regex::new(r"\w+/(\w+)/send/leave")?;
     let restart_game = Regex::new(r"\w+/(\w+)/send/start_game")?;
     let repassword = Regex::new(r"\w+/(\w+)/send/set_password")?;
@@ -261,16 +334,30 @@ fn main() -> std::result::Result<(), Error> {
     let recheckBinding = Regex::new(r"\w+/(\w+)/send/check_binding")?;
     let regetLeaderboard = Regex::new(r"\w+/(\w+)/send/get_leaderboard")?;
     let recontrol = Regex::new(r"\w+/send/control")?;
+    let redraft_control = Regex::new(r"\w+/send/draft_control")?;
     let recheck_state = Regex::new(r"\w+/(\w+)/send/check_state")?;
     let reloading = Regex::new(r"\w+/(\w+)/send/loading")?;
     let refree = Regex::new(r"\w+/send/free")?;
     let resystem_ban = Regex::new(r"\w+/send/system_ban")?;
     let reupdate_heros = Regex::new(r"\w+/send/update_heros")?;
+    let reunlock_hero = Regex::new(r"\w+/send/unlock_hero")?;
+    let refree_rotation = Regex::new(r"\w+/send/free_rotation")?;
+    let recreate_lobby = Regex::new(r"\w+/(\w+)/send/create_lobby")?;
+    let rejoin_lobby = Regex::new(r"\w+/(\w+)/send/join_lobby")?;
+    let releave_lobby = Regex::new(r"\w+/(\w+)/send/leave_lobby")?;
+    let reassign_team = Regex::new(r"\w+/(\w+)/send/assign_team")?;
+    let restart_lobby = Regex::new(r"\w+/(\w+)/send/start_lobby")?;
+    let recreate_tournament = Regex::new(r"\w+/send/create_tournament")?;
+    let restart_tournament = Regex::new(r"\w+/send/start_tournament")?;
+    let reregister_team = Regex::new(r"\w+/(\w+)/send/register_team")?;
+    let reregister_server = Regex::new(r"gameserver/(\w+)/send/register")?;
+    let reserver_heartbeat = Regex::new(r"gameserver/(\w+)/send/heartbeat")?;
+    let reget_standings = Regex::new(r"\w+/(\w+)/send/get_standings")?;
     
     
     //let mut QueueSender: Sender<QueueData>;
     let mut sender1: Sender<SqlData> = event_room::HandleSqlRequest(pool.clone())?;
-    let (mut sender, mut QueueSender): (Sender<RoomEventData>, Sender<QueueData>) = event_room::init(tx.clone(), sender1.clone(), pool.clone(), redis_client.clone(), None, isBackup)?;
+    let (mut sender, mut QueueSender): (Sender<RoomEventData>, Sender<QueueData>) = event_room::init(tx.clone(), sender1.clone(), pool.clone(), redis_client.clone(), None, isBackup, party_rules.clone(), draft_formats.clone())?;
     let update = tick(Duration::from_millis(500));
     let mut is_live = true;
     let mut sender = sender.clone();
@@ -298,7 +385,7 @@ fn main() -> std::result::Result<(), Error> {
                 if !is_live{
                     println!("Reconnect!");
                     
-                    let (mut sender1, mut QueueSender1): (Sender<RoomEventData>, Sender<QueueData>) = event_room::init(tx.clone(), sender1.clone(), pool.clone(), redis_client.clone(), None, isBackup)?;
+                    let (mut sender1, mut QueueSender1): (Sender<RoomEventData>, Sender<QueueData>) = event_room::init(tx.clone(), sender1.clone(), pool.clone(), redis_client.clone(), None, isBackup, party_rules.clone(), draft_formats.clone())?;
                     sender = sender1.clone();
                     QueueSender = QueueSender1.clone();
 
@@ -334,7 +421,57 @@ fn main() -> std::result::Result<(), Error> {
                                 if !recheckInGame.is_match(topic_name){
                                     info!("topic_name : {}", topic_name);
                                 }
-                                if reinvite.is_match(topic_name) {
+                                if recreate_tournament.is_match(topic_name) {
+                                    info!("create_tournament: json: {:?}", v);
+                                    event_room::create_tournament(v, sender.clone())?;
+                                } else if restart_tournament.is_match(topic_name) {
+                                    info!("start_tournament: json: {:?}", v);
+                                    event_room::start_tournament(v, sender.clone())?;
+                                } else if reregister_server.is_match(topic_name) {
+                                    let cap = reregister_server.captures(topic_name).unwrap();
+                                    let serverid = cap[1].to_string();
+                                    info!("register server: serverid: {} json: {:?}", serverid, v);
+                                    event_room::register_server(serverid, v, sender.clone())?;
+                                } else if reserver_heartbeat.is_match(topic_name) {
+                                    let cap = reserver_heartbeat.captures(topic_name).unwrap();
+                                    let serverid = cap[1].to_string();
+                                    event_room::server_heartbeat(serverid, v, sender.clone())?;
+                                } else if reregister_team.is_match(topic_name) {
+                                    let cap = reregister_team.captures(topic_name).unwrap();
+                                    let userid = cap[1].to_string();
+                                    info!("register_team: userid: {} json: {:?}", userid, v);
+                                    event_room::register_team(userid, v, sender.clone())?;
+                                } else if reget_standings.is_match(topic_name) {
+                                    let cap = reget_standings.captures(topic_name).unwrap();
+                                    let userid = cap[1].to_string();
+                                    info!("get_standings: userid: {} json: {:?}", userid, v);
+                                    event_room::get_standings(userid, v, sender.clone())?;
+                                } else if recreate_lobby.is_match(topic_name) {
+                                    let cap = recreate_lobby.captures(topic_name).unwrap();
+                                    let userid = cap[1].to_string();
+                                    info!("create_lobby: userid: {} json: {:?}", userid, v);
+                                    event_room::create_lobby(userid, v, sender.clone())?;
+                                } else if rejoin_lobby.is_match(topic_name) {
+                                    let cap = rejoin_lobby.captures(topic_name).unwrap();
+                                    let userid = cap[1].to_string();
+                                    info!("join_lobby: userid: {} json: {:?}", userid, v);
+                                    event_room::join_lobby(userid, v, sender.clone())?;
+                                } else if releave_lobby.is_match(topic_name) {
+                                    let cap = releave_lobby.captures(topic_name).unwrap();
+                                    let userid = cap[1].to_string();
+                                    info!("leave_lobby: userid: {} json: {:?}", userid, v);
+                                    event_room::leave_lobby(userid, v, sender.clone())?;
+                                } else if reassign_team.is_match(topic_name) {
+                                    let cap = reassign_team.captures(topic_name).unwrap();
+                                    let userid = cap[1].to_string();
+                                    info!("assign_team: userid: {} json: {:?}", userid, v);
+                                    event_room::assign_team(userid, v, sender.clone())?;
+                                } else if restart_lobby.is_match(topic_name) {
+                                    let cap = restart_lobby.captures(topic_name).unwrap();
+                                    let userid = cap[1].to_string();
+                                    info!("start_lobby: userid: {} json: {:?}", userid, v);
+                                    event_room::start_lobby(userid, v, sender.clone())?;
+                                } else if reinvite.is_match(topic_name) {
                                     let cap = reinvite.captures(topic_name).unwrap();
                                     let userid = cap[1].to_string();
                                     info!("invite: userid: {} json: {:?}", userid, v);
@@ -359,6 +496,31 @@ fn main() -> std::result::Result<(), Error> {
                                     let userid = cap[1].to_string();
                                     info!("swap hero: userid: {} json: {:?}", userid, v);
                                     event_room::swap_hero(userid, v, sender.clone())?;
+                                } else if rereroll.is_match(topic_name) {
+                                    let cap = rereroll.captures(topic_name).unwrap();
+                                    let userid = cap[1].to_string();
+                                    info!("reroll: userid: {} json: {:?}", userid, v);
+                                    event_room::reroll(userid, v, sender.clone())?;
+                                } else if rebench_swap.is_match(topic_name) {
+                                    let cap = rebench_swap.captures(topic_name).unwrap();
+                                    let userid = cap[1].to_string();
+                                    info!("bench swap: userid: {} json: {:?}", userid, v);
+                                    event_room::bench_swap(userid, v, sender.clone())?;
+                                } else if redraft_log.is_match(topic_name) {
+                                    let cap = redraft_log.captures(topic_name).unwrap();
+                                    let userid = cap[1].to_string();
+                                    info!("draft log: userid: {} json: {:?}", userid, v);
+                                    event_room::get_draft_log(userid, v, sender.clone())?;
+                                } else if restop_spectate.is_match(topic_name) {
+                                    let cap = restop_spectate.captures(topic_name).unwrap();
+                                    let userid = cap[1].to_string();
+                                    info!("stop spectate: userid: {} json: {:?}", userid, v);
+                                    event_room::stop_spectate(userid, v, sender.clone())?;
+                                } else if respectate.is_match(topic_name) {
+                                    let cap = respectate.captures(topic_name).unwrap();
+                                    let userid = cap[1].to_string();
+                                    info!("spectate: userid: {} json: {:?}", userid, v);
+                                    event_room::spectate(userid, v, sender.clone())?;
                                 } else if relocked_hero.is_match(topic_name) {
                                     let cap = relocked_hero.captures(topic_name).unwrap();
                                     let userid = cap[1].to_string();
@@ -509,15 +671,24 @@ fn main() -> std::result::Result<(), Error> {
                                 } else if recontrol.is_match(topic_name) {
                                     info!("recontrol: json: {:?}", v);
                                     event_room::control(v, sender.clone())?;
+                                } else if redraft_control.is_match(topic_name) {
+                                    info!("redraft_control: json: {:?}", v);
+                                    event_room::draft_control(v, sender.clone())?;
                                 } else if recheck_state.is_match(topic_name) {
                                     info!("recheck_state: json: {:?}", v);
                                     event_room::checkState(v, sender.clone())?;
+                                } else if refree_rotation.is_match(topic_name) {
+                                    info!("refree_rotation: json: {:?}", v);
+                                    event_room::freeRotation(v, sender.clone())?;
                                 } else if refree.is_match(topic_name) {
                                     info!("refree: json: {:?}", v);
                                     event_room::free(v, sender.clone())?;
                                 } else if resystem_ban.is_match(topic_name) {
                                     info!("resystem_ban: json: {:?}", v);
                                     event_room::systemBan(v, sender.clone())?;
+                                } else if reunlock_hero.is_match(topic_name) {
+                                    info!("reunlock_hero: json: {:?}", v);
+                                    event_room::unlockHero(v, sender.clone())?;
                                 } else if reupdate_heros.is_match(topic_name) {
                                     info!("reupdate_heros: json: {:?}", v);
                                     event_room::updateHeros(v, sender.clone())?;
@@ -0,0 +1,328 @@
+use crate::event_room::QueueRoomData;
+use std::cell::RefCell;
+use std::collections::btree_map;
+use std::collections::{BTreeMap, BTreeSet};
+use std::iter;
+use std::rc::Rc;
+
+// rooms keyed by rid plus a (rating, rid) index so candidates are looked up by rating window
+#[derive(Clone, Debug)]
+pub struct QueueIndex {
+    pub rooms: BTreeMap<u64, Rc<RefCell<QueueRoomData>>>,
+    sorted: BTreeMap<(i16, u64), Rc<RefCell<QueueRoomData>>>,
+    rating: fn(&QueueRoomData) -> i16,
+}
+
+impl QueueIndex {
+    pub fn new(rating: fn(&QueueRoomData) -> i16) -> QueueIndex {
+        QueueIndex {
+            rooms: BTreeMap::new(),
+            sorted: BTreeMap::new(),
+            rating: rating,
+        }
+    }
+
+    pub fn rating(&self, room: &QueueRoomData) -> i16 {
+        (self.rating)(room)
+    }
+
+    pub fn insert(&mut self, room: Rc<RefCell<QueueRoomData>>) {
+        let rid = room.borrow().rid;
+        self.remove(&rid);
+        let key = (self.rating(&room.borrow()), rid);
+        self.sorted.insert(key, Rc::clone(&room));
+        self.rooms.insert(rid, room);
+    }
+
+    pub fn remove(&mut self, rid: &u64) -> Option<Rc<RefCell<QueueRoomData>>> {
+        let room = self.rooms.remove(rid)?;
+        let key = (self.rating(&room.borrow()), *rid);
+        self.sorted.remove(&key);
+        Some(room)
+    }
+
+    pub fn get(&self, rid: &u64) -> Option<&Rc<RefCell<QueueRoomData>>> {
+        self.rooms.get(rid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rooms.len()
+    }
+
+    pub fn iter(&self) -> btree_map::Iter<u64, Rc<RefCell<QueueRoomData>>> {
+        self.rooms.iter()
+    }
+
+    pub fn max_queue_cnt(&self) -> i64 {
+        self.rooms.values().map(|r| r.borrow().queue_cnt).max().unwrap_or(0)
+    }
+
+    // rooms rated within center +- width, priority rooms first and the rest in rating order
+    pub fn window(&self, center: i16, width: i64) -> Vec<Rc<RefCell<QueueRoomData>>> {
+        let low = (center as i64 - width).max(i16::min_value() as i64) as i16;
+        let high = (center as i64 + width).min(i16::max_value() as i64) as i16;
+        let mut res: Vec<Rc<RefCell<QueueRoomData>>> = self
+            .sorted
+            .range((low, 0)..=(high, u64::max_value()))
+            .map(|x| Rc::clone(x.1))
+            .collect();
+        res.sort_by_key(|x| !x.borrow().priority);
+        res
+    }
+
+    // each ungrouped room in order seeds a group from its own window, which widens with that room's wait
+    // join gets the number of groups formed so far, rooms of a group that does not fill up are released
+    pub fn form_groups<G, N, J, F>(
+        &self,
+        order: &Vec<Rc<RefCell<QueueRoomData>>>,
+        range: i64,
+        interval: i64,
+        new_group: N,
+        mut join: J,
+        full: F,
+    ) -> Vec<G>
+    where
+        N: Fn() -> G,
+        J: FnMut(&mut G, &Rc<RefCell<QueueRoomData>>, u64),
+        F: Fn(&G) -> bool,
+    {
+        let mut res: Vec<G> = vec![];
+        for v in order {
+            if v.borrow().ready != 0 {
+                continue;
+            }
+            let mut g = new_group();
+            let mut joined: Vec<Rc<RefCell<QueueRoomData>>> = vec![];
+            let center = self.rating(&v.borrow());
+            let width = range + interval * v.borrow().queue_cnt;
+            for r in iter::once(Rc::clone(v)).chain(self.window(center, width)) {
+                if full(&g) {
+                    break;
+                }
+                let free = r.borrow().ready == 0;
+                join(&mut g, &r, res.len() as u64);
+                if free && r.borrow().ready != 0 {
+                    joined.push(r);
+                }
+            }
+            if full(&g) {
+                res.push(g);
+            } else {
+                for r in joined {
+                    r.borrow_mut().ready = 0;
+                    r.borrow_mut().gid = 0;
+                }
+            }
+        }
+        res
+    }
+}
+
+impl<'a> IntoIterator for &'a QueueIndex {
+    type Item = (&'a u64, &'a Rc<RefCell<QueueRoomData>>);
+    type IntoIter = btree_map::Iter<'a, u64, Rc<RefCell<QueueRoomData>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rooms.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    const SIZE: i16 = 5;
+    const RANGE: i64 = 50;
+
+    #[derive(Default)]
+    struct Group {
+        rid: Vec<u64>,
+        user_len: i16,
+        avg: i16,
+    }
+
+    fn new_rooms(n: u64) -> Vec<Rc<RefCell<QueueRoomData>>> {
+        let mut seed: u64 = 7;
+        let mut res = vec![];
+        for rid in 1..n + 1 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            res.push(Rc::new(RefCell::new(QueueRoomData {
+                rid: rid,
+                user_len: 1 + ((seed >> 33) % 3) as i16,
+                avg_ng: 800 + ((seed >> 40) % 1200) as i16,
+                queue_cnt: ((seed >> 20) % 30) as i64,
+                ..Default::default()
+            })));
+        }
+        res
+    }
+
+    // same rules as canGroup, without the database handle
+    fn try_join(g: &mut Group, r: &Rc<RefCell<QueueRoomData>>, gid: u64) {
+        let mut r = r.borrow_mut();
+        if r.ready != 0 || r.user_len + g.user_len > SIZE {
+            return;
+        }
+        if g.user_len > 0 && i64::abs((r.avg_ng - g.avg).into()) > RANGE + 2 * r.queue_cnt {
+            return;
+        }
+        g.avg = ((g.avg as i32 * g.user_len as i32 + r.avg_ng as i32 * r.user_len as i32)
+            / (g.user_len + r.user_len) as i32) as i16;
+        g.user_len += r.user_len;
+        g.rid.push(r.rid);
+        r.ready = 1;
+        r.gid = gid;
+    }
+
+    fn release(g: &Group, rooms: &BTreeMap<u64, Rc<RefCell<QueueRoomData>>>) {
+        for rid in &g.rid {
+            if let Some(r) = rooms.get(rid) {
+                r.borrow_mut().ready = 0;
+                r.borrow_mut().gid = 0;
+            }
+        }
+    }
+
+    fn order(rooms: &BTreeMap<u64, Rc<RefCell<QueueRoomData>>>) -> Vec<Rc<RefCell<QueueRoomData>>> {
+        let mut res: Vec<Rc<RefCell<QueueRoomData>>> = rooms.values().map(Rc::clone).collect();
+        res.sort_by_key(|x| !x.borrow().priority);
+        res
+    }
+
+    // the previous loop: reset every unmatched room, then try every room against every other
+    fn group_quadratic(rooms: &BTreeMap<u64, Rc<RefCell<QueueRoomData>>>) -> usize {
+        let mut matched: BTreeSet<u64> = BTreeSet::new();
+        for (_, v) in rooms {
+            for (_, v2) in rooms {
+                if !matched.contains(&v2.borrow().gid) {
+                    v2.borrow_mut().ready = 0;
+                    v2.borrow_mut().gid = 0;
+                }
+            }
+            let gid = matched.len() as u64 + 1;
+            let mut g = Group::default();
+            try_join(&mut g, v, gid);
+            for (_, v2) in rooms {
+                try_join(&mut g, v2, gid);
+            }
+            if g.user_len == SIZE {
+                matched.insert(gid);
+            } else {
+                release(&g, rooms);
+            }
+        }
+        matched.len()
+    }
+
+    // the same seeding over a linear scan of every room instead of the rating index
+    fn group_linear(rooms: &BTreeMap<u64, Rc<RefCell<QueueRoomData>>>) -> Vec<Vec<u64>> {
+        let mut res: Vec<Vec<u64>> = vec![];
+        let mut sorted: Vec<Rc<RefCell<QueueRoomData>>> = rooms.values().map(Rc::clone).collect();
+        sorted.sort_by_key(|x| (x.borrow().avg_ng, x.borrow().rid));
+        for v in order(rooms) {
+            if v.borrow().ready != 0 {
+                continue;
+            }
+            let gid = res.len() as u64 + 1;
+            let center = v.borrow().avg_ng;
+            let width = RANGE + 2 * v.borrow().queue_cnt;
+            let mut candidates: Vec<Rc<RefCell<QueueRoomData>>> = sorted
+                .iter()
+                .filter(|x| i64::abs((x.borrow().avg_ng - center).into()) <= width)
+                .map(Rc::clone)
+                .collect();
+            candidates.sort_by_key(|x| !x.borrow().priority);
+            let mut g = Group::default();
+            try_join(&mut g, &v, gid);
+            for v2 in candidates {
+                if g.user_len == SIZE {
+                    break;
+                }
+                try_join(&mut g, &v2, gid);
+            }
+            if g.user_len == SIZE {
+                res.push(g.rid);
+            } else {
+                release(&g, rooms);
+            }
+        }
+        res
+    }
+
+    fn group_indexed(index: &QueueIndex) -> Vec<Vec<u64>> {
+        let groups = index.form_groups(
+            &order(&index.rooms),
+            RANGE,
+            2,
+            Group::default,
+            |g, r, n| try_join(g, r, n + 1),
+            |g| g.user_len == SIZE,
+        );
+        groups.into_iter().map(|g| g.rid).collect()
+    }
+
+    #[test]
+    fn test_window() {
+        let mut index = QueueIndex::new(|r| r.avg_ng);
+        for r in new_rooms(200) {
+            index.insert(r);
+        }
+        index.insert(Rc::clone(index.get(&1).unwrap()));
+        assert_eq!(index.len(), 200);
+        let center = index.rating(&index.get(&1).unwrap().borrow());
+        let window = index.window(center, 20);
+        assert!(window.iter().all(|r| i64::abs((r.borrow().avg_ng - center).into()) <= 20));
+        let expect = index
+            .iter()
+            .filter(|x| i64::abs((x.1.borrow().avg_ng - center).into()) <= 20)
+            .count();
+        assert_eq!(window.len(), expect);
+        index.remove(&1);
+        assert!(index.window(center, 20).iter().all(|r| r.borrow().rid != 1));
+        assert_eq!(index.len(), 199);
+    }
+
+    #[test]
+    fn test_grouping_matches() {
+        let rooms = new_rooms(500);
+        let mut index = QueueIndex::new(|r| r.avg_ng);
+        let mut linear = BTreeMap::new();
+        for r in &rooms {
+            if r.borrow().rid % 7 == 0 {
+                r.borrow_mut().priority = true;
+            }
+            linear.insert(r.borrow().rid, Rc::new(RefCell::new(r.borrow().clone())));
+            index.insert(Rc::clone(r));
+        }
+        let groups = group_indexed(&index);
+        assert!(groups.len() > 0);
+        assert_eq!(groups, group_linear(&linear));
+        for (rid, r) in &index.rooms {
+            assert_eq!(r.borrow().ready, linear[rid].borrow().ready);
+            assert_eq!(r.borrow().gid, linear[rid].borrow().gid);
+        }
+    }
+
+    // cargo test --release -- --ignored --nocapture bench_10k_rooms
+    #[test]
+    #[ignore]
+    fn bench_10k_rooms() {
+        let rooms = new_rooms(10000);
+        let mut old = BTreeMap::new();
+        let mut index = QueueIndex::new(|r| r.avg_ng);
+        for r in &rooms {
+            old.insert(r.borrow().rid, Rc::new(RefCell::new(r.borrow().clone())));
+            index.insert(Rc::clone(r));
+        }
+        let now = Instant::now();
+        let old_groups = group_quadratic(&old);
+        let old_time = now.elapsed();
+        let now = Instant::now();
+        let new_groups = group_indexed(&index).len();
+        let new_time = now.elapsed();
+        println!("quadratic: {} groups in {:?}", old_groups, old_time);
+        println!("indexed: {} groups in {:?}", new_groups, new_time);
+        assert!(old_groups > 0 && new_groups > 0);
+    }
+}
@@ -15,10 +15,13 @@ pub struct User {
     pub ng: i16,
     pub rk: i16,
     pub at: i16,
+    pub rk_solo: i16,
+    pub at_solo: i16,
     pub aram: i16,
     pub rid: u64,
     pub gid: u64,
     pub game_id: u64,
+    pub lid: u64,
     pub online: bool,
     pub start_prestart: bool,
     pub start_get: bool,
@@ -28,6 +31,9 @@ pub struct User {
     pub isLoading: bool,
     pub email: String,
     pub phone: String,
+    pub hero_usage: Vec<String>,
+    pub reroll: i16,
+    pub owned_heros: Vec<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -43,6 +49,7 @@ pub struct RoomData {
     pub ready: i8,
     pub queue_cnt: i64,
     pub mode: String,
+    pub queue_modes: Vec<String>,
 }
 
 impl RoomData {
@@ -72,15 +79,19 @@ impl RoomData {
         let mut highest_rk = 0;
         let mut highest_at = 0;
         let mut highest_aram = 0;
+        // a room in a solo queue is matched on the solo ratings
+        let solo = self.mode.ends_with("_solo");
         for user in &self.users {
+            let rk = if solo { user.borrow().rk_solo } else { user.borrow().rk };
+            let at = if solo { user.borrow().at_solo } else { user.borrow().at };
             if user.borrow().ng > highest_ng {
                 highest_ng = user.borrow().ng;
             }
-            if user.borrow().rk > highest_rk {
-                highest_rk = user.borrow().rk;
+            if rk > highest_rk {
+                highest_rk = rk;
             }
-            if user.borrow().rk > highest_rk {
-                highest_rk = user.borrow().rk;
+            if at > highest_at {
+                highest_at = at;
             }
             if user.borrow().aram > highest_aram {
                 highest_aram = user.borrow().aram;
@@ -209,6 +220,91 @@ impl RoomData {
     }
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct LobbyData {
+    pub lid: u64,
+    pub host: String,
+    pub mode: String,
+    pub password: String,
+    pub players: Vec<Rc<RefCell<User>>>,
+    pub spectators: Vec<Rc<RefCell<User>>>,
+    pub team1: Vec<String>,
+    pub team2: Vec<String>,
+    pub game_id: u64,
+}
+
+impl LobbyData {
+    pub fn add_player(&mut self, user: Rc<RefCell<User>>) {
+        if !self.players.iter().any(|u| u.borrow().id == user.borrow().id) {
+            user.borrow_mut().lid = self.lid;
+            self.players.push(Rc::clone(&user));
+        }
+    }
+
+    pub fn add_spectator(&mut self, user: Rc<RefCell<User>>) {
+        if !self.spectators.iter().any(|u| u.borrow().id == user.borrow().id) {
+            user.borrow_mut().lid = self.lid;
+            self.spectators.push(Rc::clone(&user));
+        }
+    }
+
+    pub fn rm_user(&mut self, id: &String) {
+        self.players.retain(|u| u.borrow().id != *id);
+        self.spectators.retain(|u| u.borrow().id != *id);
+        self.team1.retain(|u| u != id);
+        self.team2.retain(|u| u != id);
+        if self.host == *id && self.players.len() > 0 {
+            self.host = self.players[0].borrow().id.clone();
+        }
+    }
+
+    pub fn assign_team(&mut self, id: &String, team: i16, team_size: usize) -> bool {
+        if !self.players.iter().any(|u| u.borrow().id == *id) {
+            return false;
+        }
+        self.team1.retain(|u| u != id);
+        self.team2.retain(|u| u != id);
+        if team == 1 && self.team1.len() < team_size {
+            self.team1.push(id.clone());
+        } else if team == 2 && self.team2.len() < team_size {
+            self.team2.push(id.clone());
+        } else if team != 0 {
+            return false;
+        }
+        true
+    }
+
+    pub fn publish_update(&self, msgtx: &Sender<MqttMsg>) -> Result<(), Error> {
+        #[derive(Serialize, Deserialize)]
+        pub struct lobbyCell {
+            pub lobby: u64,
+            pub host: String,
+            pub mode: String,
+            pub players: Vec<String>,
+            pub spectators: Vec<String>,
+            pub team1: Vec<String>,
+            pub team2: Vec<String>,
+        }
+        let l = lobbyCell {
+            lobby: self.lid,
+            host: self.host.clone(),
+            mode: self.mode.clone(),
+            players: self.players.iter().map(|u| u.borrow().id.clone()).collect(),
+            spectators: self.spectators.iter().map(|u| u.borrow().id.clone()).collect(),
+            team1: self.team1.clone(),
+            team2: self.team2.clone(),
+        };
+        let msg = serde_json::to_string(&l)?;
+        for u in self.players.iter().chain(self.spectators.iter()) {
+            msgtx.try_send(MqttMsg {
+                topic: format!("lobby/{}/res/update", u.borrow().id),
+                msg: msg.clone(),
+            })?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct FightCheck {
     pub id: String,
@@ -464,6 +560,7 @@ impl FightGame {
                 }
             }
         }
+        self.user_count = self.user_names.len() as u16;
     }
 
     pub fn check_start_get(&self) -> bool {
@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+
+// seconds without a heartbeat before a game server is considered dead
+pub const SERVER_TIMEOUT: u64 = 15;
+
+#[derive(Clone, Debug, Default)]
+pub struct GameServer {
+    pub id: String,
+    pub address: String,
+    pub capacity: usize,
+    pub games: Vec<u64>,
+    pub last_seen: u64,
+}
+
+impl GameServer {
+    pub fn is_full(&self) -> bool {
+        self.games.len() >= self.capacity
+    }
+}
+
+// dedicated game servers by id, games wait in pending until one has room
+#[derive(Clone, Debug, Default)]
+pub struct ServerRegistry {
+    pub servers: BTreeMap<String, GameServer>,
+    pub pending: Vec<u64>,
+}
+
+impl ServerRegistry {
+    pub fn register(&mut self, id: &String, address: &String, capacity: usize, now: u64) {
+        let s = self.servers.entry(id.clone()).or_insert(GameServer {
+            id: id.clone(),
+            ..Default::default()
+        });
+        s.address = address.clone();
+        s.capacity = capacity;
+        s.last_seen = now;
+    }
+
+    pub fn heartbeat(&mut self, id: &String, now: u64) -> bool {
+        match self.servers.get_mut(id) {
+            Some(s) => {
+                s.last_seen = now;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn server_of(&self, game: u64) -> Option<&GameServer> {
+        self.servers.values().find(|s| s.games.contains(&game))
+    }
+
+    // the least loaded server with room takes the game, otherwise it waits for one
+    pub fn allocate(&mut self, game: u64) -> Option<GameServer> {
+        if let Some(s) = self.server_of(game) {
+            return Some(s.clone());
+        }
+        let s = self
+            .servers
+            .values_mut()
+            .filter(|s| !s.is_full())
+            .min_by_key(|s| s.games.len());
+        match s {
+            Some(s) => {
+                s.games.push(game);
+                self.pending.retain(|g| *g != game);
+                Some(s.clone())
+            }
+            None => {
+                if !self.pending.contains(&game) {
+                    self.pending.push(game);
+                }
+                None
+            }
+        }
+    }
+
+    pub fn release(&mut self, game: u64) {
+        for s in self.servers.values_mut() {
+            s.games.retain(|g| *g != game);
+        }
+        self.pending.retain(|g| *g != game);
+    }
+
+    pub fn games(&self) -> Vec<u64> {
+        let mut res: Vec<u64> = self.pending.clone();
+        for s in self.servers.values() {
+            res.extend(s.games.iter());
+        }
+        res
+    }
+
+    // drops servers that stopped sending heartbeats, their games are returned to the caller
+    pub fn expire(&mut self, now: u64) -> Vec<GameServer> {
+        let dead: Vec<String> = self
+            .servers
+            .values()
+            .filter(|s| s.last_seen + SERVER_TIMEOUT < now)
+            .map(|s| s.id.clone())
+            .collect();
+        dead.iter()
+            .filter_map(|id| self.servers.remove(id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_least_loaded() {
+        let mut reg = ServerRegistry::default();
+        reg.register(&"a".to_string(), &"10.0.0.1:7777".to_string(), 2, 0);
+        reg.register(&"b".to_string(), &"10.0.0.2:7777".to_string(), 1, 0);
+        assert_eq!(reg.allocate(1).unwrap().id, "a");
+        assert_eq!(reg.allocate(2).unwrap().id, "b");
+        assert_eq!(reg.allocate(1).unwrap().id, "a");
+        assert_eq!(reg.allocate(3).unwrap().id, "a");
+        assert!(reg.allocate(4).is_none());
+        assert_eq!(reg.pending, vec![4]);
+        reg.release(2);
+        assert_eq!(reg.allocate(4).unwrap().id, "b");
+        assert!(reg.pending.is_empty());
+    }
+
+    #[test]
+    fn test_expire_dead_servers() {
+        let mut reg = ServerRegistry::default();
+        reg.register(&"a".to_string(), &"10.0.0.1:7777".to_string(), 4, 0);
+        reg.register(&"b".to_string(), &"10.0.0.2:7777".to_string(), 4, 0);
+        reg.allocate(1);
+        assert!(reg.heartbeat(&"b".to_string(), 10));
+        assert!(!reg.heartbeat(&"c".to_string(), 10));
+        assert!(reg.expire(SERVER_TIMEOUT).is_empty());
+        let dead = reg.expire(SERVER_TIMEOUT + 1);
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].games, vec![1]);
+        assert!(reg.server_of(1).is_none());
+    }
+}
@@ -0,0 +1,176 @@
+use crate::msg::MqttMsg;
+use std::collections::{BTreeMap, VecDeque};
+
+// public draft events, swap offers and member topics never reach spectators
+pub const SPECTATE_EVENTS: [&str; 6] = [
+    "game_status",
+    "ban_hero",
+    "locked_hero",
+    "heros",
+    "reroll",
+    "bench_swap",
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct SpectateGame {
+    pub delay: u64,
+    pub watchers: Vec<String>,
+    // (release time, watchers it was offered to, event, msg)
+    pending: VecDeque<(u64, Vec<String>, String, String)>,
+}
+
+// watchers per game, events wait out the game's delay before they are sent
+#[derive(Clone, Debug, Default)]
+pub struct SpectatorHub {
+    pub games: BTreeMap<u64, SpectateGame>,
+}
+
+impl SpectatorHub {
+    pub fn watch(&mut self, game: u64, id: &String, delay: u64) {
+        self.unwatch(id);
+        let g = self.games.entry(game).or_insert(SpectateGame {
+            delay: delay,
+            ..Default::default()
+        });
+        g.watchers.push(id.clone());
+    }
+
+    pub fn unwatch(&mut self, id: &String) -> Option<u64> {
+        let mut res = None;
+        for (game, g) in self.games.iter_mut() {
+            if g.watchers.contains(id) {
+                g.watchers.retain(|w| w != id);
+                res = Some(*game);
+            }
+        }
+        self.games.retain(|_, g| !g.watchers.is_empty());
+        res
+    }
+
+    pub fn offer(&mut self, msg: &MqttMsg, now: u64) {
+        let parts: Vec<&str> = msg.topic.split('/').collect();
+        if parts.len() != 4 || parts[0] != "game" || parts[2] != "res" {
+            return;
+        }
+        if !SPECTATE_EVENTS.contains(&parts[3]) {
+            return;
+        }
+        if let Ok(game) = parts[1].parse::<u64>() {
+            if let Some(g) = self.games.get(&game) {
+                let watchers = g.watchers.clone();
+                self.push(game, watchers, parts[3], &msg.msg, now);
+            }
+        }
+    }
+
+    // the state a new watcher starts from, held back like the events and sent to that watcher only
+    pub fn snapshot(&mut self, game: u64, id: &String, msg: &str, now: u64) {
+        self.push(game, vec![id.clone()], "snapshot", msg, now);
+    }
+
+    // events only go to the watchers there when they happened, a later watcher's snapshot already has them
+    fn push(&mut self, game: u64, to: Vec<String>, event: &str, msg: &str, now: u64) {
+        if let Some(g) = self.games.get_mut(&game) {
+            g.pending
+                .push_back((now + g.delay, to, event.to_string(), msg.to_string()));
+        }
+    }
+
+    pub fn release(&mut self, now: u64) -> Vec<MqttMsg> {
+        let mut res: Vec<MqttMsg> = vec![];
+        for (game, g) in self.games.iter_mut() {
+            while g.pending.front().map_or(false, |p| p.0 <= now) {
+                if let Some((_, to, event, msg)) = g.pending.pop_front() {
+                    res.extend(spectate_msgs(*game, &still_watching(&to, &g.watchers), &event, &msg));
+                }
+            }
+        }
+        res
+    }
+
+    // a finished game flushes whatever is still delayed, then the watchers are let go
+    pub fn close(&mut self, game: u64) -> Vec<MqttMsg> {
+        let mut res: Vec<MqttMsg> = vec![];
+        if let Some(g) = self.games.remove(&game) {
+            for (_, to, event, msg) in &g.pending {
+                res.extend(spectate_msgs(game, &still_watching(to, &g.watchers), event, msg));
+            }
+            res.extend(spectate_msgs(game, &g.watchers, "closed", "{}"));
+        }
+        res
+    }
+}
+
+fn still_watching(to: &Vec<String>, watchers: &Vec<String>) -> Vec<String> {
+    to.iter().filter(|w| watchers.contains(w)).cloned().collect()
+}
+
+fn spectate_msgs(game: u64, watchers: &Vec<String>, event: &str, msg: &str) -> Vec<MqttMsg> {
+    watchers
+        .iter()
+        .map(|w| MqttMsg {
+            topic: format!("member/{}/res/spectate", w),
+            msg: format!(
+                r#"{{"game":{}, "event":"{}", "data":{}}}"#,
+                game, event, msg
+            ),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_msg(topic: &str) -> MqttMsg {
+        MqttMsg {
+            topic: topic.to_string(),
+            msg: r#"{"status":"pick"}"#.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_filter_and_delay() {
+        let mut hub = SpectatorHub::default();
+        hub.watch(7, &"caster".to_string(), 120);
+        hub.offer(&game_msg("game/7/res/game_status"), 10);
+        hub.offer(&game_msg("game/7/res/swap_hero"), 10);
+        hub.offer(&game_msg("game/8/res/game_status"), 10);
+        hub.offer(&game_msg("member/7/res/ng_choose_hero"), 10);
+        assert_eq!(hub.release(129).len(), 0);
+        let sent = hub.release(130);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].topic, "member/caster/res/spectate");
+        assert_eq!(hub.release(200).len(), 0);
+    }
+
+    #[test]
+    fn test_close_flushes_pending() {
+        let mut hub = SpectatorHub::default();
+        hub.watch(7, &"a".to_string(), 120);
+        hub.watch(7, &"b".to_string(), 120);
+        hub.offer(&game_msg("game/7/res/ban_hero"), 10);
+        assert_eq!(hub.unwatch(&"b".to_string()), Some(7));
+        let sent = hub.close(7);
+        assert_eq!(sent.len(), 2);
+        assert!(sent[1].msg.contains("closed"));
+        assert!(hub.games.is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_only_for_new_watcher() {
+        let mut hub = SpectatorHub::default();
+        hub.watch(7, &"a".to_string(), 120);
+        hub.offer(&game_msg("game/7/res/ban_hero"), 10);
+        hub.watch(7, &"b".to_string(), 120);
+        hub.snapshot(7, &"b".to_string(), "{}", 20);
+        hub.offer(&game_msg("game/7/res/locked_hero"), 30);
+        let topics = |sent: Vec<MqttMsg>| sent.iter().map(|m| m.topic.clone()).collect::<Vec<String>>();
+        // the ban before b joined is already in b's snapshot
+        assert_eq!(topics(hub.release(130)), vec!["member/a/res/spectate"]);
+        let sent = hub.release(140);
+        assert_eq!(topics(sent.clone()), vec!["member/b/res/spectate"]);
+        assert!(sent[0].msg.contains("snapshot"));
+        assert_eq!(topics(hub.release(150)), vec!["member/a/res/spectate", "member/b/res/spectate"]);
+    }
+}
@@ -0,0 +1,347 @@
+use crate::msg::*;
+use crossbeam_channel::Sender;
+use failure::Error;
+use log::{error, info, trace, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TournamentTeam {
+    pub name: String,
+    pub roster: Vec<String>,
+    pub wins: u16,
+    pub losses: u16,
+    pub opponents: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TournamentMatch {
+    pub round: u16,
+    pub team1: usize,
+    pub team2: Option<usize>,
+    pub winner: Option<usize>,
+    pub game_id: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Tournament {
+    pub tid: u64,
+    pub name: String,
+    pub format: String,
+    pub mode: String,
+    pub status: String,
+    pub round: u16,
+    pub swiss_rounds: u16,
+    pub teams: Vec<TournamentTeam>,
+    pub matches: Vec<TournamentMatch>,
+}
+
+impl Tournament {
+    pub fn new(tid: u64, name: String, format: String, mode: String) -> Tournament {
+        Tournament {
+            tid: tid,
+            name: name,
+            format: format,
+            mode: mode,
+            status: "register".to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn has_player(&self, id: &String) -> bool {
+        self.teams.iter().any(|t| t.roster.contains(id))
+    }
+
+    pub fn register(&mut self, name: String, roster: Vec<String>, team_size: usize) -> bool {
+        if self.status != "register" || roster.len() != team_size {
+            return false;
+        }
+        let unique: BTreeSet<&String> = roster.iter().collect();
+        if unique.len() != roster.len() || roster.iter().any(|id| self.has_player(id)) {
+            return false;
+        }
+        if self.teams.iter().any(|t| t.name == name) {
+            return false;
+        }
+        self.teams.push(TournamentTeam {
+            name: name,
+            roster: roster,
+            ..Default::default()
+        });
+        true
+    }
+
+    pub fn start(&mut self) -> bool {
+        if self.status != "register" || self.teams.len() < 2 {
+            return false;
+        }
+        let mut rounds = 0;
+        while (1 << rounds) < self.teams.len() {
+            rounds += 1;
+        }
+        self.swiss_rounds = rounds;
+        self.status = "running".to_string();
+        self.next_round();
+        true
+    }
+
+    fn is_alive(&self, t: usize) -> bool {
+        if self.format == "single" {
+            self.teams[t].losses < 1
+        } else if self.format == "double" {
+            self.teams[t].losses < 2
+        } else {
+            true
+        }
+    }
+
+    // byes count as a win so swiss scores and elimination brackets stay even
+    fn add_round(&mut self, pairs: Vec<(usize, Option<usize>)>) {
+        for (t1, t2) in pairs {
+            let mut m = TournamentMatch {
+                round: self.round,
+                team1: t1,
+                team2: t2,
+                ..Default::default()
+            };
+            if t2.is_none() {
+                m.winner = Some(t1);
+                self.teams[t1].wins += 1;
+            }
+            self.matches.push(m);
+        }
+    }
+
+    fn pair_in_order(ids: &Vec<usize>) -> Vec<(usize, Option<usize>)> {
+        let mut res = vec![];
+        let mut i = 0;
+        while i < ids.len() {
+            if i + 1 < ids.len() {
+                res.push((ids[i], Some(ids[i + 1])));
+            } else {
+                res.push((ids[i], None));
+            }
+            i += 2;
+        }
+        res
+    }
+
+    fn pair_swiss(&self) -> Vec<(usize, Option<usize>)> {
+        let mut order: Vec<usize> = (0..self.teams.len()).collect();
+        order.sort_by_key(|t| std::cmp::Reverse(self.teams[*t].wins));
+        let mut res = vec![];
+        let mut used = vec![false; self.teams.len()];
+        for (i, t) in order.iter().enumerate() {
+            if used[*t] {
+                continue;
+            }
+            used[*t] = true;
+            let rest: Vec<usize> = order[i + 1..].iter().cloned().filter(|x| !used[*x]).collect();
+            let opponent = rest
+                .iter()
+                .find(|x| !self.teams[*t].opponents.contains(*x))
+                .or(rest.first())
+                .cloned();
+            if let Some(o) = opponent {
+                used[o] = true;
+            }
+            res.push((*t, opponent));
+        }
+        res
+    }
+
+    fn next_round(&mut self) {
+        let alive: Vec<usize> = (0..self.teams.len()).filter(|t| self.is_alive(*t)).collect();
+        if alive.len() <= 1 || (self.format == "swiss" && self.round >= self.swiss_rounds) {
+            self.status = "finished".to_string();
+            return;
+        }
+        self.round += 1;
+        if self.format == "swiss" {
+            let pairs = self.pair_swiss();
+            self.add_round(pairs);
+        } else if self.format == "double" {
+            // upper bracket holds unbeaten teams, lower bracket teams with one loss
+            let upper: Vec<usize> = alive.iter().cloned().filter(|t| self.teams[*t].losses == 0).collect();
+            let lower: Vec<usize> = alive.iter().cloned().filter(|t| self.teams[*t].losses == 1).collect();
+            if upper.len() == 1 && lower.len() == 1 {
+                self.add_round(vec![(upper[0], Some(lower[0]))]);
+            } else {
+                let mut pairs = vec![];
+                if upper.len() > 1 {
+                    pairs.extend(Tournament::pair_in_order(&upper));
+                }
+                if lower.len() > 1 {
+                    pairs.extend(Tournament::pair_in_order(&lower));
+                }
+                self.add_round(pairs);
+            }
+        } else {
+            self.add_round(Tournament::pair_in_order(&alive));
+        }
+    }
+
+    pub fn pending_matches(&self) -> Vec<usize> {
+        let mut res = vec![];
+        for (i, m) in self.matches.iter().enumerate() {
+            if m.winner.is_none() && m.team2.is_some() && m.game_id == 0 {
+                res.push(i);
+            }
+        }
+        res
+    }
+
+    pub fn match_rosters(&self, index: usize) -> Vec<Vec<String>> {
+        let m = &self.matches[index];
+        let mut res = vec![self.teams[m.team1].roster.clone()];
+        if let Some(t2) = m.team2 {
+            res.push(self.teams[t2].roster.clone());
+        }
+        res
+    }
+
+    pub fn report(&mut self, game_id: u64, win: &Vec<String>) -> bool {
+        if win.len() == 0 {
+            return false;
+        }
+        let pos = self.matches.iter().position(|m| m.game_id == game_id && m.winner.is_none());
+        let index = match pos {
+            Some(i) => i,
+            None => return false,
+        };
+        let t1 = self.matches[index].team1;
+        let t2 = match self.matches[index].team2 {
+            Some(t) => t,
+            None => return false,
+        };
+        let (w, l) = if win.iter().any(|id| self.teams[t1].roster.contains(id)) {
+            (t1, t2)
+        } else {
+            (t2, t1)
+        };
+        self.matches[index].winner = Some(w);
+        self.teams[w].wins += 1;
+        self.teams[l].losses += 1;
+        self.teams[w].opponents.push(l);
+        self.teams[l].opponents.push(w);
+        let round = self.round;
+        if self.matches.iter().all(|m| m.round != round || m.winner.is_some()) {
+            self.next_round();
+        }
+        true
+    }
+
+    // a player leaving a bracket game loses it for their team
+    pub fn forfeit(&mut self, game_id: u64, id: &String) -> bool {
+        let m = match self.matches.iter().find(|m| m.game_id == game_id && m.winner.is_none()) {
+            Some(m) => m.clone(),
+            None => return false,
+        };
+        let t2 = match m.team2 {
+            Some(t) => t,
+            None => return false,
+        };
+        let w = if self.teams[m.team1].roster.contains(id) {
+            t2
+        } else if self.teams[t2].roster.contains(id) {
+            m.team1
+        } else {
+            return false;
+        };
+        let win = self.teams[w].roster.clone();
+        self.report(game_id, &win)
+    }
+
+    pub fn publish_standings(&self, msgtx: &Sender<MqttMsg>, topic: String) -> Result<(), Error> {
+        #[derive(Serialize, Deserialize)]
+        pub struct standingCell {
+            pub tournament: u64,
+            pub name: String,
+            pub format: String,
+            pub status: String,
+            pub round: u16,
+            pub ranking: Vec<String>,
+            pub teams: Vec<TournamentTeam>,
+            pub matches: Vec<TournamentMatch>,
+        }
+        let mut ranking: Vec<&TournamentTeam> = self.teams.iter().collect();
+        ranking.sort_by(|a, b| b.wins.cmp(&a.wins).then(a.losses.cmp(&b.losses)));
+        let s = standingCell {
+            tournament: self.tid,
+            name: self.name.clone(),
+            format: self.format.clone(),
+            status: self.status.clone(),
+            round: self.round,
+            ranking: ranking.iter().map(|t| t.name.clone()).collect(),
+            teams: self.teams.clone(),
+            matches: self.matches.clone(),
+        };
+        msgtx.try_send(MqttMsg {
+            topic: topic,
+            msg: serde_json::to_string(&s)?,
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play_all(t: &mut Tournament, upset: bool) {
+        let mut game_id = 0;
+        while t.status == "running" {
+            let pending = t.pending_matches();
+            assert!(pending.len() > 0);
+            for index in pending {
+                game_id += 1;
+                t.matches[index].game_id = game_id;
+                let rosters = t.match_rosters(index);
+                let win = if upset { rosters[1].clone() } else { rosters[0].clone() };
+                assert!(t.report(game_id, &win));
+            }
+        }
+    }
+
+    #[test]
+    fn test_brackets() {
+        for format in vec!["single", "double", "swiss"] {
+            for upset in vec![false, true] {
+                let mut t = Tournament::new(1, "cup".to_string(), format.to_string(), "ng".to_string());
+                for i in 0..5 {
+                    let roster: Vec<String> = (0..5).map(|j| format!("p{}_{}", i, j)).collect();
+                    assert!(t.register(format!("team{}", i), roster, 5));
+                }
+                assert!(!t.register("dup".to_string(), (0..5).map(|j| format!("p0_{}", j)).collect(), 5));
+                assert!(t.start());
+                play_all(&mut t, upset);
+                assert_eq!(t.status, "finished");
+                if format == "swiss" {
+                    assert_eq!(t.round, t.swiss_rounds);
+                } else {
+                    let max_losses = if format == "single" { 1 } else { 2 };
+                    let alive = t.teams.iter().filter(|x| x.losses < max_losses).count();
+                    assert_eq!(alive, 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_forfeit() {
+        let mut t = Tournament::new(1, "cup".to_string(), "single".to_string(), "ng".to_string());
+        for i in 0..2 {
+            let roster: Vec<String> = (0..5).map(|j| format!("p{}_{}", i, j)).collect();
+            assert!(t.register(format!("team{}", i), roster, 5));
+        }
+        assert!(t.start());
+        let index = t.pending_matches()[0];
+        t.matches[index].game_id = 7;
+        assert!(!t.forfeit(7, &"nobody".to_string()));
+        let leaver = t.match_rosters(index)[0][2].clone();
+        assert!(t.forfeit(7, &leaver));
+        assert_eq!(t.matches[index].winner, t.matches[index].team2);
+        assert_eq!(t.status, "finished");
+        assert!(!t.forfeit(7, &leaver));
+    }
+}